@@ -0,0 +1,57 @@
+//! Compiles the shaders in `src/graphics/shaders` and `assets/shaders` to
+//! `$OUT_DIR/shaders/<name>.spv`, with `.wgsl` dropped from the name, e.g.
+//! `triangle.vert.spv`. The library render pass is multiview, so `MULTIVIEW`
+//! is defined.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use xrrs_shader::{shader_kind, ShaderCompiler};
+
+const SHADER_DIRS: &[&str] = &["src/graphics/shaders", "assets/shaders"];
+
+fn main() {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("shaders");
+    fs::create_dir_all(&out_dir).unwrap();
+    let compiler = ShaderCompiler::new().define("MULTIVIEW", "1");
+
+    let mut failed = false;
+    for dir in SHADER_DIRS {
+        println!("cargo:rerun-if-changed={}", dir);
+        let mut paths = fs::read_dir(dir)
+            .unwrap_or_else(|e| panic!("reading {}: {}", dir, e))
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| shader_kind(path).is_some())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            match compiler.compile_file(&path) {
+                Ok(shader) => {
+                    for source in &shader.sources {
+                        println!("cargo:rerun-if-changed={}", source.display());
+                    }
+                    fs::write(out_dir.join(spv_name(&path)), shader.to_bytes()).unwrap();
+                }
+                Err(e) => {
+                    // Each diagnostic on its own line keeps file:line clickable
+                    println!("cargo:rerun-if-changed={}", path.display());
+                    eprintln!("{}", e);
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn spv_name(path: &Path) -> String {
+    let name = path.file_name().unwrap().to_str().unwrap();
+    format!("{}.spv", name.strip_suffix(".wgsl").unwrap_or(name))
+}
@@ -2,6 +2,12 @@ use xrrs::{ app::App };
 
 #[cfg_attr(target_os = "android", ndk_glue::main)]
 fn main() {
-    App::new()
-    .run();
+    let app = App::builder()
+        .application_name("demo")
+        .engine_name("demo engine")
+        .build();
+
+    if let Err(e) = app.and_then(|mut app| app.run()) {
+        eprintln!("{}", e);
+    }
 }
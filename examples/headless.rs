@@ -0,0 +1,31 @@
+use ash::{vk::{self}};
+
+use xrrs::{
+    graphics::{
+        capture::CaptureView,
+        vk_base::VkBase,
+        vk_renderer::VkRenderer
+    },
+    Renderer,
+    xr::{frame::FrameContext, swapchain::Swapchain}
+};
+
+const FRAME_COUNT: u32 = 3;
+
+fn main() -> xrrs::Result<()> {
+    let vk_base = VkBase::headless()?;
+
+    let mut swapchain = Swapchain::offscreen(&vk_base, vk::Extent2D { width: 512, height: 512 }, 2)?;
+
+    let mut renderer = VkRenderer::new(vk_base.clone(), &swapchain)?;
+
+    for _ in 0..FRAME_COUNT {
+        renderer.draw(&mut swapchain, &FrameContext::default())?;
+    }
+
+    swapchain.capture(&vk_base, CaptureView::SideBySide)?.save_png("headless.png")?;
+
+    println!("Rendered {} frames to headless.png", FRAME_COUNT);
+
+    Ok(())
+}
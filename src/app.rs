@@ -1,27 +1,41 @@
+use ash::{vk::{self}};
 use openxr as xr;
 use std::{
+    path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
-    }
+        Arc, Once,
+    },
+    thread,
+    time::Duration,
 };
 
 use crate::{
+    error::{Context, Result},
+    math::DepthRange,
     graphics::{
+        capture::CaptureTrigger,
+        pipeline_cache,
         vk_base::VkBase,
         vk_renderer::VkRenderer
     },
     Renderer,
     xr::{
-        xr_base::XRBase,
+        action::{Action, ActionManifest, LEFT_HAND, RIGHT_HAND},
+        frame::FrameContext,
+        xr_base::{ExtensionFlag, XRBase, XRConfig},
         xr_renderer::XRRenderer,
     }
 };
 
-const VIEW_TYPE: xr::ViewConfigurationType = xr::ViewConfigurationType::PRIMARY_STEREO;
+// The Ctrl-C handler can only be installed once per process, so it lives
+// outside of App to allow an App to be recreated after a failure
+static RUNNING: AtomicBool = AtomicBool::new(true);
+static CTRLC_HANDLER: Once = Once::new();
 
 #[allow(dead_code)]
 pub struct App {
+    capture_trigger: CaptureTrigger,
     // Maintain order for proper resource destruction
     vk_renderer: VkRenderer,
     xr_renderer: XRRenderer,
@@ -29,49 +43,221 @@ pub struct App {
     xr_base: Arc<XRBase>,
 }
 
-impl App {
+/// Events reported to the callback of `App::run_with`
+#[derive(Clone, Debug, PartialEq)]
+pub enum AppEvent {
+    /// The runtime picked a new interaction profile for either hand, e.g. to
+    /// show matching controller models and button hints
+    InteractionProfileChanged {
+        left: Option<String>,
+        right: Option<String>,
+    },
+}
+
+#[derive(Default)]
+pub struct AppBuilder {
+    config: XRConfig,
+    /// Handed to `VkBase`, which every renderer reads it from
+    depth: DepthRange,
+}
+
+impl AppBuilder {
     pub fn new() -> Self {
-        let xr_base = XRBase::new();
-        let vk_base = VkBase::new(&xr_base.xr_instance, xr_base.system_id);
+        Self::default()
+    }
 
-        let xr_renderer = XRRenderer::new(xr_base.clone(), &vk_base);
-        let vk_renderer = VkRenderer::new(vk_base.clone(), &xr_renderer.swapchain);
+    pub fn application_name(mut self, name: &str) -> Self {
+        self.config.application_name = name.to_owned();
+        self
+    }
+
+    pub fn application_version(mut self, version: u32) -> Self {
+        self.config.application_version = version;
+        self
+    }
+
+    pub fn engine_name(mut self, name: &str) -> Self {
+        self.config.engine_name = name.to_owned();
+        self
+    }
+
+    pub fn engine_version(mut self, version: u32) -> Self {
+        self.config.engine_version = version;
+        self
+    }
 
-        App {
+    /// Enables an extension the app cannot run without, e.g. `|e| &mut e.khr_visibility_mask`
+    pub fn extension(mut self, flag: ExtensionFlag) -> Self {
+        *flag(&mut self.config.required_extensions) = true;
+        self
+    }
+
+    /// Enables an extension only if the runtime advertises it.
+    /// Check `XRBase::enabled_extensions` to see whether it was enabled.
+    pub fn optional_extension(mut self, flag: ExtensionFlag) -> Self {
+        self.config.optional_extensions.push(flag);
+        self
+    }
+
+    pub fn form_factor(mut self, form_factor: xr::FormFactor) -> Self {
+        self.config.form_factor = form_factor;
+        self
+    }
+
+    pub fn view_type(mut self, view_type: xr::ViewConfigurationType) -> Self {
+        self.config.view_type = view_type;
+        self
+    }
+
+    pub fn blend_mode(mut self, blend_mode: xr::EnvironmentBlendMode) -> Self {
+        self.config.blend_mode = Some(blend_mode);
+        self
+    }
+
+    /// Replaces the default hand pose actions
+    pub fn actions(mut self, manifest: ActionManifest) -> Self {
+        self.config.actions = manifest;
+        self
+    }
+
+    /// Near and far planes in meters, `far` may be `f32::INFINITY`
+    pub fn depth_range(mut self, near: f32, far: f32) -> Self {
+        self.depth.near = near;
+        self.depth.far = far;
+        self
+    }
+
+    pub fn reverse_z(mut self, reverse_z: bool) -> Self {
+        self.depth.reverse_z = reverse_z;
+        self
+    }
+
+    /// Depth buffer formats to pick from, in order of preference
+    pub fn depth_formats(mut self, formats: &[vk::Format]) -> Self {
+        self.config.depth_formats = formats.to_vec();
+        self
+    }
+
+    /// Keeps the Vulkan pipeline cache in `path` instead of the platform data
+    /// directory. Needed on Android, e.g. with the activity's internal data path.
+    pub fn pipeline_cache_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.pipeline_cache_path = Some(path.into());
+        self
+    }
+
+    /// Compiles the renderer's shaders from their sources in `dir` and
+    /// reloads them when they change, see `VkRenderer::watch_shaders`
+    pub fn watch_shaders(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.shader_dir = Some(dir.into());
+        self
+    }
+
+    /// Uses an already loaded OpenXR entry point instead of the system loader.
+    /// The pipeline cache then stays in memory unless `pipeline_cache_path`
+    /// is set.
+    pub fn entry(mut self, entry: xr::Entry) -> Self {
+        self.config.entry = Some(entry);
+        self
+    }
+
+    pub fn build(self) -> Result<App> {
+        let xr_base = XRBase::new(self.config)?;
+        // A custom entry is usually a test or mock runtime, which shouldn't
+        // write into the user's data directory
+        let pipeline_cache_path = match (&xr_base.config.pipeline_cache_path, &xr_base.config.entry) {
+            (Some(path), _) => Some(path.clone()),
+            (None, Some(_)) => None,
+            (None, None) => pipeline_cache::default_path(&xr_base.config.application_name),
+        };
+        let vk_base = VkBase::new(&xr_base.xr_instance,
+                                  xr_base.system_id,
+                                  self.depth,
+                                  &xr_base.config.depth_formats,
+                                  pipeline_cache_path
+        )?;
+
+        let xr_renderer = XRRenderer::new(xr_base.clone(), &vk_base)?;
+        let mut vk_renderer = VkRenderer::new(vk_base.clone(), &xr_renderer.swapchain)?;
+        if let Some(dir) = &xr_base.config.shader_dir {
+            vk_renderer.watch_shaders(dir)?;
+        }
+
+        Ok(App {
+            capture_trigger: CaptureTrigger::default(),
             vk_renderer,
             xr_renderer,
             vk_base,
             xr_base,
-        }
+        })
+    }
+}
+
+impl App {
+    pub fn new() -> Result<Self> {
+        AppBuilder::new().build()
+    }
+
+    pub fn builder() -> AppBuilder {
+        AppBuilder::new()
+    }
+
+    pub fn actions(&self) -> &Action {
+        &self.xr_renderer.actions
+    }
+
+    /// The views and poses handed to the renderer for the most recently
+    /// rendered frame
+    pub fn last_frame(&self) -> Option<&FrameContext> {
+        self.xr_renderer.last_frame.as_ref()
+    }
+
+    /// Returns a handle that can request a screenshot of the next frame from
+    /// any thread while the app is running
+    pub fn capture_trigger(&self) -> CaptureTrigger {
+        self.capture_trigger.clone()
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        self.run_with(|_| {})
     }
 
-    pub fn run(&mut self) {
-        let running = Arc::new(AtomicBool::new(true));
-        let r = running.clone();
-        ctrlc::set_handler(move || {
-            r.store(false, Ordering::Relaxed);
-        }).expect("Error setting Ctrl-C handler");
+    pub fn run_with(&mut self, mut on_event: impl FnMut(&AppEvent)) -> Result<()> {
+        let mut handler_result = Ok(());
+        CTRLC_HANDLER.call_once(|| {
+            handler_result = ctrlc::set_handler(|| {
+                RUNNING.store(false, Ordering::Relaxed);
+            });
+        });
+        handler_result?;
+        RUNNING.store(true, Ordering::Relaxed);
+
+        let mut session_running = false;
 
         'main: loop {
-            if !running.load(Ordering::Relaxed) {
+            if !RUNNING.load(Ordering::Relaxed) {
                 match self.xr_renderer.session.request_exit() {
                     Ok(()) => {}
                     Err(xr::sys::Result::ERROR_SESSION_NOT_RUNNING) => break 'main,
-                    Err(e) => panic!("{}", e),
+                    Err(e) => return Err(e).context("requesting session exit"),
                 }
             }
 
-            while let Some(event) = &self.xr_base.xr_instance.poll_event(&mut self.xr_renderer.event_storage).unwrap() {
+            while let Some(event) = &self.xr_base.xr_instance
+                .poll_event(&mut self.xr_renderer.event_storage)
+                .context("polling events")?
+            {
                 use xr::Event::*;
                 match event {
                     SessionStateChanged(e) => {
                         println!("OpenXR session state change: {:?}", e.state());
                         match e.state() {
                             xr::SessionState::READY => {
-                                self.xr_renderer.session.begin(VIEW_TYPE).unwrap();
+                                self.xr_renderer.session.begin(self.xr_base.config.view_type).context("beginning session")?;
+                                session_running = true;
                             }
                             xr::SessionState::STOPPING => {
-                                self.xr_renderer.session.end().unwrap();
+                                self.xr_renderer.session.end().context("ending session")?;
+                                session_running = false;
                             }
                             xr::SessionState::EXITING | xr::SessionState::LOSS_PENDING => {
                                 break 'main;
@@ -79,6 +265,14 @@ impl App {
                             _ => {}
                         }
                     }
+                    InteractionProfileChanged(_) => {
+                        let event = AppEvent::InteractionProfileChanged {
+                            left: self.xr_renderer.actions.current_interaction_profile(LEFT_HAND)?,
+                            right: self.xr_renderer.actions.current_interaction_profile(RIGHT_HAND)?,
+                        };
+                        println!("OpenXR interaction profile change: {:?}", event);
+                        on_event(&event);
+                    }
                     InstanceLossPending(_) => {
                         break 'main;
                     }
@@ -86,9 +280,21 @@ impl App {
                 }
             }
 
-            self.xr_renderer.update_frame(&mut self.vk_renderer);
+            if !session_running {
+                // Frames can only be waited on while the session is running
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+
+            if let Some(request) = self.capture_trigger.take() {
+                self.vk_renderer.capture_next_frame(request);
+            }
+
+            self.xr_renderer.update_frame(&mut self.vk_renderer)?;
         }
 
         println!("Clean exit");
+
+        Ok(())
     }
 }
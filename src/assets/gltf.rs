@@ -0,0 +1,357 @@
+//! Loads glTF 2.0 `.gltf` and `.glb` files into `ModelData`.
+//!
+//! Only the core specification is read. Extensions, sparse accessors and
+//! primitives that aren't triangle lists end up in `ModelData::warnings`
+//! instead of failing the load; the rest of the file is still loaded.
+
+use ash::{vk::{self}};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use gltf::{
+    image::Format,
+    json::{self, validation::Validate},
+    mesh::Mode,
+    texture::{MagFilter, MinFilter, WrappingMode},
+};
+use std::{
+    collections::{HashSet},
+    fs,
+    path::{Path},
+};
+
+use crate::{
+    assets::model::{
+        flat_normals, index_out_of_range, AlphaMode, AssetWarning, Camera, ImageData, Material, MeshData, ModelData,
+        Node, Projection, Texture, TextureRef, Vertex
+    },
+    error::{Context, Error, Result},
+    graphics::sampler::{SamplerDesc}
+};
+
+impl ModelData {
+    pub fn from_gltf_file(path: impl AsRef<Path>) -> Result<ModelData> {
+        let path = path.as_ref();
+        let data = fs::read(path).context("reading glTF file")?;
+
+        ModelData::from_gltf_slice(&data, path.parent().unwrap_or_else(|| Path::new(".")))
+    }
+
+    /// Loads a `.gltf` or `.glb` file's contents. External buffers and
+    /// images are looked up relative to `base`.
+    pub fn from_gltf_slice(data: &[u8], base: &Path) -> Result<ModelData> {
+        let (document, blob) = parse(data).map_err(Error::Gltf)?;
+        let buffers = gltf::import_buffers(&document, Some(base), blob).map_err(Error::Gltf)?;
+        let images = gltf::import_images(&document, Some(base), &buffers).map_err(Error::Gltf)?;
+
+        let mut warnings = Vec::new();
+        for name in document.extensions_used() {
+            let required = document.extensions_required().any(|required| required == name);
+            warnings.push(AssetWarning::Extension { name: name.to_owned(), required });
+        }
+        for name in document.extensions_required() {
+            if !document.extensions_used().any(|used| used == name) {
+                warnings.push(AssetWarning::Extension { name: name.to_owned(), required: true });
+            }
+        }
+
+        let meshes = document
+            .meshes()
+            .map(|mesh| load_mesh(&mesh, &buffers, &mut warnings))
+            .collect();
+
+        // Color textures are stored as sRGB, everything else is linear data
+        let mut color_images = HashSet::new();
+        for material in document.materials() {
+            let color_textures = [material.pbr_metallic_roughness().base_color_texture(), material.emissive_texture()];
+            for info in color_textures.into_iter().flatten() {
+                color_images.insert(info.texture().source().index());
+            }
+        }
+        let images = document
+            .images()
+            .zip(images)
+            .map(|(image, data)| ImageData {
+                name: image.name().map(str::to_owned),
+                width: data.width,
+                height: data.height,
+                pixels: to_rgba8(&data),
+                srgb: color_images.contains(&image.index()),
+            })
+            .collect();
+
+        let textures = document
+            .textures()
+            .map(|texture| Texture { image: texture.source().index(), sampler: sampler_desc(&texture.sampler()) })
+            .collect();
+
+        let materials = document
+            .materials()
+            .enumerate()
+            .map(|(index, material)| load_material(index, &material, &mut warnings))
+            .collect();
+
+        let cameras = document
+            .cameras()
+            .map(|camera| Camera {
+                name: camera.name().map(str::to_owned),
+                projection: match camera.projection() {
+                    gltf::camera::Projection::Perspective(perspective) => Projection::Perspective {
+                        yfov: perspective.yfov(),
+                        aspect_ratio: perspective.aspect_ratio(),
+                        znear: perspective.znear(),
+                        zfar: perspective.zfar(),
+                    },
+                    gltf::camera::Projection::Orthographic(orthographic) => Projection::Orthographic {
+                        xmag: orthographic.xmag(),
+                        ymag: orthographic.ymag(),
+                        znear: orthographic.znear(),
+                        zfar: orthographic.zfar(),
+                    },
+                },
+            })
+            .collect();
+
+        let mut nodes = document
+            .nodes()
+            .map(|node| Node {
+                name: node.name().map(str::to_owned),
+                transform: Mat4::from_cols_array_2d(&node.transform().matrix()),
+                parent: None,
+                children: node.children().map(|child| child.index()).collect(),
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                camera: node.camera().map(|camera| camera.index()),
+            })
+            .collect::<Vec<_>>();
+        // Nodes form disjoint trees, anything else would make walking up or
+        // down the hierarchy loop forever
+        for index in 0..nodes.len() {
+            for child in nodes[index].children.clone() {
+                let mut ancestor = Some(index);
+                while let Some(node) = ancestor {
+                    if node == child {
+                        return Err(invalid_node_graph(index));
+                    }
+                    ancestor = nodes[node].parent;
+                }
+                if nodes[child].parent.is_some() {
+                    return Err(invalid_node_graph(index));
+                }
+                nodes[child].parent = Some(index);
+            }
+        }
+
+        // Without any scene every parentless node is a root
+        let roots = match document.default_scene().or_else(|| document.scenes().next()) {
+            Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+            None => (0..nodes.len()).filter(|&index| nodes[index].parent.is_none()).collect(),
+        };
+
+        Ok(ModelData { meshes, images, textures, materials, cameras, nodes, roots, warnings })
+    }
+}
+
+/// A node whose children would give a node two parents or close a cycle
+fn invalid_node_graph(node: usize) -> Error {
+    let path = json::Path::new().field("nodes").index(node).field("children");
+    Error::Gltf(gltf::Error::Validation(vec![(path, json::validation::Error::Invalid)]))
+}
+
+/// Like `gltf::Gltf::from_slice`, but unsupported required extensions don't
+/// fail validation
+fn parse(data: &[u8]) -> gltf::Result<(gltf::Document, Option<Vec<u8>>)> {
+    let (root, blob): (json::Root, Option<Vec<u8>>) = if data.starts_with(b"glTF") {
+        let glb = gltf::Glb::from_slice(data)?;
+        (json::deserialize::from_slice(&glb.json)?, glb.bin.map(|bin| bin.into_owned()))
+    } else {
+        (json::deserialize::from_slice(data)?, None)
+    };
+
+    let mut errors = Vec::new();
+    root.validate(&root, json::Path::new, &mut |path: &dyn Fn() -> json::Path, error| {
+        let path = path();
+        if !path.as_str().starts_with("extensionsRequired") {
+            errors.push((path, error));
+        }
+    });
+    if !errors.is_empty() {
+        return Err(gltf::Error::Validation(errors));
+    }
+
+    Ok((gltf::Document::from_json_without_validation(root), blob))
+}
+
+fn load_mesh(mesh: &gltf::Mesh, buffers: &[gltf::buffer::Data], warnings: &mut Vec<AssetWarning>) -> MeshData {
+    let mut data = MeshData { name: mesh.name().map(str::to_owned), ..Default::default() };
+
+    for primitive in mesh.primitives() {
+        let (mesh, index) = (mesh.index(), primitive.index());
+        if primitive.mode() != Mode::Triangles {
+            let mode = format!("{:?}", primitive.mode());
+            warnings.push(AssetWarning::PrimitiveMode { mesh, primitive: index, mode });
+            continue;
+        }
+        let sparse = primitive
+            .attributes()
+            .map(|(_, accessor)| accessor)
+            .chain(primitive.indices())
+            .find(|accessor| accessor.sparse().is_some());
+        if let Some(accessor) = sparse {
+            warnings.push(AssetWarning::SparseAccessor { mesh, primitive: index, accessor: accessor.index() });
+            continue;
+        }
+
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()].0[..]));
+        let mut vertices = match reader.read_positions() {
+            Some(positions) => positions
+                .map(|position| Vertex { position: Vec3::from(position), ..Default::default() })
+                .collect::<Vec<_>>(),
+            None => {
+                warnings.push(AssetWarning::MissingPositions { mesh, primitive: index });
+                continue;
+            }
+        };
+        if let Some(tex_coords) = reader.read_tex_coords(0) {
+            for (vertex, tex_coord) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+                vertex.tex_coord = Vec2::from(tex_coord);
+            }
+        }
+        if let Some(colors) = reader.read_colors(0) {
+            for (vertex, color) in vertices.iter_mut().zip(colors.into_rgba_f32()) {
+                vertex.color = Vec4::from(color);
+            }
+        }
+        let indices = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+            None => (0..vertices.len() as u32).collect(),
+        };
+        if let Some(bad) = index_out_of_range(&indices, vertices.len()) {
+            let vertex_count = vertices.len();
+            warnings.push(AssetWarning::IndexOutOfRange { mesh, primitive: index, index: bad, vertex_count });
+            continue;
+        }
+
+        let material = primitive.material().index();
+        match reader.read_normals() {
+            Some(normals) => {
+                for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                    vertex.normal = Vec3::from(normal);
+                }
+                data.push_primitive(&vertices, &indices, material);
+            }
+            // The spec asks for flat shading when normals are missing
+            None => {
+                let (vertices, indices) = flat_normals(&vertices, &indices);
+                data.push_primitive(&vertices, &indices, material);
+            }
+        }
+    }
+
+    data
+}
+
+fn load_material(index: usize, material: &gltf::Material, warnings: &mut Vec<AssetWarning>) -> Material {
+    let mut texture_ref = |texture: gltf::Texture, tex_coord: u32| {
+        if tex_coord != 0 {
+            warnings.push(AssetWarning::TexCoordSet { material: index, set: tex_coord });
+        }
+        TextureRef { texture: texture.index(), tex_coord }
+    };
+
+    let pbr = material.pbr_metallic_roughness();
+    let base_color_texture = pbr.base_color_texture().map(|info| texture_ref(info.texture(), info.tex_coord()));
+    let metallic_roughness_texture = pbr
+        .metallic_roughness_texture()
+        .map(|info| texture_ref(info.texture(), info.tex_coord()));
+    let normal = material.normal_texture();
+    let normal_texture = normal.as_ref().map(|normal| texture_ref(normal.texture(), normal.tex_coord()));
+    let occlusion = material.occlusion_texture();
+    let occlusion_texture = occlusion
+        .as_ref()
+        .map(|occlusion| texture_ref(occlusion.texture(), occlusion.tex_coord()));
+    let emissive_texture = material.emissive_texture().map(|info| texture_ref(info.texture(), info.tex_coord()));
+
+    Material {
+        name: material.name().map(str::to_owned),
+        base_color_factor: Vec4::from(pbr.base_color_factor()),
+        base_color_texture,
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        metallic_roughness_texture,
+        normal_texture,
+        normal_scale: normal.map_or(1.0, |normal| normal.scale()),
+        occlusion_texture,
+        occlusion_strength: occlusion.map_or(1.0, |occlusion| occlusion.strength()),
+        emissive_factor: Vec3::from(material.emissive_factor()),
+        emissive_texture,
+        alpha_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask(material.alpha_cutoff().unwrap_or(0.5)),
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        double_sided: material.double_sided(),
+    }
+}
+
+fn sampler_desc(sampler: &gltf::texture::Sampler) -> SamplerDesc {
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+    };
+    let (min_filter, mipmap_mode) = match sampler.min_filter() {
+        Some(MinFilter::Nearest) | Some(MinFilter::NearestMipmapLinear) => {
+            (vk::Filter::NEAREST, vk::SamplerMipmapMode::LINEAR)
+        }
+        Some(MinFilter::NearestMipmapNearest) => (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST),
+        Some(MinFilter::LinearMipmapNearest) => (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST),
+        _ => (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR),
+    };
+
+    SamplerDesc {
+        mag_filter: match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => vk::Filter::NEAREST,
+            _ => vk::Filter::LINEAR,
+        },
+        min_filter,
+        mipmap_mode,
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+    }
+}
+
+/// Expands a decoded image to RGBA8, keeping the top byte of 16 bit channels
+/// and clamping float channels to [0, 1]
+fn to_rgba8(data: &gltf::image::Data) -> Vec<u8> {
+    let samples = match data.format {
+        Format::R8 | Format::R8G8 | Format::R8G8B8 | Format::R8G8B8A8 => data.pixels.clone(),
+        Format::R16 | Format::R16G16 | Format::R16G16B16 | Format::R16G16B16A16 => data
+            .pixels
+            .chunks_exact(2)
+            .map(|bytes| (u16::from_ne_bytes([bytes[0], bytes[1]]) >> 8) as u8)
+            .collect(),
+        Format::R32G32B32FLOAT | Format::R32G32B32A32FLOAT => data
+            .pixels
+            .chunks_exact(4)
+            .map(|bytes| {
+                let value = f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                (value.clamp(0.0, 1.0) * 255.0).round() as u8
+            })
+            .collect(),
+    };
+    let channels = match data.format {
+        Format::R8 | Format::R16 => 1,
+        Format::R8G8 | Format::R16G16 => 2,
+        Format::R8G8B8 | Format::R16G16B16 | Format::R32G32B32FLOAT => 3,
+        Format::R8G8B8A8 | Format::R16G16B16A16 | Format::R32G32B32A32FLOAT => 4,
+    };
+
+    // One and two channel images are luminance and luminance-alpha
+    samples
+        .chunks_exact(channels)
+        .flat_map(|pixel| match *pixel {
+            [l] => [l, l, l, 255],
+            [l, a] => [l, l, l, a],
+            [r, g, b] => [r, g, b, 255],
+            _ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+        })
+        .collect()
+}
@@ -0,0 +1,3 @@
+pub mod gltf;
+pub mod model;
+pub mod obj;
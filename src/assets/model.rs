@@ -0,0 +1,352 @@
+//! Format independent model data. Loaders fill in a `ModelData` on the CPU,
+//! `ModelData::upload` turns it into a `Model` with GPU meshes and images.
+
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use std::{
+    fmt,
+    path::{PathBuf},
+};
+
+use crate::{
+    error::{Result},
+    graphics::{
+        mesh::{DrawRange, Mesh, VertexLayout},
+        sampler::{SamplerDesc},
+        texture::{self, ColorSpace},
+        upload::{UploadBatch}
+    }
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, VertexLayout)]
+#[repr(C)]
+pub struct Vertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub tex_coord: Vec2,
+    pub color: Vec4,
+}
+
+impl Default for Vertex {
+    fn default() -> Self {
+        Vertex { position: Vec3::ZERO, normal: Vec3::ZERO, tex_coord: Vec2::ZERO, color: Vec4::ONE }
+    }
+}
+
+/// A draw range of a mesh and the material it is drawn with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Primitive {
+    pub range: DrawRange,
+    pub material: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub name: Option<String>,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub primitives: Vec<Primitive>,
+}
+
+/// The first index that doesn't refer to one of `vertex_count` vertices
+pub fn index_out_of_range(indices: &[u32], vertex_count: usize) -> Option<u32> {
+    indices.iter().copied().find(|&index| index as usize >= vertex_count)
+}
+
+/// Unwelds triangles so that each gets its own vertices carrying the face
+/// normal. `indices` must be in range, see `index_out_of_range`.
+pub fn flat_normals(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut flat = Vec::with_capacity(indices.len());
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|corner| vertices[triangle[corner] as usize]);
+        let normal = (b.position - a.position).cross(c.position - a.position).normalize_or_zero();
+        flat.extend([a, b, c].map(|vertex| Vertex { normal, ..vertex }));
+    }
+    let indices = (0..flat.len() as u32).collect();
+
+    (flat, indices)
+}
+
+impl MeshData {
+    /// Appends a primitive, offsetting its indices past the vertices already
+    /// added. Primitives without indices are dropped.
+    pub fn push_primitive(&mut self, vertices: &[Vertex], indices: &[u32], material: Option<usize>) {
+        if indices.is_empty() {
+            return;
+        }
+        let range = DrawRange {
+            first: self.indices.len() as u32,
+            count: indices.len() as u32,
+            vertex_offset: self.vertices.len() as i32,
+        };
+        self.vertices.extend_from_slice(vertices);
+        self.indices.extend_from_slice(indices);
+        self.primitives.push(Primitive { range, material });
+    }
+
+    pub fn upload(&self, batch: &mut UploadBatch) -> Result<Mesh> {
+        let ranges = self.primitives.iter().map(|primitive| primitive.range).collect();
+
+        Ok(Mesh::indexed(batch, &self.vertices, &self.indices)?.with_ranges(ranges))
+    }
+}
+
+/// An RGBA8 image
+#[derive(Clone, Debug)]
+pub struct ImageData {
+    pub name: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    /// Holds color rather than data such as normals, and is uploaded as sRGB
+    pub srgb: bool,
+}
+
+impl ImageData {
+    pub fn color_space(&self) -> ColorSpace {
+        if self.srgb {
+            ColorSpace::Srgb
+        } else {
+            ColorSpace::Linear
+        }
+    }
+
+    /// Uploads a texture with a generated mip chain
+    pub fn upload(&self, batch: &mut UploadBatch) -> Result<texture::Texture> {
+        texture::Texture::from_rgba8(batch, self.width, self.height, &self.pixels, self.color_space(), true)
+    }
+}
+
+/// An image in `images` and how to sample it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Texture {
+    pub image: usize,
+    pub sampler: SamplerDesc,
+}
+
+/// A material's use of a texture in `textures`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureRef {
+    pub texture: usize,
+    pub tex_coord: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlphaMode {
+    Opaque,
+    /// Alpha below the cutoff is discarded
+    Mask(f32),
+    Blend,
+}
+
+/// A metallic-roughness PBR material
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: Option<String>,
+    pub base_color_factor: Vec4,
+    pub base_color_texture: Option<TextureRef>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub metallic_roughness_texture: Option<TextureRef>,
+    pub normal_texture: Option<TextureRef>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<TextureRef>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: Vec3,
+    pub emissive_texture: Option<TextureRef>,
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            name: None,
+            base_color_factor: Vec4::ONE,
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: Vec3::ZERO,
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Perspective {
+        yfov: f32,
+        aspect_ratio: Option<f32>,
+        znear: f32,
+        /// Infinite when unset
+        zfar: Option<f32>,
+    },
+    Orthographic {
+        xmag: f32,
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub name: Option<String>,
+    pub projection: Projection,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub name: Option<String>,
+    /// Relative to the parent node
+    pub transform: Mat4,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+}
+
+/// Something in a model file that the loader skipped or only partly supports
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssetWarning {
+    /// An extension the loader ignores. Required ones will likely render wrong.
+    Extension { name: String, required: bool },
+    /// A primitive reading a sparse accessor, which is skipped
+    SparseAccessor { mesh: usize, primitive: usize, accessor: usize },
+    /// A primitive that isn't a triangle list, which is skipped
+    PrimitiveMode { mesh: usize, primitive: usize, mode: String },
+    /// A primitive without positions, which is skipped
+    MissingPositions { mesh: usize, primitive: usize },
+    /// A primitive with an index past its vertices, which is skipped
+    IndexOutOfRange { mesh: usize, primitive: usize, index: u32, vertex_count: usize },
+    /// A texture read with a texture coordinate set other than 0, which
+    /// `Vertex` doesn't carry
+    TexCoordSet { material: usize, set: u32 },
+    /// A material library that couldn't be read, leaving meshes without materials
+    MaterialLibrary { error: String },
+    /// A texture image that couldn't be read, which is left out of its material
+    Texture { path: PathBuf, error: String },
+}
+
+impl fmt::Display for AssetWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetWarning::Extension { name, required: true } => write!(f, "required extension {} is not supported", name),
+            AssetWarning::Extension { name, required: false } => write!(f, "extension {} is ignored", name),
+            AssetWarning::SparseAccessor { mesh, primitive, accessor } => write!(
+                f,
+                "mesh {} primitive {} skipped, sparse accessor {} is not supported",
+                mesh, primitive, accessor
+            ),
+            AssetWarning::PrimitiveMode { mesh, primitive, mode } => write!(
+                f,
+                "mesh {} primitive {} skipped, mode {} is not supported",
+                mesh, primitive, mode
+            ),
+            AssetWarning::MissingPositions { mesh, primitive } => {
+                write!(f, "mesh {} primitive {} skipped, it has no positions", mesh, primitive)
+            }
+            AssetWarning::IndexOutOfRange { mesh, primitive, index, vertex_count } => write!(
+                f,
+                "mesh {} primitive {} skipped, index {} is past its {} vertices",
+                mesh, primitive, index, vertex_count
+            ),
+            AssetWarning::TexCoordSet { material, set } => {
+                write!(f, "material {} uses texture coordinate set {}, only set 0 is loaded", material, set)
+            }
+            AssetWarning::MaterialLibrary { error } => write!(f, "materials not loaded: {}", error),
+            AssetWarning::Texture { path, error } => write!(f, "texture {} not loaded: {}", path.display(), error),
+        }
+    }
+}
+
+/// A model loaded into memory, not yet uploaded
+#[derive(Clone, Debug, Default)]
+pub struct ModelData {
+    pub meshes: Vec<MeshData>,
+    pub images: Vec<ImageData>,
+    pub textures: Vec<Texture>,
+    pub materials: Vec<Material>,
+    pub cameras: Vec<Camera>,
+    pub nodes: Vec<Node>,
+    /// Nodes without a parent in the scene that was loaded
+    pub roots: Vec<usize>,
+    pub warnings: Vec<AssetWarning>,
+}
+
+impl ModelData {
+    /// Transform from a node's space to model space
+    pub fn world_transform(&self, node: usize) -> Mat4 {
+        world_transform(&self.nodes, node)
+    }
+
+    /// Uploads meshes and images. Meshes without primitives get `None`.
+    pub fn upload(self, batch: &mut UploadBatch) -> Result<Model> {
+        let meshes = self
+            .meshes
+            .iter()
+            .map(|mesh| {
+                if mesh.primitives.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(ModelMesh {
+                    mesh: mesh.upload(batch)?,
+                    materials: mesh.primitives.iter().map(|primitive| primitive.material).collect(),
+                }))
+            })
+            .collect::<Result<_>>()?;
+        let images = self.images.iter().map(|image| image.upload(batch)).collect::<Result<_>>()?;
+
+        Ok(Model {
+            meshes,
+            images,
+            textures: self.textures,
+            materials: self.materials,
+            cameras: self.cameras,
+            nodes: self.nodes,
+            roots: self.roots,
+            warnings: self.warnings,
+        })
+    }
+}
+
+pub struct ModelMesh {
+    pub mesh: Mesh,
+    /// Material of each of `mesh.ranges`
+    pub materials: Vec<Option<usize>>,
+}
+
+/// A model whose meshes and images live on the GPU
+pub struct Model {
+    pub meshes: Vec<Option<ModelMesh>>,
+    pub images: Vec<texture::Texture>,
+    pub textures: Vec<Texture>,
+    pub materials: Vec<Material>,
+    pub cameras: Vec<Camera>,
+    pub nodes: Vec<Node>,
+    pub roots: Vec<usize>,
+    pub warnings: Vec<AssetWarning>,
+}
+
+impl Model {
+    /// Transform from a node's space to model space
+    pub fn world_transform(&self, node: usize) -> Mat4 {
+        world_transform(&self.nodes, node)
+    }
+}
+
+fn world_transform(nodes: &[Node], node: usize) -> Mat4 {
+    let mut transform = nodes[node].transform;
+    let mut parent = nodes[node].parent;
+    while let Some(index) = parent {
+        transform = nodes[index].transform * transform;
+        parent = nodes[index].parent;
+    }
+    transform
+}
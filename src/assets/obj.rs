@@ -0,0 +1,145 @@
+//! Loads Wavefront `.obj` files and their `.mtl` material libraries into
+//! `ModelData`.
+//!
+//! Every object or group becomes a mesh with a root node of the same name.
+//! Polygons are triangulated, and flat normals are generated for faces
+//! without any. MTL diffuse colors and textures map onto the base color of
+//! a metallic-roughness material.
+
+use glam::{Mat4, Vec2, Vec3};
+use std::{
+    collections::{HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    assets::model::{
+        flat_normals, index_out_of_range, AlphaMode, AssetWarning, ImageData, Material, MeshData, ModelData, Node,
+        Texture, TextureRef, Vertex
+    },
+    error::{Error, Result},
+    graphics::sampler::{SamplerDesc}
+};
+
+impl ModelData {
+    /// Loads an OBJ file. Material libraries and textures are looked up
+    /// relative to it, and ones that can't be read are reported as warnings.
+    pub fn from_obj_file(path: impl AsRef<Path>) -> Result<ModelData> {
+        let path = path.as_ref();
+        let (models, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS).map_err(Error::Obj)?;
+        let base = path.parent().unwrap_or_else(|| Path::new("."));
+
+        let mut data = ModelData::default();
+        let materials = materials.unwrap_or_else(|e| {
+            data.warnings.push(AssetWarning::MaterialLibrary { error: e.to_string() });
+            Vec::new()
+        });
+
+        let mut textures = HashMap::new();
+        for material in &materials {
+            let mut texture_ref = |file: &Option<String>, srgb: bool| {
+                let path = base.join(file.as_ref()?);
+                load_texture(&mut data, &mut textures, path, srgb)
+            };
+            let base_color_texture = texture_ref(&material.diffuse_texture, true);
+            let normal_texture = texture_ref(&material.normal_texture, false);
+
+            let diffuse = material.diffuse.map_or(Vec3::ONE, Vec3::from);
+            let alpha = material.dissolve.unwrap_or(1.0);
+            data.materials.push(Material {
+                name: Some(material.name.clone()),
+                base_color_factor: diffuse.extend(alpha),
+                base_color_texture,
+                metallic_factor: 0.0,
+                // Maps the Phong exponent onto a comparable roughness
+                roughness_factor: material.shininess.map_or(1.0, |shininess| (2.0 / (shininess.max(0.0) + 2.0)).sqrt()),
+                normal_texture,
+                alpha_mode: if alpha < 1.0 { AlphaMode::Blend } else { AlphaMode::Opaque },
+                ..Default::default()
+            });
+        }
+
+        for model in models {
+            let mesh = &model.mesh;
+            let mut vertices = mesh
+                .positions
+                .chunks_exact(3)
+                .map(|position| Vertex { position: Vec3::from_slice(position), ..Default::default() })
+                .collect::<Vec<_>>();
+            if !mesh.texcoords.is_empty() {
+                // OBJ puts v = 0 at the bottom of the image, Vulkan at the top
+                for (vertex, tex_coord) in vertices.iter_mut().zip(mesh.texcoords.chunks_exact(2)) {
+                    vertex.tex_coord = Vec2::new(tex_coord[0], 1.0 - tex_coord[1]);
+                }
+            }
+            if !mesh.vertex_color.is_empty() {
+                for (vertex, color) in vertices.iter_mut().zip(mesh.vertex_color.chunks_exact(3)) {
+                    vertex.color = Vec3::from_slice(color).extend(1.0);
+                }
+            }
+
+            let mut mesh_data = MeshData { name: Some(model.name.clone()), ..Default::default() };
+            if let Some(index) = index_out_of_range(&mesh.indices, vertices.len()) {
+                data.warnings.push(AssetWarning::IndexOutOfRange {
+                    mesh: data.meshes.len(),
+                    primitive: 0,
+                    index,
+                    vertex_count: vertices.len(),
+                });
+            } else if mesh.normals.is_empty() {
+                let (vertices, indices) = flat_normals(&vertices, &mesh.indices);
+                mesh_data.push_primitive(&vertices, &indices, mesh.material_id);
+            } else {
+                for (vertex, normal) in vertices.iter_mut().zip(mesh.normals.chunks_exact(3)) {
+                    vertex.normal = Vec3::from_slice(normal);
+                }
+                mesh_data.push_primitive(&vertices, &mesh.indices, mesh.material_id);
+            }
+
+            data.roots.push(data.nodes.len());
+            data.nodes.push(Node {
+                name: Some(model.name),
+                transform: Mat4::IDENTITY,
+                parent: None,
+                children: Vec::new(),
+                mesh: Some(data.meshes.len()),
+                camera: None,
+            });
+            data.meshes.push(mesh_data);
+        }
+
+        Ok(data)
+    }
+}
+
+/// Loads an image once per path and color space and gives it a texture with
+/// the default sampler
+fn load_texture(data: &mut ModelData,
+                textures: &mut HashMap<(PathBuf, bool), usize>,
+                path: PathBuf,
+                srgb: bool
+) -> Option<TextureRef> {
+    if let Some(&texture) = textures.get(&(path.clone(), srgb)) {
+        return Some(TextureRef { texture, tex_coord: 0 });
+    }
+
+    let image = match image::open(&path) {
+        Ok(image) => image.to_rgba8(),
+        Err(e) => {
+            data.warnings.push(AssetWarning::Texture { path, error: e.to_string() });
+            return None;
+        }
+    };
+    data.images.push(ImageData {
+        name: path.file_name().map(|name| name.to_string_lossy().into_owned()),
+        width: image.width(),
+        height: image.height(),
+        pixels: image.into_raw(),
+        srgb,
+    });
+    let texture = data.textures.len();
+    data.textures.push(Texture { image: data.images.len() - 1, sampler: SamplerDesc::default() });
+    textures.insert((path, srgb), texture);
+
+    Some(TextureRef { texture, tex_coord: 0 })
+}
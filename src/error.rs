@@ -0,0 +1,297 @@
+use std::{fmt, path::PathBuf};
+
+use ash::{vk::{self}};
+use openxr as xr;
+
+use crate::{
+    graphics::shader_module::CompileError,
+    xr::{action::ActionType, bindings::Input, manifest::ManifestDiagnostic},
+};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The OpenXR loader library could not be found or loaded
+    XrLoader(xr::LoadError),
+    /// The Vulkan loader library could not be found or loaded
+    VkLoader(ash::LoadingError),
+    /// An OpenXR call failed while performing `stage`
+    Xr {
+        stage: &'static str,
+        result: xr::sys::Result,
+    },
+    /// A Vulkan call failed while performing `stage`
+    Vk {
+        stage: &'static str,
+        result: vk::Result,
+    },
+    /// An I/O operation failed while performing `stage`
+    Io {
+        stage: &'static str,
+        source: std::io::Error,
+    },
+    /// The OpenXR runtime does not accept the Vulkan version we target
+    UnsupportedVulkanVersion {
+        requested: xr::Version,
+        min_supported: xr::Version,
+        max_supported: xr::Version,
+    },
+    /// The system does not support the requested view configuration
+    UnsupportedViewConfiguration(xr::ViewConfigurationType),
+    /// The view configuration has more views than the renderer supports
+    TooManyViews {
+        view_count: u32,
+        max_views: u32,
+    },
+    /// The system does not support the requested environment blend mode
+    UnsupportedBlendMode(xr::EnvironmentBlendMode),
+    /// The system reports no environment blend mode for the view configuration
+    NoBlendModes,
+    /// An action name that is not declared in the action manifest
+    UnknownAction(String),
+    /// A subaction path that no action in the manifest was declared with
+    UnknownSubactionPath(String),
+    /// An action was queried as a different type than it was declared with
+    ActionTypeMismatch {
+        action: String,
+        expected: ActionType,
+        actual: ActionType,
+    },
+    /// An action was declared with an `Input` its type can't be bound to
+    InputTypeMismatch {
+        action: String,
+        action_type: ActionType,
+        input: Input,
+    },
+    /// An action manifest file failed to parse or validate
+    Manifest {
+        file: PathBuf,
+        diagnostics: Vec<ManifestDiagnostic>,
+    },
+    /// The physical device has no queue family that supports graphics
+    NoGraphicsQueue,
+    /// None of the configured depth formats can be used as an attachment
+    NoSupportedDepthFormat,
+    /// No memory type satisfies a resource's requirements
+    NoSuitableMemoryType,
+    /// Host access to memory that isn't host visible
+    MemoryNotMapped,
+    /// A capture was requested before any swapchain image was rendered
+    NoRenderedImage,
+    /// A capture was requested of an array layer the swapchain doesn't have
+    InvalidCaptureLayer {
+        layer: u32,
+        view_count: u32,
+    },
+    /// A buffer upload reaches past the end of its destination
+    UploadOutOfBounds {
+        offset: u64,
+        len: u64,
+        size: u64,
+    },
+    /// An image upload has data for more mip levels than the image has
+    TooManyImageLevels {
+        levels: usize,
+        mip_levels: u32,
+    },
+    /// Data for an image upload is shorter than its mip level
+    ImageLevelTooShort {
+        level: u32,
+        len: usize,
+        expected: u64,
+    },
+    /// A captured image could not be encoded
+    Png(png::EncodingError),
+    /// A reference image could not be decoded
+    PngDecode(png::DecodingError),
+    /// A glTF file, or a buffer or image it references, could not be read
+    Gltf(gltf::Error),
+    /// An OBJ file could not be read
+    Obj(tobj::LoadError),
+    /// A PNG or JPEG texture could not be decoded
+    Image(image::ImageError),
+    /// A KTX2 texture could not be parsed
+    Ktx2(ktx2::ParseError),
+    /// A texture uses a feature that isn't supported
+    UnsupportedTexture(&'static str),
+    /// Raw texture pixels don't match the texture's size
+    TextureSizeMismatch {
+        len: usize,
+        expected: usize,
+    },
+    /// A shader module isn't a valid SPIR-V binary
+    InvalidSpirv(String),
+    /// A GLSL or WGSL shader failed to compile
+    Shader(CompileError),
+    /// Shader files could not be watched for changes
+    Watch(notify::Error),
+    /// The Ctrl-C handler could not be installed
+    Signal(ctrlc::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::XrLoader(e) => write!(f, "error loading OpenXR loader: {}", e),
+            Error::VkLoader(e) => write!(f, "error loading Vulkan loader: {}", e),
+            Error::Xr { stage, result } => write!(f, "OpenXR error {}: {}", stage, result),
+            Error::Vk { stage, result } => write!(f, "Vulkan error {}: {}", stage, result),
+            Error::Io { stage, source } => write!(f, "I/O error {}: {}", stage, source),
+            Error::UnsupportedVulkanVersion { requested, min_supported, max_supported } => write!(
+                f,
+                "Vulkan version {} not supported. OpenXR runtime requires Vulkan version > {}, < {}.0.0",
+                requested,
+                min_supported,
+                max_supported.major() + 1
+            ),
+            Error::UnsupportedViewConfiguration(view_type) => {
+                write!(f, "view configuration {:?} not supported", view_type)
+            }
+            Error::TooManyViews { view_count, max_views } => write!(
+                f,
+                "{} views requested, the renderer supports at most {}",
+                view_count, max_views
+            ),
+            Error::UnsupportedBlendMode(blend_mode) => {
+                write!(f, "environment blend mode {:?} not supported", blend_mode)
+            }
+            Error::NoBlendModes => write!(f, "no environment blend modes supported"),
+            Error::UnknownAction(name) => write!(f, "unknown action {}", name),
+            Error::UnknownSubactionPath(path) => write!(f, "unknown subaction path {}", path),
+            Error::ActionTypeMismatch { action, expected, actual } => write!(
+                f,
+                "action {} is a {:?} action, not {:?}",
+                action, actual, expected
+            ),
+            Error::InputTypeMismatch { action, action_type, input } => write!(
+                f,
+                "{:?} action {} cannot be bound to input {:?}",
+                action_type, action, input
+            ),
+            Error::Manifest { file, diagnostics } => {
+                write!(f, "invalid action manifest {}:", file.display())?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n  {}:{}", file.display(), diagnostic)?;
+                }
+                Ok(())
+            }
+            Error::NoGraphicsQueue => write!(f, "no Vulkan queue family supports graphics"),
+            Error::NoSupportedDepthFormat => write!(f, "no supported depth format"),
+            Error::NoSuitableMemoryType => write!(f, "no suitable Vulkan memory type"),
+            Error::MemoryNotMapped => write!(f, "memory is not mapped for host access"),
+            Error::NoRenderedImage => write!(f, "no swapchain image has been rendered yet"),
+            Error::InvalidCaptureLayer { layer, view_count } => write!(
+                f,
+                "can't capture layer {} of a swapchain with {} views",
+                layer, view_count
+            ),
+            Error::UploadOutOfBounds { offset, len, size } => write!(
+                f,
+                "upload of {} bytes at offset {} is past the end of a {} byte buffer",
+                len, offset, size
+            ),
+            Error::TooManyImageLevels { levels, mip_levels } => write!(
+                f,
+                "upload has {} mip levels, the image has {}",
+                levels, mip_levels
+            ),
+            Error::ImageLevelTooShort { level, len, expected } => write!(
+                f,
+                "mip level {} has {} bytes of data, needs {}",
+                level, len, expected
+            ),
+            Error::Png(e) => write!(f, "error encoding PNG: {}", e),
+            Error::PngDecode(e) => write!(f, "error decoding PNG: {}", e),
+            Error::Gltf(e) => write!(f, "error loading glTF: {}", e),
+            Error::Obj(e) => write!(f, "error loading OBJ: {}", e),
+            Error::Image(e) => write!(f, "error decoding image: {}", e),
+            Error::Ktx2(e) => write!(f, "error parsing KTX2: {}", e),
+            Error::UnsupportedTexture(feature) => write!(f, "unsupported texture: {}", feature),
+            Error::TextureSizeMismatch { len, expected } => write!(
+                f,
+                "texture has {} bytes of pixels, its size needs {}",
+                len, expected
+            ),
+            Error::InvalidSpirv(reason) => write!(f, "shader is not valid SPIR-V: {}", reason),
+            Error::Shader(e) => write!(f, "{}", e),
+            Error::Watch(e) => write!(f, "error watching shader files: {}", e),
+            Error::Signal(e) => write!(f, "error setting Ctrl-C handler: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::XrLoader(e) => Some(e),
+            Error::VkLoader(e) => Some(e),
+            Error::Xr { result, .. } => Some(result),
+            Error::Vk { result, .. } => Some(result),
+            Error::Io { source, .. } => Some(source),
+            Error::Png(e) => Some(e),
+            Error::PngDecode(e) => Some(e),
+            Error::Gltf(e) => Some(e),
+            Error::Obj(e) => Some(e),
+            Error::Image(e) => Some(e),
+            Error::Ktx2(e) => Some(e),
+            Error::Shader(e) => Some(e),
+            Error::Watch(e) => Some(e),
+            Error::Signal(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<xr::LoadError> for Error {
+    fn from(e: xr::LoadError) -> Self {
+        Error::XrLoader(e)
+    }
+}
+
+impl From<ash::LoadingError> for Error {
+    fn from(e: ash::LoadingError) -> Self {
+        Error::VkLoader(e)
+    }
+}
+
+impl From<CompileError> for Error {
+    fn from(e: CompileError) -> Self {
+        Error::Shader(e)
+    }
+}
+
+impl From<notify::Error> for Error {
+    fn from(e: notify::Error) -> Self {
+        Error::Watch(e)
+    }
+}
+
+impl From<ctrlc::Error> for Error {
+    fn from(e: ctrlc::Error) -> Self {
+        Error::Signal(e)
+    }
+}
+
+/// Attaches the stage that failed to a raw OpenXR, Vulkan or I/O result
+pub(crate) trait Context<T> {
+    fn context(self, stage: &'static str) -> Result<T>;
+}
+
+impl<T> Context<T> for std::result::Result<T, xr::sys::Result> {
+    fn context(self, stage: &'static str) -> Result<T> {
+        self.map_err(|result| Error::Xr { stage, result })
+    }
+}
+
+impl<T> Context<T> for std::result::Result<T, vk::Result> {
+    fn context(self, stage: &'static str) -> Result<T> {
+        self.map_err(|result| Error::Vk { stage, result })
+    }
+}
+
+impl<T> Context<T> for std::result::Result<T, std::io::Error> {
+    fn context(self, stage: &'static str) -> Result<T> {
+        self.map_err(|source| Error::Io { stage, source })
+    }
+}
@@ -0,0 +1,136 @@
+use ash::{vk::{self}};
+use glam::Mat4;
+use std::{
+    mem,
+    sync::{Arc},
+};
+
+use crate::{
+    error::{Result},
+    graphics::{
+        descriptor::{DescriptorAllocator, DescriptorLayoutBuilder, DescriptorWriter},
+        memory::{Buffer, MemoryLocation},
+        vk_base::VkBase,
+        PIPELINE_DEPTH
+    },
+    xr::{frame::FrameContext}
+};
+
+/// Views a camera uniform buffer has room for; enough for stereo.
+/// `VkRenderer::new` refuses swapchains with more views.
+pub const MAX_VIEWS: usize = 2;
+
+/// Layout of the `Camera` uniform block at set 0, binding 0 of the library
+/// pipelines, declared in `shaders/camera.wgsl`. Multiview shaders pick their
+/// eye with `@builtin(view_index)`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CameraUniforms {
+    pub view: [Mat4; MAX_VIEWS],
+    pub projection: [Mat4; MAX_VIEWS],
+    pub view_projection: [Mat4; MAX_VIEWS],
+}
+
+impl CameraUniforms {
+    /// Views past `MAX_VIEWS` are ignored, slots without a view are left at
+    /// identity
+    pub fn from_frame(frame: &FrameContext) -> Self {
+        let mut uniforms = CameraUniforms {
+            view: [Mat4::IDENTITY; MAX_VIEWS],
+            projection: [Mat4::IDENTITY; MAX_VIEWS],
+            view_projection: [Mat4::IDENTITY; MAX_VIEWS],
+        };
+        for (i, eye) in frame.views.iter().take(MAX_VIEWS).enumerate() {
+            uniforms.view[i] = eye.view;
+            uniforms.projection[i] = eye.projection;
+            uniforms.view_projection[i] = eye.view_projection();
+        }
+        uniforms
+    }
+}
+
+/// One persistently mapped camera uniform buffer and descriptor set per frame
+/// in flight
+pub struct CameraBuffers {
+    /// Owned by `VkBase`'s layout cache
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    pub buffers: Vec<Buffer>,
+    /// Owns the pools `descriptor_sets` come from
+    _descriptors: DescriptorAllocator,
+    // Held so the device outlives the descriptor objects
+    vk_base: Arc<VkBase>,
+}
+
+impl CameraBuffers {
+    pub fn new(vk_base: &Arc<VkBase>) -> Result<CameraBuffers> {
+        let descriptor_set_layout = CameraBuffers::layout(vk_base)?;
+        let mut descriptors = DescriptorAllocator::with_ratios(vk_base,
+                                                               PIPELINE_DEPTH,
+                                                               &[(vk::DescriptorType::UNIFORM_BUFFER, 1.0)]
+        );
+
+        let size = mem::size_of::<CameraUniforms>() as vk::DeviceSize;
+        let mut descriptor_sets = Vec::new();
+        let mut buffers = Vec::new();
+        for _ in 0..PIPELINE_DEPTH {
+            let buffer = Buffer::new(vk_base,
+                                     size,
+                                     vk::BufferUsageFlags::UNIFORM_BUFFER,
+                                     MemoryLocation::HostVisible
+            )?;
+            let descriptor_set = descriptors.allocate(descriptor_set_layout)?;
+            DescriptorWriter::new()
+                .uniform_buffer(0, &buffer, 0, size)
+                .write(vk_base, descriptor_set);
+
+            descriptor_sets.push(descriptor_set);
+            buffers.push(buffer);
+        }
+
+        Ok(CameraBuffers {
+            descriptor_set_layout,
+            descriptor_sets,
+            buffers,
+            _descriptors: descriptors,
+            vk_base: vk_base.clone(),
+        })
+    }
+
+    /// The layout of set 0 in pipelines that read `CameraUniforms`
+    pub fn layout(vk_base: &VkBase) -> Result<vk::DescriptorSetLayout> {
+        DescriptorLayoutBuilder::new()
+            .uniform_buffer(0, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+            .build(vk_base)
+    }
+
+    /// Writes the matrices for `frame` into the buffer of frame in flight
+    /// `index`. The previous submission using that buffer must have completed.
+    pub fn update(&mut self, index: usize, frame: &FrameContext) -> Result<()> {
+        self.buffers[index].write(0, &[CameraUniforms::from_frame(frame)])
+    }
+
+    pub fn cmd_bind(&self,
+                    cmd_buffer: vk::CommandBuffer,
+                    pipeline_layout: vk::PipelineLayout,
+                    index: usize,
+    ) {
+        unsafe {
+            self.vk_base.device.handle.cmd_bind_descriptor_sets(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline_layout,
+                0,
+                &[self.descriptor_sets[index]],
+                &[],
+            );
+        }
+    }
+}
+
+impl Drop for CameraBuffers {
+    fn drop(&mut self) {
+        // The descriptor pools are destroyed with `_descriptors`
+        let _ = self.vk_base.device.device_wait_idle();
+    }
+}
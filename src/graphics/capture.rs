@@ -0,0 +1,312 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use ash::{vk::{self}};
+
+use crate::{
+    error::{Context, Error, Result},
+    graphics::{
+        vk_base::VkBase
+    }
+};
+
+/// Which part of a layered swapchain image to read back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureView {
+    /// A single array layer, 0 being the left eye and 1 the right eye
+    Layer(u32),
+    /// Every layer placed next to each other, left to right
+    SideBySide,
+}
+
+#[derive(Clone, Debug)]
+pub struct CaptureRequest {
+    pub path: PathBuf,
+    pub view: CaptureView,
+}
+
+/// Thread-safe handle for requesting a capture of the next rendered frame,
+/// e.g. from a hotkey handler while `App::run` is blocking
+#[derive(Clone, Default)]
+pub struct CaptureTrigger {
+    request: Arc<Mutex<Option<CaptureRequest>>>,
+}
+
+impl CaptureTrigger {
+    pub fn request(&self, path: impl Into<PathBuf>, view: CaptureView) {
+        *self.request.lock().unwrap() = Some(CaptureRequest {
+            path: path.into(),
+            view,
+        });
+    }
+
+    pub fn take(&self) -> Option<CaptureRequest> {
+        self.request.lock().unwrap().take()
+    }
+}
+
+/// Tightly packed 8-bit RGBA pixels, in the swapchain's sRGB encoding
+#[derive(Clone, Debug, PartialEq)]
+pub struct CapturedImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl CapturedImage {
+    pub fn side_by_side(images: &[CapturedImage]) -> CapturedImage {
+        let height = images.iter().map(|image| image.height).max().unwrap_or(0);
+        let width = images.iter().map(|image| image.width).sum();
+
+        let mut pixels = vec![0; (width * height * 4) as usize];
+        let mut x_offset = 0;
+        for image in images {
+            let row_size = (image.width * 4) as usize;
+            for y in 0..image.height as usize {
+                let src = y * row_size;
+                let dst = (y * width as usize + x_offset as usize) * 4;
+                pixels[dst..dst + row_size].copy_from_slice(&image.pixels[src..src + row_size]);
+            }
+            x_offset += image.width;
+        }
+
+        CapturedImage { width, height, pixels }
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = File::create(path).context("creating capture file")?;
+
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_srgb(png::SrgbRenderingIntent::Perceptual);
+
+        let mut writer = encoder.write_header().map_err(Error::Png)?;
+        writer.write_image_data(&self.pixels).map_err(Error::Png)
+    }
+
+    /// Loads an 8-bit RGB or RGBA PNG, e.g. a previously saved capture
+    pub fn load_png(path: impl AsRef<Path>) -> Result<CapturedImage> {
+        let file = File::open(path).context("opening PNG file")?;
+
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(Error::PngDecode)?;
+
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(Error::PngDecode)?;
+        buffer.truncate(info.buffer_size());
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buffer,
+            png::ColorType::Rgb => buffer
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buffer
+                .chunks_exact(2)
+                .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+                .collect(),
+            _ => buffer
+                .iter()
+                .flat_map(|&g| [g, g, g, 255])
+                .collect(),
+        };
+
+        Ok(CapturedImage {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+}
+
+/// Copies one array layer of `image` into host memory. The image must be in
+/// `COLOR_ATTACHMENT_OPTIMAL` layout with all rendering to it completed, and is
+/// left in that layout. `layer` must be one of the image's array layers;
+/// `Swapchain::capture` checks it against the view count.
+pub fn capture_layer(vk_base: &VkBase,
+                     image: vk::Image,
+                     extent: vk::Extent2D,
+                     layer: u32,
+) -> Result<CapturedImage> {
+    let device = &vk_base.device.handle;
+    let size = (extent.width * extent.height * 4) as vk::DeviceSize;
+
+    unsafe {
+        let buffer = device
+            .create_buffer(
+                &vk::BufferCreateInfo::builder()
+                    .size(size)
+                    .usage(vk::BufferUsageFlags::TRANSFER_DST)
+                    .sharing_mode(vk::SharingMode::EXCLUSIVE),
+                None,
+            )
+            .context("creating capture buffer")?;
+
+        let memory_requirements = device.get_buffer_memory_requirements(buffer);
+        let memory_type_index = vk_base
+            .physical_device
+            .find_memory_type_index(
+                &memory_requirements,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )
+            .ok_or(Error::NoSuitableMemoryType);
+        let memory = memory_type_index.and_then(|memory_type_index| {
+            device
+                .allocate_memory(
+                    &vk::MemoryAllocateInfo::builder()
+                        .allocation_size(memory_requirements.size)
+                        .memory_type_index(memory_type_index),
+                    None,
+                )
+                .context("allocating capture memory")
+        });
+        let memory = match memory {
+            Ok(memory) => memory,
+            Err(e) => {
+                device.destroy_buffer(buffer, None);
+                return Err(e);
+            }
+        };
+
+        let result = copy_layer(vk_base, image, extent, layer, buffer, memory, size);
+
+        device.destroy_buffer(buffer, None);
+        device.free_memory(memory, None);
+
+        result
+    }
+}
+
+unsafe fn copy_layer(vk_base: &VkBase,
+                     image: vk::Image,
+                     extent: vk::Extent2D,
+                     layer: u32,
+                     buffer: vk::Buffer,
+                     memory: vk::DeviceMemory,
+                     size: vk::DeviceSize,
+) -> Result<CapturedImage> {
+    let device = &vk_base.device.handle;
+
+    device
+        .bind_buffer_memory(buffer, memory, 0)
+        .context("binding capture memory")?;
+
+    let cmd_buffer = device
+        .allocate_command_buffers(
+            &vk::CommandBufferAllocateInfo::builder()
+                .command_pool(vk_base.command_pool.handle)
+                .command_buffer_count(1),
+        )
+        .context("allocating capture command buffer")?[0];
+    let fence = match device.create_fence(&vk::FenceCreateInfo::default(), None) {
+        Ok(fence) => fence,
+        Err(result) => {
+            device.free_command_buffers(vk_base.command_pool.handle, &[cmd_buffer]);
+            return Err(Error::Vk { stage: "creating capture fence", result });
+        }
+    };
+
+    let subresource_range = vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: layer,
+        layer_count: 1,
+    };
+
+    let submitted = (|| {
+        vk_base.device.begin_command_buffer(cmd_buffer)?;
+
+        device.cmd_pipeline_barrier(
+            cmd_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[vk::ImageMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .old_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(subresource_range)
+                .build()],
+        );
+
+        device.cmd_copy_image_to_buffer(
+            cmd_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            buffer,
+            &[vk::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: layer,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D::default(),
+                image_extent: extent.into(),
+            }],
+        );
+
+        device.cmd_pipeline_barrier(
+            cmd_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[vk::BufferMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::HOST_READ)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .buffer(buffer)
+                .offset(0)
+                .size(vk::WHOLE_SIZE)
+                .build()],
+            &[vk::ImageMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .new_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(subresource_range)
+                .build()],
+        );
+
+        vk_base.device.end_command_buffer(cmd_buffer)?;
+        vk_base.device.queue_submit(cmd_buffer, fence)?;
+        vk_base.device.wait_for_fences(&vec![fence], u64::MAX)
+    })();
+
+    device.destroy_fence(fence, None);
+    device.free_command_buffers(vk_base.command_pool.handle, &[cmd_buffer]);
+    submitted?;
+
+    let ptr = device
+        .map_memory(memory, 0, size, vk::MemoryMapFlags::empty())
+        .context("mapping capture memory")?;
+    let pixels = std::slice::from_raw_parts(ptr as *const u8, size as usize).to_vec();
+    device.unmap_memory(memory);
+
+    Ok(CapturedImage {
+        width: extent.width,
+        height: extent.height,
+        pixels,
+    })
+}
@@ -1,19 +1,21 @@
 use ash::{vk::{self}};
 use std::sync::{Arc};
 
-use crate::graphics::{
-    device::Device,
-    command_pool::CommandPool
+use crate::{
+    error::{Context, Result},
+    graphics::{
+        device::Device,
+        command_pool::CommandPool,
+        PIPELINE_DEPTH
+    }
 };
 
-const PIPELINE_DEPTH: u32 = 2;
-
 pub struct CommandBuffer {
     pub handle: Vec<ash::vk::CommandBuffer>
 }
 
 impl CommandBuffer {
-    pub fn new(device: &Arc<Device>, command_pool: &Arc<CommandPool>) -> Arc<CommandBuffer> {
+    pub fn new(device: &Arc<Device>, command_pool: &Arc<CommandPool>) -> Result<Arc<CommandBuffer>> {
         unsafe {
             let handle = device
                 .handle
@@ -22,11 +24,11 @@ impl CommandBuffer {
                         .command_pool(command_pool.handle)
                         .command_buffer_count(PIPELINE_DEPTH),
                 )
-                .unwrap();
+                .context("allocating command buffers")?;
 
-            Arc::new(CommandBuffer {
+            Ok(Arc::new(CommandBuffer {
                 handle
-            })
+            }))
         }
     }
 }
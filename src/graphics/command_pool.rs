@@ -1,8 +1,11 @@
 use ash::{vk::{self}};
 use std::sync::{Arc};
 
-use crate::graphics::{
-    device::Device,
+use crate::{
+    error::{Context, Result},
+    graphics::{
+        device::Device,
+    }
 };
 
 pub struct CommandPool {
@@ -10,7 +13,7 @@ pub struct CommandPool {
 }
 
 impl CommandPool {
-    pub fn new(device: &Arc<Device>) -> Arc<CommandPool> {
+    pub fn new(device: &Arc<Device>) -> Result<Arc<CommandPool>> {
         unsafe {
             let handle = device
                 .handle
@@ -23,11 +26,11 @@ impl CommandPool {
                         ),
                     None,
                 )
-                .unwrap();
+                .context("creating command pool")?;
 
-            Arc::new(CommandPool {
+            Ok(Arc::new(CommandPool {
                 handle
-            })
+            }))
         }
     }
 }
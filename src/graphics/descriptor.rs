@@ -0,0 +1,405 @@
+//! Descriptor set layouts, pools and writes.
+//!
+//! Layouts are described with `DescriptorLayoutBuilder` and cached on
+//! `VkBase` by their bindings, so identical descriptions share one layout.
+//! `DescriptorAllocator` hands out sets from pools it adds as they fill up,
+//! and gives all of them back on `reset`, e.g. once per frame in flight.
+//! `DescriptorWriter` points the bindings of a set at buffers and images.
+
+use ash::{vk::{self}};
+use std::{
+    collections::HashMap,
+    slice,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    error::{Context, Result},
+    graphics::{
+        memory::Buffer,
+        texture::Texture,
+        vk_base::VkBase
+    }
+};
+
+/// Descriptors of each type per set in the pools of a `DescriptorAllocator`
+pub const DEFAULT_POOL_RATIOS: &[(vk::DescriptorType, f32)] = &[
+    (vk::DescriptorType::UNIFORM_BUFFER, 2.0),
+    (vk::DescriptorType::STORAGE_BUFFER, 2.0),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4.0),
+    (vk::DescriptorType::STORAGE_IMAGE, 1.0),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DescriptorBinding {
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub count: u32,
+    pub stages: vk::ShaderStageFlags,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DescriptorLayoutBuilder {
+    bindings: Vec<DescriptorBinding>,
+}
+
+impl DescriptorLayoutBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn binding(mut self,
+                   binding: u32,
+                   descriptor_type: vk::DescriptorType,
+                   count: u32,
+                   stages: vk::ShaderStageFlags,
+    ) -> Self {
+        assert!(
+            self.bindings.iter().all(|existing| existing.binding != binding),
+            "binding {} is described twice", binding
+        );
+        self.bindings.push(DescriptorBinding { binding, descriptor_type, count, stages });
+        self
+    }
+
+    pub fn uniform_buffer(self, binding: u32, stages: vk::ShaderStageFlags) -> Self {
+        self.binding(binding, vk::DescriptorType::UNIFORM_BUFFER, 1, stages)
+    }
+
+    pub fn storage_buffer(self, binding: u32, stages: vk::ShaderStageFlags) -> Self {
+        self.binding(binding, vk::DescriptorType::STORAGE_BUFFER, 1, stages)
+    }
+
+    pub fn combined_image_sampler(self, binding: u32, stages: vk::ShaderStageFlags) -> Self {
+        self.binding(binding, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1, stages)
+    }
+
+    pub fn storage_image(self, binding: u32, stages: vk::ShaderStageFlags) -> Self {
+        self.binding(binding, vk::DescriptorType::STORAGE_IMAGE, 1, stages)
+    }
+
+    pub fn bindings(&self) -> &[DescriptorBinding] {
+        &self.bindings
+    }
+
+    /// The layout for these bindings from `vk_base`'s cache, created on first
+    /// use. It lives as long as `vk_base`.
+    pub fn build(&self, vk_base: &VkBase) -> Result<vk::DescriptorSetLayout> {
+        vk_base.descriptor_layouts.get(&self.bindings)
+    }
+}
+
+/// Creates one `vk::DescriptorSetLayout` per distinct set of bindings. Owned
+/// by `VkBase`, which destroys the layouts along with the device.
+pub struct DescriptorLayoutCache {
+    device: ash::Device,
+    layouts: Mutex<HashMap<Vec<DescriptorBinding>, vk::DescriptorSetLayout>>,
+}
+
+impl DescriptorLayoutCache {
+    pub fn new(device: &ash::Device) -> DescriptorLayoutCache {
+        DescriptorLayoutCache {
+            device: device.clone(),
+            layouts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Vec<DescriptorBinding>, vk::DescriptorSetLayout>> {
+        self.layouts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The layout for `bindings` in any order, created on first use
+    pub fn get(&self, bindings: &[DescriptorBinding]) -> Result<vk::DescriptorSetLayout> {
+        let mut key = bindings.to_vec();
+        key.sort_by_key(|binding| binding.binding);
+
+        let mut layouts = self.lock();
+        if let Some(&layout) = layouts.get(&key) {
+            return Ok(layout);
+        }
+
+        let vk_bindings = key
+            .iter()
+            .map(|binding| vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding.binding)
+                .descriptor_type(binding.descriptor_type)
+                .descriptor_count(binding.count)
+                .stage_flags(binding.stages)
+                .build())
+            .collect::<Vec<_>>();
+        let layout = unsafe {
+            self.device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&vk_bindings),
+                    None,
+                )
+                .context("creating descriptor set layout")?
+        };
+        layouts.insert(key, layout);
+
+        Ok(layout)
+    }
+
+    /// Number of distinct layouts created so far
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Destroys every layout. Called by `VkBase` once the device is idle.
+    pub fn destroy(&self) {
+        for (_, layout) in self.lock().drain() {
+            unsafe {
+                self.device.destroy_descriptor_set_layout(layout, None);
+            }
+        }
+    }
+}
+
+struct Pool {
+    handle: vk::DescriptorPool,
+    sets: u32,
+}
+
+/// Allocates descriptor sets from a growing list of pools. A new pool is
+/// created when the current ones are full, and `reset` frees every set at
+/// once while keeping the pools for reuse.
+pub struct DescriptorAllocator {
+    sets_per_pool: u32,
+    ratios: Vec<(vk::DescriptorType, f32)>,
+    pools: Vec<Pool>,
+    /// Index of the pool sets are allocated from
+    current: usize,
+    // Held so the device outlives the pools
+    vk_base: Arc<VkBase>,
+}
+
+impl DescriptorAllocator {
+    /// Pools hold `sets_per_pool` sets with descriptors in the proportions of
+    /// `DEFAULT_POOL_RATIOS`
+    pub fn new(vk_base: &Arc<VkBase>, sets_per_pool: u32) -> DescriptorAllocator {
+        DescriptorAllocator::with_ratios(vk_base, sets_per_pool, DEFAULT_POOL_RATIOS)
+    }
+
+    /// `ratios` gives the number of descriptors of each type per set
+    pub fn with_ratios(vk_base: &Arc<VkBase>,
+                       sets_per_pool: u32,
+                       ratios: &[(vk::DescriptorType, f32)],
+    ) -> DescriptorAllocator {
+        assert!(sets_per_pool > 0, "descriptor pools need room for a set");
+
+        DescriptorAllocator {
+            sets_per_pool,
+            ratios: ratios.to_vec(),
+            pools: Vec::new(),
+            current: 0,
+            vk_base: vk_base.clone(),
+        }
+    }
+
+    pub fn allocate(&mut self, layout: vk::DescriptorSetLayout) -> Result<vk::DescriptorSet> {
+        let device = &self.vk_base.device.handle;
+
+        loop {
+            if self.current == self.pools.len() {
+                let pool = self.create_pool()?;
+                self.pools.push(Pool { handle: pool, sets: 0 });
+            }
+
+            let pool = &mut self.pools[self.current];
+            if pool.sets < self.sets_per_pool {
+                let allocated = unsafe {
+                    device.allocate_descriptor_sets(
+                        &vk::DescriptorSetAllocateInfo::builder()
+                            .descriptor_pool(pool.handle)
+                            .set_layouts(&[layout]),
+                    )
+                };
+                match allocated {
+                    Ok(sets) => {
+                        pool.sets += 1;
+                        return Ok(sets[0]);
+                    }
+                    // Out of descriptors rather than sets, a fresh pool may fit it
+                    Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) if pool.sets > 0 => {}
+                    Err(e) => return Err(e).context("allocating descriptor set"),
+                }
+            }
+            self.current += 1;
+        }
+    }
+
+    /// Frees every set allocated so far. None of them may still be in use by
+    /// the GPU.
+    pub fn reset(&mut self) -> Result<()> {
+        for pool in &mut self.pools {
+            unsafe {
+                self.vk_base
+                    .device
+                    .handle
+                    .reset_descriptor_pool(pool.handle, vk::DescriptorPoolResetFlags::empty())
+                    .context("resetting descriptor pool")?;
+            }
+            pool.sets = 0;
+        }
+        self.current = 0;
+
+        Ok(())
+    }
+
+    /// Number of pools created so far
+    pub fn pool_count(&self) -> usize {
+        self.pools.len()
+    }
+
+    fn create_pool(&self) -> Result<vk::DescriptorPool> {
+        let pool_sizes = self
+            .ratios
+            .iter()
+            .map(|&(ty, ratio)| vk::DescriptorPoolSize {
+                ty,
+                descriptor_count: ((ratio * self.sets_per_pool as f32).ceil() as u32).max(1),
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            self.vk_base
+                .device
+                .handle
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .max_sets(self.sets_per_pool)
+                        .pool_sizes(&pool_sizes),
+                    None,
+                )
+                .context("creating descriptor pool")
+        }
+    }
+}
+
+impl Drop for DescriptorAllocator {
+    fn drop(&mut self) {
+        for pool in &self.pools {
+            unsafe {
+                self.vk_base.device.handle.destroy_descriptor_pool(pool.handle, None);
+            }
+        }
+    }
+}
+
+enum DescriptorInfo {
+    Buffer(vk::DescriptorBufferInfo),
+    Image(vk::DescriptorImageInfo),
+}
+
+struct Write {
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    info: DescriptorInfo,
+}
+
+/// Collects descriptor writes and applies them to sets with `write`
+#[derive(Default)]
+pub struct DescriptorWriter {
+    writes: Vec<Write>,
+}
+
+impl DescriptorWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `range` may be `vk::WHOLE_SIZE`
+    pub fn uniform_buffer(self,
+                          binding: u32,
+                          buffer: &Buffer,
+                          offset: vk::DeviceSize,
+                          range: vk::DeviceSize,
+    ) -> Self {
+        self.buffer(binding, vk::DescriptorType::UNIFORM_BUFFER, buffer, offset, range)
+    }
+
+    /// `range` may be `vk::WHOLE_SIZE`
+    pub fn storage_buffer(self,
+                          binding: u32,
+                          buffer: &Buffer,
+                          offset: vk::DeviceSize,
+                          range: vk::DeviceSize,
+    ) -> Self {
+        self.buffer(binding, vk::DescriptorType::STORAGE_BUFFER, buffer, offset, range)
+    }
+
+    pub fn combined_image_sampler(self,
+                                  binding: u32,
+                                  view: vk::ImageView,
+                                  sampler: vk::Sampler,
+                                  layout: vk::ImageLayout,
+    ) -> Self {
+        self.image(binding, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, view, sampler, layout)
+    }
+
+    /// A combined image sampler reading all of `texture`'s mip levels
+    pub fn texture(self, binding: u32, texture: &Texture, sampler: vk::Sampler) -> Self {
+        self.combined_image_sampler(binding, texture.view, sampler, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    /// `layout` is usually `GENERAL`
+    pub fn storage_image(self, binding: u32, view: vk::ImageView, layout: vk::ImageLayout) -> Self {
+        self.image(binding, vk::DescriptorType::STORAGE_IMAGE, view, vk::Sampler::null(), layout)
+    }
+
+    /// Applies every collected write to `set`
+    pub fn write(&self, vk_base: &VkBase, set: vk::DescriptorSet) {
+        let writes = self
+            .writes
+            .iter()
+            .map(|write| {
+                let builder = vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(write.binding)
+                    .descriptor_type(write.descriptor_type);
+                match &write.info {
+                    DescriptorInfo::Buffer(info) => builder.buffer_info(slice::from_ref(info)).build(),
+                    DescriptorInfo::Image(info) => builder.image_info(slice::from_ref(info)).build(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            vk_base.device.handle.update_descriptor_sets(&writes, &[]);
+        }
+    }
+
+    fn buffer(mut self,
+              binding: u32,
+              descriptor_type: vk::DescriptorType,
+              buffer: &Buffer,
+              offset: vk::DeviceSize,
+              range: vk::DeviceSize,
+    ) -> Self {
+        self.writes.push(Write {
+            binding,
+            descriptor_type,
+            info: DescriptorInfo::Buffer(vk::DescriptorBufferInfo { buffer: buffer.handle, offset, range }),
+        });
+        self
+    }
+
+    fn image(mut self,
+             binding: u32,
+             descriptor_type: vk::DescriptorType,
+             view: vk::ImageView,
+             sampler: vk::Sampler,
+             layout: vk::ImageLayout,
+    ) -> Self {
+        self.writes.push(Write {
+            binding,
+            descriptor_type,
+            info: DescriptorInfo::Image(vk::DescriptorImageInfo { sampler, image_view: view, image_layout: layout }),
+        });
+        self
+    }
+}
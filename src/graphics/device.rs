@@ -1,15 +1,22 @@
 use ash::{vk::{self, Handle}};
-use std::sync::{Arc};
+use std::sync::{Arc, Mutex, MutexGuard};
 
-use crate::graphics::{
-    physical_device::PhysicalDevice,
-    vk_instance::VkInstance
+use crate::{
+    error::{Context, Error, Result},
+    graphics::{
+        mesh::{DrawRange, Mesh},
+        physical_device::PhysicalDevice,
+        vk_instance::VkInstance
+    }
 };
 
 pub struct Device {
     pub handle: ash::Device,
     pub queue: ash::vk::Queue,
-    pub queue_family_index: u32
+    pub queue_family_index: u32,
+    /// `queue` is shared by the renderer, uploads from any thread and the
+    /// OpenXR runtime, and each use of it has to be externally synchronized
+    queue_lock: Mutex<()>,
 }
 
 impl Device {
@@ -17,23 +24,9 @@ impl Device {
                vk_instance: &Arc<VkInstance>,
                physical_device: &Arc<PhysicalDevice>,
                system_id: openxr::SystemId,
-    ) -> Arc<Device> {
+    ) -> Result<Arc<Device>> {
         unsafe {
-            let entry = ash::Entry::load().unwrap();
-
-            let queue_family_index = vk_instance
-                .handle
-                .get_physical_device_queue_family_properties(physical_device.handle)
-                .into_iter()
-                .enumerate()
-                .find_map(|(queue_family_index, info)| {
-                    if info.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-                        Some(queue_family_index as u32)
-                    } else {
-                        None
-                    }
-                })
-                .unwrap();
+            let queue_family_index = Self::graphics_queue_family_index(vk_instance, physical_device)?;
 
             let handle = {
                 let device_queue_create_info = [vk::DeviceQueueCreateInfo::builder()
@@ -53,28 +46,86 @@ impl Device {
                 let device = xr_instance
                     .create_vulkan_device(
                         system_id,
-                        std::mem::transmute(entry.static_fn().get_instance_proc_addr),
+                        std::mem::transmute(vk_instance.entry.static_fn().get_instance_proc_addr),
                         physical_device.handle.as_raw() as _,
                         &device_create_info as *const _ as *const _,
                     )
-                    .expect("OpenXR error creating Vulkan device")
+                    .context("creating Vulkan device")?
                     .map_err(vk::Result::from_raw)
-                    .expect("Vulkan error creating Vulkan device");
+                    .context("creating Vulkan device")?;
 
                 ash::Device::load(vk_instance.handle.fp_v1_0(), vk::Device::from_raw(device as _))
             };
 
             let queue = handle.get_device_queue(queue_family_index, 0);
 
-            Arc::new(Device {
+            Ok(Arc::new(Device {
                 handle,
                 queue,
-                queue_family_index
-            })
+                queue_family_index,
+                queue_lock: Mutex::new(()),
+            }))
+        }
+    }
+
+    /// Creates the device directly through ash, without an OpenXR runtime
+    pub fn headless(vk_instance: &Arc<VkInstance>,
+                    physical_device: &Arc<PhysicalDevice>,
+    ) -> Result<Arc<Device>> {
+        unsafe {
+            let queue_family_index = Self::graphics_queue_family_index(vk_instance, physical_device)?;
+
+            let device_queue_create_info = [vk::DeviceQueueCreateInfo::builder()
+                .queue_family_index(queue_family_index)
+                .queue_priorities(&[1.0])
+                .build()];
+
+            let mut multiview_features = vk::PhysicalDeviceMultiviewFeatures {
+                multiview: vk::TRUE,
+                ..Default::default()
+            };
+
+            let device_create_info = vk::DeviceCreateInfo::builder()
+                .queue_create_infos(&device_queue_create_info)
+                .push_next(&mut multiview_features);
+
+            let handle = vk_instance
+                .handle
+                .create_device(physical_device.handle, &device_create_info, None)
+                .context("creating Vulkan device")?;
+
+            let queue = handle.get_device_queue(queue_family_index, 0);
+
+            Ok(Arc::new(Device {
+                handle,
+                queue,
+                queue_family_index,
+                queue_lock: Mutex::new(()),
+            }))
+        }
+    }
+
+    fn graphics_queue_family_index(vk_instance: &VkInstance,
+                                   physical_device: &PhysicalDevice
+    ) -> Result<u32> {
+        unsafe {
+            vk_instance
+                .handle
+                .get_physical_device_queue_family_properties(physical_device.handle)
+                .into_iter()
+                .enumerate()
+                .find_map(|(queue_family_index, info)| {
+                    if info.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
+                        Some(queue_family_index as u32)
+                    } else {
+                        None
+                    }
+                })
+                .ok_or(Error::NoGraphicsQueue)
         }
     }
 
-    pub fn begin_command_buffer(&self, cmd_buffer: ash::vk::CommandBuffer) {
+    pub fn begin_command_buffer(&self, cmd_buffer: ash::vk::CommandBuffer) -> Result<()> {
         unsafe {
             self.handle
                 .begin_command_buffer(
@@ -82,15 +133,15 @@ impl Device {
                     &vk::CommandBufferBeginInfo::builder()
                         .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
                 )
-                .unwrap()
+                .context("beginning command buffer")
         }
     }
 
-    pub fn end_command_buffer(&self, cmd_buffer: ash::vk::CommandBuffer) {
+    pub fn end_command_buffer(&self, cmd_buffer: ash::vk::CommandBuffer) -> Result<()> {
         unsafe {
             self.handle
                 .end_command_buffer(cmd_buffer)
-                .unwrap()
+                .context("ending command buffer")
         }
     }
 
@@ -98,7 +149,8 @@ impl Device {
                              cmd_buffer: ash::vk::CommandBuffer,
                              render_pass: ash::vk::RenderPass,
                              framebuffer: ash::vk::Framebuffer,
-                             extent: ash::vk::Extent2D
+                             extent: ash::vk::Extent2D,
+                             clear_depth: f32
     ) {
         unsafe {
             self.handle.cmd_begin_render_pass(
@@ -110,11 +162,19 @@ impl Device {
                         offset: vk::Offset2D::default(),
                         extent: extent,
                     })
-                    .clear_values(&[vk::ClearValue {
-                        color: vk::ClearColorValue {
-                            float32: [0.0, 0.0, 0.0, 1.0],
+                    .clear_values(&[
+                        vk::ClearValue {
+                            color: vk::ClearColorValue {
+                                float32: [0.0, 0.0, 0.0, 1.0],
+                            },
+                        },
+                        vk::ClearValue {
+                            depth_stencil: vk::ClearDepthStencilValue {
+                                depth: clear_depth,
+                                stencil: 0,
+                            },
                         },
-                    }]),
+                    ]),
                 vk::SubpassContents::INLINE,
             );
         }
@@ -124,17 +184,17 @@ impl Device {
         unsafe { self.handle.cmd_end_render_pass(cmd_buffer); }
     }
 
-    pub fn wait_for_fences(&self, fences: &Vec<ash::vk::Fence>, timeout: u64) {
+    pub fn wait_for_fences(&self, fences: &Vec<ash::vk::Fence>, timeout: u64) -> Result<()> {
         unsafe {
             self.handle
                 .wait_for_fences(fences, true, timeout)
-                .unwrap();
+                .context("waiting for fences")
         }
     }
 
-    pub fn reset_fences(&self, fences: ash::vk::Fence) {
+    pub fn reset_fences(&self, fences: ash::vk::Fence) -> Result<()> {
         unsafe {
-            self.handle.reset_fences(&[fences]).unwrap();
+            self.handle.reset_fences(&[fences]).context("resetting fences")
         }
     }
 
@@ -175,10 +235,69 @@ impl Device {
         }
     }
 
+    pub fn cmd_push_constants<T: Copy>(&self,
+                                       cmd_buffer: ash::vk::CommandBuffer,
+                                       pipeline_layout: ash::vk::PipelineLayout,
+                                       stages: ash::vk::ShaderStageFlags,
+                                       constants: &T
+    ) {
+        unsafe {
+            let bytes = std::slice::from_raw_parts(constants as *const T as *const u8, std::mem::size_of::<T>());
+            self.handle.cmd_push_constants(cmd_buffer, pipeline_layout, stages, 0, bytes);
+        }
+    }
+
+    pub fn cmd_bind_mesh(&self, cmd_buffer: ash::vk::CommandBuffer, mesh: &Mesh) {
+        unsafe {
+            self.handle.cmd_bind_vertex_buffers(cmd_buffer, mesh.binding.binding, &[mesh.vertex_buffer.handle], &[0]);
+            if let Some(index_buffer) = &mesh.index_buffer {
+                self.handle.cmd_bind_index_buffer(cmd_buffer, index_buffer.handle, 0, mesh.index_type);
+            }
+        }
+    }
+
+    /// Draws one range of a mesh already bound with `cmd_bind_mesh`
+    pub fn cmd_draw_mesh_range(&self,
+                               cmd_buffer: ash::vk::CommandBuffer,
+                               mesh: &Mesh,
+                               range: DrawRange,
+                               instance_count: u32
+    ) {
+        unsafe {
+            if mesh.is_indexed() {
+                self.handle.cmd_draw_indexed(cmd_buffer,
+                                             range.count,
+                                             instance_count,
+                                             range.first,
+                                             range.vertex_offset,
+                                             0
+                );
+            } else {
+                self.handle.cmd_draw(cmd_buffer, range.count, instance_count, range.first, 0);
+            }
+        }
+    }
+
+    /// Binds a mesh and draws all of its ranges
+    pub fn cmd_draw_mesh(&self, cmd_buffer: ash::vk::CommandBuffer, mesh: &Mesh) {
+        self.cmd_bind_mesh(cmd_buffer, mesh);
+        for range in &mesh.ranges {
+            self.cmd_draw_mesh_range(cmd_buffer, mesh, *range, 1);
+        }
+    }
+
+    /// Held while using `queue` directly, and around the OpenXR calls that
+    /// use it: beginning and ending frames and acquiring, waiting on and
+    /// releasing swapchain images
+    pub fn lock_queue(&self) -> MutexGuard<'_, ()> {
+        self.queue_lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
     pub fn queue_submit(&self,
                         cmd_buffer: ash::vk::CommandBuffer,
                         fence: ash::vk::Fence
-    ) {
+    ) -> Result<()> {
+        let _queue = self.lock_queue();
         unsafe {
             self.handle
                 .queue_submit(
@@ -186,12 +305,13 @@ impl Device {
                     &[vk::SubmitInfo::builder().command_buffers(&[cmd_buffer]).build()],
                     fence
                 )
-                .unwrap()
+                .context("submitting to queue")
         }
     }
 
-    pub fn device_wait_idle(&self) {
-        unsafe { self.handle.device_wait_idle().unwrap(); }
+    pub fn device_wait_idle(&self) -> Result<()> {
+        let _queue = self.lock_queue();
+        unsafe { self.handle.device_wait_idle().context("waiting for device idle") }
     }
 
     pub fn destroy_fences(&self, fences: &Vec<ash::vk::Fence>) {
@@ -1,18 +1,20 @@
 use ash::{vk::{self}};
 use std::sync::{Arc};
 
-use crate::graphics::{
-    device::Device
+use crate::{
+    error::{Context, Result},
+    graphics::{
+        device::Device,
+        PIPELINE_DEPTH
+    }
 };
 
-const PIPELINE_DEPTH: u32 = 2;
-
 pub struct Fence {
     pub handle: Vec<ash::vk::Fence>
 }
 
 impl Fence {
-    pub fn new(device: &Arc<Device>) -> Arc<Fence> {
+    pub fn new(device: &Arc<Device>) -> Result<Arc<Fence>> {
         unsafe {
             let handle = (0..PIPELINE_DEPTH)
                 .map(|_| {
@@ -22,13 +24,13 @@ impl Fence {
                             &vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED),
                             None,
                         )
-                        .unwrap()
+                        .context("creating fence")
                 })
-                .collect::<Vec<_>>();
+                .collect::<Result<Vec<_>>>()?;
 
-            Arc::new(Fence {
+            Ok(Arc::new(Fence {
                 handle
-            })
+            }))
         }
     }
 }
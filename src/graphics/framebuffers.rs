@@ -1,11 +1,13 @@
 use std::sync::{Arc};
 
-use ash::{vk::{self, Handle}};
+use ash::{vk::{self}};
 
 use crate::{
+    error::{Context, Result},
     graphics::{
-        device::Device,
-        render_pass::RenderPass
+        memory::{Image, MemoryLocation},
+        render_pass::{RenderPass, COLOR_FORMAT},
+        vk_base::VkBase
     },
     xr::{swapchain::Swapchain}
 };
@@ -13,64 +15,198 @@ use crate::{
 pub struct Framebuffer {
     pub framebuffer: vk::Framebuffer,
     pub color: vk::ImageView,
+    /// Depth attachment with one layer per view. `None` if the image belongs
+    /// to the OpenXR depth swapchain.
+    pub depth_image: Option<Image>,
+    pub depth: vk::ImageView,
 }
 
 pub struct Framebuffers {
-    pub handle: Vec<Framebuffer>
+    /// Indexed by color image, then by depth swapchain image if there is one
+    pub handle: Vec<Framebuffer>,
+    depth_image_count: usize,
 }
 
-const COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
-const VIEW_COUNT: u32 = 2;
+impl Framebuffer {
+    /// Creates its own depth image unless `depth_image` comes from the depth
+    /// swapchain. Nothing is left behind on failure.
+    fn new(vk_base: &Arc<VkBase>,
+           swapchain: &Swapchain,
+           render_pass: &RenderPass,
+           color_image: vk::Image,
+           depth_image: Option<vk::Image>,
+    ) -> Result<Framebuffer> {
+        let (depth_image, owned_depth_image) = match depth_image {
+            Some(depth_image) => (depth_image, None),
+            None => {
+                let image = create_depth_image(vk_base, swapchain)?;
+                (image.handle, Some(image))
+            }
+        };
+
+        let color = create_view(vk_base, swapchain, color_image, COLOR_FORMAT, vk::ImageAspectFlags::COLOR)?;
+        let depth = match create_view(vk_base,
+                                      swapchain,
+                                      depth_image,
+                                      vk_base.depth_format,
+                                      depth_aspect_mask(vk_base.depth_format)
+        ) {
+            Ok(depth) => depth,
+            Err(e) => {
+                vk_base.device.destroy_image_view(color);
+                return Err(e);
+            }
+        };
+        match create_framebuffer(vk_base, swapchain, render_pass, color, depth) {
+            Ok(framebuffer) => Ok(Framebuffer {
+                framebuffer,
+                color,
+                depth_image: owned_depth_image,
+                depth,
+            }),
+            Err(e) => {
+                vk_base.device.destroy_image_view(depth);
+                vk_base.device.destroy_image_view(color);
+                Err(e)
+            }
+        }
+    }
+
+    /// Destroys the framebuffer and its views. An owned depth image is freed
+    /// when `self` is dropped.
+    pub fn destroy(&self, vk_base: &VkBase) {
+        vk_base.device.destroy_framebuffer(self.framebuffer);
+        vk_base.device.destroy_image_view(self.color);
+        vk_base.device.destroy_image_view(self.depth);
+    }
+}
+
+/// Aspects an attachment view of a depth format has to cover
+pub fn depth_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::DEPTH,
+    }
+}
 
 impl Framebuffers {
+    /// Renders into the depth swapchain if `swapchain` has one. Its images may
+    /// be acquired out of step with the color images, so there is a
+    /// framebuffer for every pair of them.
     pub fn new(swapchain: &Swapchain,
-               device: &Device,
+               vk_base: &Arc<VkBase>,
                render_pass: &RenderPass,
-    ) -> Arc<Framebuffers> {
-        let images = swapchain.handle.enumerate_images().unwrap();
-
-        let handle = images
-            .into_iter()
-            .map(|color_image| {
-                let color_image = vk::Image::from_raw(color_image);
-                unsafe {
-                    let color = device
-                        .handle
-                        .create_image_view(
-                            &vk::ImageViewCreateInfo::builder()
-                                .image(color_image)
-                                .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
-                                .format(COLOR_FORMAT)
-                                .subresource_range(vk::ImageSubresourceRange {
-                                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                                    base_mip_level: 0,
-                                    level_count: 1,
-                                    base_array_layer: 0,
-                                    layer_count: VIEW_COUNT,
-                                }),
-                            None,
-                        )
-                        .unwrap();
-
-                    let framebuffer = device
-                        .handle
-                        .create_framebuffer(
-                            &vk::FramebufferCreateInfo::builder()
-                                .render_pass(render_pass.handle)
-                                .width(swapchain.resolution.width)
-                                .height(swapchain.resolution.height)
-                                .attachments(&[color])
-                                .layers(1),
-                            None,
-                        )
-                        .unwrap();
-                    Framebuffer { framebuffer, color }
+    ) -> Result<Arc<Framebuffers>> {
+        let images = swapchain.enumerate_images()?;
+        let depth_images = swapchain.enumerate_depth_images()?;
+        let depth_image_count = depth_images.as_ref().map_or(1, |images| images.len());
+
+        let pairs = images.iter().flat_map(|&color_image| match &depth_images {
+            Some(depth_images) => depth_images.iter().map(|&depth_image| (color_image, Some(depth_image))).collect(),
+            None => vec![(color_image, None)],
+        });
+
+        let mut handle = Vec::new();
+        for (color_image, depth_image) in pairs {
+            match Framebuffer::new(vk_base, swapchain, render_pass, color_image, depth_image) {
+                Ok(framebuffer) => handle.push(framebuffer),
+                Err(e) => {
+                    for framebuffer in &handle {
+                        framebuffer.destroy(vk_base);
+                    }
+                    return Err(e);
                 }
+            }
+        }
+
+        Ok(Arc::new(Framebuffers{
+            handle,
+            depth_image_count,
+        }))
+    }
+
+    /// Framebuffer for the images most recently acquired from `swapchain`
+    pub fn current(&self, swapchain: &Swapchain) -> Option<&Framebuffer> {
+        let color = swapchain.current_image? as usize;
+        let depth = match &swapchain.depth {
+            Some(depth) => depth.current_image? as usize,
+            None => 0,
+        };
+        self.handle.get(color * self.depth_image_count + depth)
+    }
+}
+
+fn create_view(vk_base: &VkBase,
+               swapchain: &Swapchain,
+               image: vk::Image,
+               format: vk::Format,
+               aspect_mask: vk::ImageAspectFlags,
+) -> Result<vk::ImageView> {
+    unsafe {
+        vk_base.device
+            .handle
+            .create_image_view(
+                &vk::ImageViewCreateInfo::builder()
+                    .image(image)
+                    .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+                    .format(format)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask,
+                        base_mip_level: 0,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: swapchain.view_count,
+                    }),
+                None,
+            )
+            .context("creating framebuffer image view")
+    }
+}
+
+fn create_depth_image(vk_base: &Arc<VkBase>, swapchain: &Swapchain) -> Result<Image> {
+    Image::new(
+        vk_base,
+        &vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(vk_base.depth_format)
+            .extent(vk::Extent3D {
+                width: swapchain.resolution.width,
+                height: swapchain.resolution.height,
+                depth: 1,
             })
-            .collect();
+            .mip_levels(1)
+            .array_layers(swapchain.view_count)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED),
+        MemoryLocation::DeviceLocal,
+    )
+}
 
-        Arc::new(Framebuffers{
-            handle
-        })
+fn create_framebuffer(vk_base: &VkBase,
+                      swapchain: &Swapchain,
+                      render_pass: &RenderPass,
+                      color: vk::ImageView,
+                      depth: vk::ImageView,
+) -> Result<vk::Framebuffer> {
+    unsafe {
+        vk_base.device
+            .handle
+            .create_framebuffer(
+                &vk::FramebufferCreateInfo::builder()
+                    .render_pass(render_pass.handle)
+                    .width(swapchain.resolution.width)
+                    .height(swapchain.resolution.height)
+                    .attachments(&[color, depth])
+                    .layers(1),
+                None,
+            )
+            .context("creating framebuffer")
     }
 }
@@ -0,0 +1,260 @@
+use ash::{vk::{self}};
+use openxr as xr;
+use std::{
+    env,
+    fs,
+    path::{PathBuf},
+    sync::{Arc},
+};
+
+use crate::{
+    error::{Context, Result},
+    graphics::{
+        capture::{CaptureView, CapturedImage},
+        vk_base::VkBase
+    },
+    math::DepthRange,
+    Renderer,
+    xr::{
+        frame::{EyeView, FrameContext},
+        swapchain::Swapchain
+    }
+};
+
+/// Setting this environment variable overwrites reference images with the
+/// current output instead of comparing against them
+pub const UPDATE_ENV: &str = "XRRS_UPDATE_GOLDEN";
+
+/// Renders a `Renderer` offscreen and compares both eye layers, placed side by
+/// side, against a reference PNG.
+///
+/// A missing reference fails the test like a mismatch does. The output is
+/// written next to where a mismatch would put it, to be reviewed and committed,
+/// or run with `UPDATE_ENV` set to write the reference directly.
+pub struct GoldenTest {
+    pub name: String,
+    pub resolution: vk::Extent2D,
+    pub view_count: u32,
+    /// Frames drawn before the capture, so renderers that animate or fill
+    /// their frames in flight are compared in a steady state
+    pub frames: u32,
+    /// Views every frame is rendered from, one per array layer
+    pub views: Vec<xr::View>,
+    pub depth: DepthRange,
+    /// Largest per-channel difference still counted as a match
+    pub tolerance: u8,
+    /// Number of mismatched pixels still accepted, for rasterization
+    /// differences between drivers along edges
+    pub max_mismatched_pixels: usize,
+    pub reference_dir: PathBuf,
+    /// Where the actual output and diff image are written on failure
+    pub output_dir: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum GoldenOutcome {
+    Matched,
+    /// The reference was (re)written from the current output because
+    /// `UPDATE_ENV` is set
+    Blessed(PathBuf),
+    /// There is no reference to compare against
+    MissingReference {
+        reference_path: PathBuf,
+        actual_path: PathBuf,
+    },
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+        actual_path: PathBuf,
+    },
+    Mismatched {
+        mismatched_pixels: usize,
+        max_difference: u8,
+        actual_path: PathBuf,
+        diff_path: PathBuf,
+    },
+}
+
+pub struct ImageDiff {
+    pub mismatched_pixels: usize,
+    pub max_difference: u8,
+    /// Mismatched pixels in red, scaled by their difference, over a dimmed
+    /// grayscale copy of the reference
+    pub image: CapturedImage,
+}
+
+impl GoldenTest {
+    pub fn new(name: &str) -> Self {
+        GoldenTest {
+            name: name.to_owned(),
+            resolution: vk::Extent2D { width: 128, height: 128 },
+            view_count: 2,
+            frames: 3,
+            views: fixed_views(2),
+            depth: DepthRange::default(),
+            tolerance: 2,
+            max_mismatched_pixels: 0,
+            reference_dir: PathBuf::from("tests/golden"),
+            output_dir: PathBuf::from("target/golden"),
+        }
+    }
+
+    pub fn reference_path(&self) -> PathBuf {
+        self.reference_dir.join(format!("{}.png", self.name))
+    }
+
+    /// Renders `self.frames` frames with a new `R` and captures the last one
+    pub fn render<R: Renderer>(&self, vk_base: &Arc<VkBase>) -> Result<CapturedImage> {
+        let mut swapchain = Swapchain::offscreen(vk_base, self.resolution, self.view_count)?;
+        let mut renderer = R::new(vk_base.clone(), &swapchain)?;
+
+        let frame = FrameContext {
+            views: self.views.iter().map(|view| EyeView::new(view, self.depth)).collect(),
+            depth: self.depth,
+            ..Default::default()
+        };
+        for _ in 0..self.frames {
+            renderer.draw(&mut swapchain, &frame)?;
+        }
+        vk_base.device.device_wait_idle()?;
+
+        swapchain.capture(vk_base, CaptureView::SideBySide)
+    }
+
+    pub fn run<R: Renderer>(&self, vk_base: &Arc<VkBase>) -> Result<GoldenOutcome> {
+        let actual = self.render::<R>(vk_base)?;
+        self.check(&actual)
+    }
+
+    /// Compares an already captured image against the reference
+    pub fn check(&self, actual: &CapturedImage) -> Result<GoldenOutcome> {
+        let reference_path = self.reference_path();
+        if env::var_os(UPDATE_ENV).is_some() {
+            fs::create_dir_all(&self.reference_dir).context("creating reference directory")?;
+            actual.save_png(&reference_path)?;
+            return Ok(GoldenOutcome::Blessed(reference_path));
+        }
+        if !reference_path.exists() {
+            return Ok(GoldenOutcome::MissingReference {
+                reference_path,
+                actual_path: self.write_output("actual", actual)?,
+            });
+        }
+
+        let reference = CapturedImage::load_png(&reference_path)?;
+
+        if (reference.width, reference.height) != (actual.width, actual.height) {
+            let actual_path = self.write_output("actual", actual)?;
+            return Ok(GoldenOutcome::SizeMismatch {
+                expected: (reference.width, reference.height),
+                actual: (actual.width, actual.height),
+                actual_path,
+            });
+        }
+
+        let diff = compare(&reference, actual, self.tolerance);
+        if diff.mismatched_pixels <= self.max_mismatched_pixels {
+            return Ok(GoldenOutcome::Matched);
+        }
+
+        Ok(GoldenOutcome::Mismatched {
+            mismatched_pixels: diff.mismatched_pixels,
+            max_difference: diff.max_difference,
+            actual_path: self.write_output("actual", actual)?,
+            diff_path: self.write_output("diff", &diff.image)?,
+        })
+    }
+
+    fn write_output(&self, suffix: &str, image: &CapturedImage) -> Result<PathBuf> {
+        fs::create_dir_all(&self.output_dir).context("creating golden output directory")?;
+        let path = self.output_dir.join(format!("{}.{}.png", self.name, suffix));
+        image.save_png(&path)?;
+        Ok(path)
+    }
+}
+
+impl GoldenOutcome {
+    /// Panics with a description of the mismatch, for use in tests
+    pub fn assert_matched(&self) {
+        match self {
+            GoldenOutcome::Matched => {}
+            GoldenOutcome::Blessed(path) => println!("Wrote reference image {}", path.display()),
+            GoldenOutcome::MissingReference { reference_path, actual_path } => panic!(
+                "missing golden image {}: review {} and copy it there, or rerun with {}=1",
+                reference_path.display(), actual_path.display(), UPDATE_ENV
+            ),
+            GoldenOutcome::SizeMismatch { expected, actual, actual_path } => panic!(
+                "golden image size mismatch: expected {}x{}, got {}x{} (written to {})",
+                expected.0, expected.1, actual.0, actual.1, actual_path.display()
+            ),
+            GoldenOutcome::Mismatched { mismatched_pixels, max_difference, actual_path, diff_path } => panic!(
+                "golden image mismatch: {} pixels differ by up to {} (actual {}, diff {})",
+                mismatched_pixels, max_difference, actual_path.display(), diff_path.display()
+            ),
+        }
+    }
+}
+
+/// Eyes 64mm apart at a standing height of 1.6m, looking down -Z with a 90°
+/// field of view
+pub fn fixed_views(view_count: u32) -> Vec<xr::View> {
+    (0..view_count)
+        .map(|i| {
+            let x = match (view_count, i) {
+                (1, _) => 0.0,
+                (_, 0) => -0.032,
+                _ => 0.032,
+            };
+            xr::View {
+                pose: xr::Posef {
+                    orientation: xr::Quaternionf::IDENTITY,
+                    position: xr::Vector3f { x, y: 1.6, z: 0.0 },
+                },
+                fov: xr::Fovf {
+                    angle_left: -0.785,
+                    angle_right: 0.785,
+                    angle_up: 0.785,
+                    angle_down: -0.785,
+                },
+            }
+        })
+        .collect()
+}
+
+/// Compares two images of the same size pixel by pixel. A pixel mismatches if
+/// any channel differs by more than `tolerance`.
+pub fn compare(reference: &CapturedImage, actual: &CapturedImage, tolerance: u8) -> ImageDiff {
+    assert_eq!((reference.width, reference.height), (actual.width, actual.height));
+
+    let mut mismatched_pixels = 0;
+    let mut max_difference = 0;
+    let mut pixels = Vec::with_capacity(reference.pixels.len());
+
+    for (expected, actual) in reference.pixels.chunks_exact(4).zip(actual.pixels.chunks_exact(4)) {
+        let difference = expected
+            .iter()
+            .zip(actual)
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+
+        if difference > tolerance {
+            mismatched_pixels += 1;
+            pixels.extend_from_slice(&[128u8.saturating_add(difference / 2), 0, 0, 255]);
+        } else {
+            let luma = ((expected[0] as u32 + expected[1] as u32 + expected[2] as u32) / 12) as u8;
+            pixels.extend_from_slice(&[luma, luma, luma, 255]);
+        }
+    }
+
+    ImageDiff {
+        mismatched_pixels,
+        max_difference,
+        image: CapturedImage {
+            width: reference.width,
+            height: reference.height,
+            pixels,
+        },
+    }
+}
@@ -0,0 +1,225 @@
+//! Rebuilding pipelines when their shader files change during a session
+
+use ash::{vk::{self}};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::{Result},
+    graphics::{
+        pipeline::{GraphicsPipelineBuilder, Pipeline},
+        shader_module::{shader_kind, ShaderCompiler, ShaderModule},
+        vk_base::VkBase
+    }
+};
+
+/// How long a file has to go without changes before it's reported, so the
+/// partial writes of a shader compiler aren't picked up
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// Reports changes to shader files. Events arrive on a background thread and
+/// are collected by `changed`.
+pub struct ShaderWatcher {
+    watcher: RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    directories: HashSet<PathBuf>,
+    files: HashSet<PathBuf>,
+    pending: HashMap<PathBuf, Instant>,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> Result<ShaderWatcher> {
+        let (sender, events) = mpsc::channel();
+        let watcher = notify::recommended_watcher(sender)?;
+
+        Ok(ShaderWatcher {
+            watcher,
+            events,
+            directories: HashSet::new(),
+            files: HashSet::new(),
+            pending: HashMap::new(),
+        })
+    }
+
+    /// Reports changes to the file at `path`. Its directory is watched rather
+    /// than the file, since editors and compilers often replace files instead
+    /// of writing to them.
+    pub fn watch(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = normalize(path.as_ref());
+        if let Some(directory) = path.parent() {
+            if !self.directories.contains(directory) {
+                self.watcher.watch(directory, RecursiveMode::NonRecursive)?;
+                self.directories.insert(directory.to_owned());
+            }
+        }
+        self.files.insert(path);
+        Ok(())
+    }
+
+    /// Watched files that changed since the last call and have since settled,
+    /// as absolute paths. Meant to be called once per frame.
+    pub fn changed(&mut self) -> HashSet<PathBuf> {
+        let now = Instant::now();
+        for event in self.events.try_iter() {
+            match event {
+                Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                    for path in event.paths {
+                        if self.files.contains(&path) {
+                            self.pending.insert(path, now);
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => println!("Error watching shader files: {}", e),
+            }
+        }
+
+        let settled = self
+            .pending
+            .iter()
+            .filter(|&(_, &changed)| now.duration_since(changed) >= SETTLE_TIME)
+            .map(|(path, _)| path.clone())
+            .collect::<HashSet<_>>();
+        self.pending.retain(|path, _| !settled.contains(path));
+        settled
+    }
+}
+
+/// A pipeline built from shader files that can be rebuilt when they change.
+/// Derefs to the current `Pipeline`.
+pub struct ReloadablePipeline {
+    pipeline: Pipeline,
+    builder: GraphicsPipelineBuilder<'static>,
+    compiler: ShaderCompiler,
+    shaders: Vec<(vk::ShaderStageFlags, PathBuf)>,
+    /// Every file the current pipeline was built from, includes too
+    sources: Vec<PathBuf>,
+}
+
+impl ReloadablePipeline {
+    /// `builder` describes everything but the shader stages, which run the
+    /// `main` of the file given for each stage. SPIR-V files are loaded as
+    /// they are, GLSL and WGSL files named as described in `shader_kind` are
+    /// compiled with `compiler`.
+    pub fn new(vk_base: &Arc<VkBase>,
+               builder: GraphicsPipelineBuilder<'static>,
+               compiler: ShaderCompiler,
+               shaders: &[(vk::ShaderStageFlags, PathBuf)],
+    ) -> Result<ReloadablePipeline> {
+        let shaders = shaders
+            .iter()
+            .map(|(stage, path)| (*stage, normalize(path)))
+            .collect::<Vec<_>>();
+        let (pipeline, sources) = build(vk_base, &builder, &compiler, &shaders)?;
+
+        Ok(ReloadablePipeline {
+            pipeline,
+            builder,
+            compiler,
+            shaders,
+            sources,
+        })
+    }
+
+    /// The shader files and everything they include, as of the last
+    /// successful build
+    pub fn sources(&self) -> impl Iterator<Item = &Path> {
+        self.sources.iter().map(|path| path.as_path())
+    }
+
+    /// Watches every source of the pipeline. Call again after a reload to
+    /// pick up new includes.
+    pub fn watch(&self, watcher: &mut ShaderWatcher) -> Result<()> {
+        self.sources().try_for_each(|path| watcher.watch(path))
+    }
+
+    /// Rebuilds from the shader files as they are now. The current pipeline
+    /// is kept if loading, compiling or building fails.
+    pub fn reload(&mut self, vk_base: &Arc<VkBase>) -> Result<()> {
+        let (pipeline, sources) = build(vk_base, &self.builder, &self.compiler, &self.shaders)?;
+
+        // The current pipeline may still be used by submitted frames
+        vk_base.device.device_wait_idle()?;
+        self.pipeline = pipeline;
+        self.sources = sources;
+        Ok(())
+    }
+
+    /// Reloads if one of the sources is in `changed`, as returned by
+    /// `ShaderWatcher::changed`. Errors are printed rather than returned, so a
+    /// broken shader doesn't end the session. Returns whether the pipeline was
+    /// replaced.
+    pub fn reload_changed(&mut self, vk_base: &Arc<VkBase>, changed: &HashSet<PathBuf>) -> bool {
+        if !self.sources().any(|path| changed.contains(path)) {
+            return false;
+        }
+
+        match self.reload(vk_base) {
+            Ok(()) => {
+                for path in self.sources().filter(|path| changed.contains(*path)) {
+                    println!("Reloaded shader {}", path.display());
+                }
+                true
+            }
+            Err(e) => {
+                println!("Error reloading shaders, keeping the previous pipeline: {}", e);
+                false
+            }
+        }
+    }
+}
+
+impl Deref for ReloadablePipeline {
+    type Target = Pipeline;
+
+    fn deref(&self) -> &Pipeline {
+        &self.pipeline
+    }
+}
+
+/// The pipeline and every file read to build it
+fn build(vk_base: &Arc<VkBase>,
+         builder: &GraphicsPipelineBuilder<'static>,
+         compiler: &ShaderCompiler,
+         shaders: &[(vk::ShaderStageFlags, PathBuf)],
+) -> Result<(Pipeline, Vec<PathBuf>)> {
+    let mut sources = Vec::new();
+    let mut modules = Vec::new();
+    for (stage, path) in shaders {
+        let module = if shader_kind(path).is_some() {
+            let shader = compiler.compile_file(path)?;
+            sources.extend(shader.sources.iter().map(|source| normalize(source)));
+            ShaderModule::from_words(vk_base, &shader.spirv)?
+        } else {
+            sources.push(path.clone());
+            ShaderModule::from_file(vk_base, path)?
+        };
+        modules.push((*stage, module));
+    }
+
+    let pipeline = modules
+        .iter()
+        .fold(builder.clone(), |builder, (stage, module)| builder.shader(*stage, module))
+        .build(vk_base)?;
+    Ok((pipeline, sources))
+}
+
+/// Resolves the directory of `path`, which is how the watcher reports paths.
+/// The file itself doesn't need to exist.
+fn normalize(path: &Path) -> PathBuf {
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+
+    match (directory.canonicalize(), path.file_name()) {
+        (Ok(directory), Some(name)) => directory.join(name),
+        _ => path.to_owned(),
+    }
+}
@@ -0,0 +1,569 @@
+//! Device memory sub-allocation.
+//!
+//! Memory is allocated from the driver in large blocks, one pool of blocks per
+//! `MemoryLocation` and memory type, and handed out in pieces. Linear and
+//! non-linear resources that share a block are kept `bufferImageGranularity`
+//! apart. Host-visible blocks stay mapped for their whole lifetime.
+
+use ash::{vk::{self}};
+use std::{
+    fmt,
+    ptr,
+    slice,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    error::{Context, Error, Result},
+    graphics::{
+        physical_device::PhysicalDevice,
+        vk_base::VkBase
+    }
+};
+
+/// Size of the blocks requested from the driver
+pub const BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+/// Requests larger than this get a block of their own
+const DEDICATED_THRESHOLD: vk::DeviceSize = BLOCK_SIZE / 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryLocation {
+    /// Only accessible by the GPU
+    DeviceLocal,
+    /// Written by the CPU and read by the GPU, e.g. uniforms and staging
+    HostVisible,
+    /// Written by the GPU and read back by the CPU, cached where possible
+    Readback,
+}
+
+impl MemoryLocation {
+    fn required_flags(self) -> vk::MemoryPropertyFlags {
+        match self {
+            MemoryLocation::DeviceLocal => vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryLocation::HostVisible | MemoryLocation::Readback => {
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT
+            }
+        }
+    }
+
+    fn preferred_flags(self) -> vk::MemoryPropertyFlags {
+        match self {
+            MemoryLocation::Readback => vk::MemoryPropertyFlags::HOST_CACHED,
+            _ => vk::MemoryPropertyFlags::empty(),
+        }
+    }
+}
+
+/// Whether a resource is linear or non-linear in the sense of
+/// `bufferImageGranularity`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceKind {
+    /// Buffers and linearly tiled images
+    Linear,
+    /// Optimally tiled images
+    NonLinear,
+}
+
+/// A piece of a block, returned to the allocator with `Allocator::free`
+pub struct Allocation {
+    pub memory: vk::DeviceMemory,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+    pub location: MemoryLocation,
+    /// Null unless the memory is host visible
+    mapped: *mut u8,
+    pool: usize,
+    block: u64,
+}
+
+impl Allocation {
+    /// Host address of the allocation, if its memory is host visible
+    pub fn mapped_ptr(&self) -> Option<*mut u8> {
+        (!self.mapped.is_null()).then_some(self.mapped)
+    }
+}
+
+struct Suballocation {
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    kind: ResourceKind,
+}
+
+struct Block {
+    id: u64,
+    memory: vk::DeviceMemory,
+    size: vk::DeviceSize,
+    mapped: *mut u8,
+    dedicated: bool,
+    /// Sorted by offset
+    allocations: Vec<Suballocation>,
+}
+
+// The mapping is only dereferenced through allocations, which the caller
+// synchronises
+unsafe impl Send for Block {}
+
+impl Block {
+    /// Finds room for a suballocation, returning where to insert it into
+    /// `allocations` and its offset
+    fn place(&self,
+             size: vk::DeviceSize,
+             alignment: vk::DeviceSize,
+             kind: ResourceKind,
+             granularity: vk::DeviceSize,
+    ) -> Option<(usize, vk::DeviceSize)> {
+        for index in 0..=self.allocations.len() {
+            let previous = index.checked_sub(1).map(|i| &self.allocations[i]);
+            let next = self.allocations.get(index);
+
+            let start = previous.map_or(0, |previous| previous.offset + previous.size);
+            let end = next.map_or(self.size, |next| next.offset);
+
+            let mut offset = align_up(start, alignment);
+            if let Some(previous) = previous {
+                if previous.kind != kind
+                    && same_page(previous.offset + previous.size - 1, offset, granularity)
+                {
+                    offset = align_up(offset, granularity);
+                }
+            }
+            if let Some(next) = next {
+                if next.kind != kind && same_page(offset + size - 1, next.offset, granularity) {
+                    continue;
+                }
+            }
+            if offset + size <= end {
+                return Some((index, offset));
+            }
+        }
+        None
+    }
+
+    fn allocated_bytes(&self) -> vk::DeviceSize {
+        self.allocations.iter().map(|allocation| allocation.size).sum()
+    }
+}
+
+struct Pool {
+    location: MemoryLocation,
+    memory_type_index: u32,
+    blocks: Vec<Block>,
+}
+
+struct AllocatorState {
+    pools: Vec<Pool>,
+    next_block_id: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct PoolStats {
+    pub location: MemoryLocation,
+    pub memory_type_index: u32,
+    pub block_count: usize,
+    /// Blocks holding a single oversized allocation
+    pub dedicated_block_count: usize,
+    /// Memory allocated from the driver
+    pub block_bytes: vk::DeviceSize,
+    pub allocation_count: usize,
+    /// Memory handed out to resources
+    pub allocated_bytes: vk::DeviceSize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AllocatorStats {
+    pub pools: Vec<PoolStats>,
+}
+
+impl AllocatorStats {
+    pub fn allocation_count(&self) -> usize {
+        self.pools.iter().map(|pool| pool.allocation_count).sum()
+    }
+
+    pub fn allocated_bytes(&self) -> vk::DeviceSize {
+        self.pools.iter().map(|pool| pool.allocated_bytes).sum()
+    }
+
+    pub fn block_bytes(&self) -> vk::DeviceSize {
+        self.pools.iter().map(|pool| pool.block_bytes).sum()
+    }
+}
+
+impl fmt::Display for AllocatorStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} allocations, {} of {} bytes in use",
+            self.allocation_count(),
+            self.allocated_bytes(),
+            self.block_bytes()
+        )?;
+        for pool in &self.pools {
+            writeln!(
+                f,
+                "  {:?} (type {}): {} allocations, {} of {} bytes in {} blocks ({} dedicated)",
+                pool.location,
+                pool.memory_type_index,
+                pool.allocation_count,
+                pool.allocated_bytes,
+                pool.block_bytes,
+                pool.block_count,
+                pool.dedicated_block_count
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Owned by `VkBase`, which frees every block before destroying the device
+pub struct Allocator {
+    device: ash::Device,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    buffer_image_granularity: vk::DeviceSize,
+    state: Mutex<AllocatorState>,
+}
+
+impl Allocator {
+    pub fn new(device: &ash::Device, physical_device: &PhysicalDevice) -> Allocator {
+        Allocator {
+            device: device.clone(),
+            memory_properties: physical_device.memory_properties,
+            buffer_image_granularity: physical_device.properties.limits.buffer_image_granularity,
+            state: Mutex::new(AllocatorState {
+                pools: Vec::new(),
+                next_block_id: 0,
+            }),
+        }
+    }
+
+    fn find_memory_type_index(&self,
+                              memory_requirements: &vk::MemoryRequirements,
+                              location: MemoryLocation,
+    ) -> Option<u32> {
+        let memory_types = &self.memory_properties.memory_types
+            [..self.memory_properties.memory_type_count as usize];
+        let find = |flags: vk::MemoryPropertyFlags| {
+            memory_types
+                .iter()
+                .enumerate()
+                .position(|(index, memory_type)| {
+                    (1 << index) & memory_requirements.memory_type_bits != 0
+                        && memory_type.property_flags.contains(flags)
+                })
+                .map(|index| index as u32)
+        };
+
+        find(location.required_flags() | location.preferred_flags())
+            .or_else(|| find(location.required_flags()))
+    }
+
+    pub fn allocate(&self,
+                    memory_requirements: &vk::MemoryRequirements,
+                    location: MemoryLocation,
+                    kind: ResourceKind,
+    ) -> Result<Allocation> {
+        let memory_type_index = self
+            .find_memory_type_index(memory_requirements, location)
+            .ok_or(Error::NoSuitableMemoryType)?;
+        let size = memory_requirements.size;
+        let alignment = memory_requirements.alignment.max(1);
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let pool_index = match state
+            .pools
+            .iter()
+            .position(|pool| pool.location == location && pool.memory_type_index == memory_type_index)
+        {
+            Some(index) => index,
+            None => {
+                state.pools.push(Pool {
+                    location,
+                    memory_type_index,
+                    blocks: Vec::new(),
+                });
+                state.pools.len() - 1
+            }
+        };
+
+        let granularity = self.buffer_image_granularity;
+        if size <= DEDICATED_THRESHOLD {
+            for block in state.pools[pool_index].blocks.iter_mut().filter(|block| !block.dedicated) {
+                if let Some((index, offset)) = block.place(size, alignment, kind, granularity) {
+                    block.allocations.insert(index, Suballocation { offset, size, kind });
+                    return Ok(self.allocation(block, pool_index, location, offset, size));
+                }
+            }
+        }
+
+        let dedicated = size > DEDICATED_THRESHOLD;
+        let block_size = if dedicated { size } else { BLOCK_SIZE };
+        let memory = unsafe {
+            self.device
+                .allocate_memory(
+                    &vk::MemoryAllocateInfo::builder()
+                        .allocation_size(block_size)
+                        .memory_type_index(memory_type_index),
+                    None,
+                )
+                .context("allocating memory block")?
+        };
+        let mapped = if location == MemoryLocation::DeviceLocal {
+            ptr::null_mut()
+        } else {
+            let mapped = unsafe {
+                self.device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+            };
+            match mapped {
+                Ok(mapped) => mapped as *mut u8,
+                Err(result) => {
+                    unsafe { self.device.free_memory(memory, None) };
+                    return Err(Error::Vk { stage: "mapping memory block", result });
+                }
+            }
+        };
+
+        let id = state.next_block_id;
+        state.next_block_id += 1;
+        let mut block = Block {
+            id,
+            memory,
+            size: block_size,
+            mapped,
+            dedicated,
+            allocations: Vec::new(),
+        };
+        block.allocations.push(Suballocation { offset: 0, size, kind });
+        let allocation = self.allocation(&block, pool_index, location, 0, size);
+        state.pools[pool_index].blocks.push(block);
+
+        Ok(allocation)
+    }
+
+    fn allocation(&self,
+                  block: &Block,
+                  pool: usize,
+                  location: MemoryLocation,
+                  offset: vk::DeviceSize,
+                  size: vk::DeviceSize,
+    ) -> Allocation {
+        Allocation {
+            memory: block.memory,
+            offset,
+            size,
+            location,
+            mapped: if block.mapped.is_null() {
+                ptr::null_mut()
+            } else {
+                unsafe { block.mapped.add(offset as usize) }
+            },
+            pool,
+            block: block.id,
+        }
+    }
+
+    /// Returns `allocation` to its block. Empty blocks are released unless
+    /// they are the last one of their pool.
+    pub fn free(&self, allocation: &Allocation) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let pool = &mut state.pools[allocation.pool];
+        let block_index = match pool.blocks.iter().position(|block| block.id == allocation.block) {
+            Some(index) => index,
+            None => return,
+        };
+
+        let block = &mut pool.blocks[block_index];
+        block.allocations.retain(|suballocation| suballocation.offset != allocation.offset);
+
+        if block.allocations.is_empty() && (block.dedicated || pool.blocks.len() > 1) {
+            let block = pool.blocks.remove(block_index);
+            unsafe { self.device.free_memory(block.memory, None) };
+        }
+    }
+
+    pub fn stats(&self) -> AllocatorStats {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        AllocatorStats {
+            pools: state
+                .pools
+                .iter()
+                .map(|pool| PoolStats {
+                    location: pool.location,
+                    memory_type_index: pool.memory_type_index,
+                    block_count: pool.blocks.len(),
+                    dedicated_block_count: pool.blocks.iter().filter(|block| block.dedicated).count(),
+                    block_bytes: pool.blocks.iter().map(|block| block.size).sum(),
+                    allocation_count: pool.blocks.iter().map(|block| block.allocations.len()).sum(),
+                    allocated_bytes: pool.blocks.iter().map(Block::allocated_bytes).sum(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Frees every block, reporting allocations that are still alive
+    pub fn free_all(&self) {
+        let stats = self.stats();
+        if stats.allocation_count() > 0 {
+            println!("Freeing memory with live allocations:\n{}", stats);
+        }
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        for pool in state.pools.drain(..) {
+            for block in pool.blocks {
+                unsafe { self.device.free_memory(block.memory, None) };
+            }
+        }
+    }
+}
+
+fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    (value + alignment - 1) / alignment * alignment
+}
+
+fn same_page(a: vk::DeviceSize, b: vk::DeviceSize, page_size: vk::DeviceSize) -> bool {
+    a / page_size == b / page_size
+}
+
+/// A buffer bound to memory from the `VkBase` allocator
+pub struct Buffer {
+    pub handle: vk::Buffer,
+    pub size: vk::DeviceSize,
+    pub allocation: Allocation,
+    // Held so the device outlives the buffer
+    vk_base: Arc<VkBase>,
+}
+
+impl Buffer {
+    pub fn new(vk_base: &Arc<VkBase>,
+               size: vk::DeviceSize,
+               usage: vk::BufferUsageFlags,
+               location: MemoryLocation,
+    ) -> Result<Buffer> {
+        let device = &vk_base.device.handle;
+
+        unsafe {
+            let handle = device
+                .create_buffer(
+                    &vk::BufferCreateInfo::builder()
+                        .size(size)
+                        .usage(usage)
+                        .sharing_mode(vk::SharingMode::EXCLUSIVE),
+                    None,
+                )
+                .context("creating buffer")?;
+
+            let memory_requirements = device.get_buffer_memory_requirements(handle);
+            let allocation = match vk_base.allocator.allocate(&memory_requirements, location, ResourceKind::Linear) {
+                Ok(allocation) => allocation,
+                Err(e) => {
+                    device.destroy_buffer(handle, None);
+                    return Err(e);
+                }
+            };
+
+            // From here on dropping the buffer cleans up
+            let buffer = Buffer {
+                handle,
+                size,
+                allocation,
+                vk_base: vk_base.clone(),
+            };
+            device
+                .bind_buffer_memory(handle, buffer.allocation.memory, buffer.allocation.offset)
+                .context("binding buffer memory")?;
+
+            Ok(buffer)
+        }
+    }
+
+    /// Host view of the buffer, `None` for device-local memory
+    pub fn mapped(&self) -> Option<&[u8]> {
+        self.allocation
+            .mapped_ptr()
+            .map(|mapped| unsafe { slice::from_raw_parts(mapped, self.size as usize) })
+    }
+
+    /// Copies `data` into host-visible memory at byte `offset`. The GPU must
+    /// not be using that range.
+    pub fn write<T: Copy>(&self, offset: vk::DeviceSize, data: &[T]) -> Result<()> {
+        let mapped = self.allocation.mapped_ptr().ok_or(Error::MemoryNotMapped)?;
+        let len = std::mem::size_of_val(data);
+        assert!(offset as usize + len <= self.size as usize, "write past the end of a buffer");
+
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr() as *const u8, mapped.add(offset as usize), len);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.vk_base.device.handle.destroy_buffer(self.handle, None);
+        }
+        self.vk_base.allocator.free(&self.allocation);
+    }
+}
+
+/// An image bound to memory from the `VkBase` allocator
+pub struct Image {
+    pub handle: vk::Image,
+    pub format: vk::Format,
+    pub extent: vk::Extent3D,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub allocation: Allocation,
+    // Held so the device outlives the image
+    vk_base: Arc<VkBase>,
+}
+
+impl Image {
+    pub fn new(vk_base: &Arc<VkBase>,
+               info: &vk::ImageCreateInfo,
+               location: MemoryLocation,
+    ) -> Result<Image> {
+        let device = &vk_base.device.handle;
+        let kind = if info.tiling == vk::ImageTiling::LINEAR {
+            ResourceKind::Linear
+        } else {
+            ResourceKind::NonLinear
+        };
+
+        unsafe {
+            let handle = device.create_image(info, None).context("creating image")?;
+
+            let memory_requirements = device.get_image_memory_requirements(handle);
+            let allocation = match vk_base.allocator.allocate(&memory_requirements, location, kind) {
+                Ok(allocation) => allocation,
+                Err(e) => {
+                    device.destroy_image(handle, None);
+                    return Err(e);
+                }
+            };
+
+            let image = Image {
+                handle,
+                format: info.format,
+                extent: info.extent,
+                mip_levels: info.mip_levels,
+                array_layers: info.array_layers,
+                allocation,
+                vk_base: vk_base.clone(),
+            };
+            device
+                .bind_image_memory(handle, image.allocation.memory, image.allocation.offset)
+                .context("binding image memory")?;
+
+            Ok(image)
+        }
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe {
+            self.vk_base.device.handle.destroy_image(self.handle, None);
+        }
+        self.vk_base.allocator.free(&self.allocation);
+    }
+}
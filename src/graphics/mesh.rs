@@ -0,0 +1,154 @@
+//! Vertex and index buffers plus the vertex input state needed to draw them.
+
+use ash::{vk::{self}};
+use glam::{Vec2, Vec3, Vec4};
+use std::{mem};
+
+use crate::{
+    error::{Result},
+    graphics::{
+        memory::{Buffer},
+        upload::{UploadBatch}
+    }
+};
+
+pub use xrrs_derive::VertexLayout;
+
+/// Vertex attribute format of a field type
+pub trait VertexFormat {
+    const FORMAT: vk::Format;
+}
+
+macro_rules! vertex_format {
+    ($($ty:ty => $format:ident),* $(,)?) => {
+        $(impl VertexFormat for $ty {
+            const FORMAT: vk::Format = vk::Format::$format;
+        })*
+    };
+}
+
+vertex_format! {
+    f32 => R32_SFLOAT,
+    [f32; 2] => R32G32_SFLOAT,
+    [f32; 3] => R32G32B32_SFLOAT,
+    [f32; 4] => R32G32B32A32_SFLOAT,
+    Vec2 => R32G32_SFLOAT,
+    Vec3 => R32G32B32_SFLOAT,
+    Vec4 => R32G32B32A32_SFLOAT,
+    u32 => R32_UINT,
+    [u32; 2] => R32G32_UINT,
+    [u32; 3] => R32G32B32_UINT,
+    [u32; 4] => R32G32B32A32_UINT,
+    i32 => R32_SINT,
+    [i32; 2] => R32G32_SINT,
+    [i32; 3] => R32G32B32_SINT,
+    [i32; 4] => R32G32B32A32_SINT,
+    [u16; 2] => R16G16_UINT,
+    [u16; 4] => R16G16B16A16_UINT,
+    [u8; 4] => R8G8B8A8_UINT,
+}
+
+/// Describes how a `#[repr(C)]` vertex struct is read by the vertex shader.
+/// Usually derived with `#[derive(VertexLayout)]`.
+pub trait VertexLayout: Copy {
+    fn attributes(binding: u32) -> Vec<vk::VertexInputAttributeDescription>;
+
+    fn binding(binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding,
+            stride: mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }
+    }
+}
+
+pub trait IndexFormat: Copy {
+    const INDEX_TYPE: vk::IndexType;
+}
+
+impl IndexFormat for u16 {
+    const INDEX_TYPE: vk::IndexType = vk::IndexType::UINT16;
+}
+
+impl IndexFormat for u32 {
+    const INDEX_TYPE: vk::IndexType = vk::IndexType::UINT32;
+}
+
+/// A run of indices, or of vertices for non-indexed meshes, drawn with one call
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DrawRange {
+    pub first: u32,
+    pub count: u32,
+    pub vertex_offset: i32,
+}
+
+pub struct Mesh {
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Option<Buffer>,
+    pub index_type: vk::IndexType,
+    pub vertex_count: u32,
+    pub index_count: u32,
+    pub ranges: Vec<DrawRange>,
+    pub binding: vk::VertexInputBindingDescription,
+    pub attributes: Vec<vk::VertexInputAttributeDescription>,
+}
+
+impl Mesh {
+    /// Uploads `vertices` as a non-indexed mesh drawn as a single range
+    pub fn new<V: VertexLayout>(batch: &mut UploadBatch, vertices: &[V]) -> Result<Mesh> {
+        let vertex_buffer = batch.buffer(vertices, vk::BufferUsageFlags::VERTEX_BUFFER)?;
+
+        Ok(Mesh {
+            vertex_buffer,
+            index_buffer: None,
+            index_type: vk::IndexType::UINT32,
+            vertex_count: vertices.len() as u32,
+            index_count: 0,
+            ranges: vec![DrawRange { first: 0, count: vertices.len() as u32, vertex_offset: 0 }],
+            binding: V::binding(0),
+            attributes: V::attributes(0),
+        })
+    }
+
+    /// Uploads `vertices` and `indices` as a mesh drawn as a single range
+    pub fn indexed<V: VertexLayout, I: IndexFormat>(batch: &mut UploadBatch,
+                                                    vertices: &[V],
+                                                    indices: &[I]
+    ) -> Result<Mesh> {
+        let vertex_buffer = batch.buffer(vertices, vk::BufferUsageFlags::VERTEX_BUFFER)?;
+        let index_buffer = batch.buffer(indices, vk::BufferUsageFlags::INDEX_BUFFER)?;
+
+        Ok(Mesh {
+            vertex_buffer,
+            index_buffer: Some(index_buffer),
+            index_type: I::INDEX_TYPE,
+            vertex_count: vertices.len() as u32,
+            index_count: indices.len() as u32,
+            ranges: vec![DrawRange { first: 0, count: indices.len() as u32, vertex_offset: 0 }],
+            binding: V::binding(0),
+            attributes: V::attributes(0),
+        })
+    }
+
+    /// Replaces the default single range, e.g. for one range per material
+    pub fn with_ranges(mut self, ranges: Vec<DrawRange>) -> Mesh {
+        let limit = if self.is_indexed() { self.index_count } else { self.vertex_count };
+        assert!(
+            ranges.iter().all(|range| range.first.checked_add(range.count).is_some_and(|end| end <= limit)),
+            "draw range past the end of a mesh"
+        );
+        self.ranges = ranges;
+        self
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.index_buffer.is_some()
+    }
+
+    /// Vertex input state for a pipeline drawing this mesh. Borrows `self`.
+    pub fn vertex_input_state(&self) -> vk::PipelineVertexInputStateCreateInfoBuilder<'_> {
+        vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_binding_descriptions(std::slice::from_ref(&self.binding))
+            .vertex_attribute_descriptions(&self.attributes)
+    }
+}
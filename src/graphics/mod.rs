@@ -1,12 +1,28 @@
+pub mod camera;
+pub mod capture;
 pub mod command_buffer;
 pub mod command_pool;
+pub mod descriptor;
 pub mod device;
 pub mod fence;
 pub mod framebuffers;
+pub mod golden;
+pub mod hot_reload;
+pub mod memory;
+pub mod mesh;
+pub mod offscreen;
 pub mod vk_base;
 pub mod vk_instance;
 pub mod pipeline;
+pub mod pipeline_cache;
 pub mod physical_device;
 pub mod vk_renderer;
 pub mod render_pass;
+pub mod sampler;
 pub mod shader_module;
+pub mod texture;
+pub mod upload;
+
+/// Frames in flight; sizes the per-frame fences, command buffers and camera
+/// buffers
+pub const PIPELINE_DEPTH: u32 = 2;
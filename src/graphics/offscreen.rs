@@ -0,0 +1,70 @@
+use ash::{vk::{self}};
+use std::sync::{Arc};
+
+use crate::{
+    error::{Result},
+    graphics::{
+        memory::{Image, MemoryLocation},
+        render_pass::COLOR_FORMAT,
+        vk_base::VkBase
+    }
+};
+
+const IMAGE_COUNT: u32 = 2;
+
+/// Layered color images standing in for an OpenXR swapchain in headless mode
+pub struct OffscreenImages {
+    pub images: Vec<vk::Image>,
+    // Owns the images above; dropped after the device is idle
+    allocated: Vec<Image>,
+    vk_base: Arc<VkBase>,
+    next_image: u32,
+}
+
+impl OffscreenImages {
+    pub fn new(vk_base: &Arc<VkBase>,
+               resolution: vk::Extent2D,
+               view_count: u32,
+    ) -> Result<OffscreenImages> {
+        let allocated = (0..IMAGE_COUNT)
+            .map(|_| {
+                Image::new(
+                    vk_base,
+                    &vk::ImageCreateInfo::builder()
+                        .image_type(vk::ImageType::TYPE_2D)
+                        .format(COLOR_FORMAT)
+                        .extent(resolution.into())
+                        .mip_levels(1)
+                        .array_layers(view_count)
+                        .samples(vk::SampleCountFlags::TYPE_1)
+                        .tiling(vk::ImageTiling::OPTIMAL)
+                        .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT
+                            | vk::ImageUsageFlags::SAMPLED
+                            | vk::ImageUsageFlags::TRANSFER_SRC)
+                        .sharing_mode(vk::SharingMode::EXCLUSIVE),
+                    MemoryLocation::DeviceLocal,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(OffscreenImages {
+            images: allocated.iter().map(|image| image.handle).collect(),
+            allocated,
+            vk_base: vk_base.clone(),
+            next_image: 0,
+        })
+    }
+
+    pub fn acquire_image(&mut self) -> u32 {
+        let index = self.next_image;
+        self.next_image = (self.next_image + 1) % IMAGE_COUNT;
+        index
+    }
+}
+
+impl Drop for OffscreenImages {
+    fn drop(&mut self) {
+        let _ = self.vk_base.device.device_wait_idle();
+        self.allocated.clear();
+    }
+}
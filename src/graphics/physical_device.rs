@@ -1,29 +1,113 @@
 use ash::{vk::{self, Handle}};
 use std::sync::{Arc};
 
-use crate::graphics::{
-    vk_instance::VkInstance
+use crate::{
+    error::{Context, Error, Result},
+    graphics::{
+        vk_instance::VkInstance
+    }
 };
 
 pub struct PhysicalDevice {
-    pub handle: ash::vk::PhysicalDevice
+    pub handle: ash::vk::PhysicalDevice,
+    pub memory_properties: ash::vk::PhysicalDeviceMemoryProperties,
+    pub properties: ash::vk::PhysicalDeviceProperties,
 }
 
 impl PhysicalDevice {
     pub fn new(xr_instance: &openxr::Instance,
                vk_instance: &Arc<VkInstance>,
                system_id: openxr::SystemId
-    ) -> Arc<PhysicalDevice> {
+    ) -> Result<Arc<PhysicalDevice>> {
         let handle = vk::PhysicalDevice::from_raw(
             unsafe {
                 xr_instance
                     .vulkan_graphics_device(system_id, vk_instance.handle.handle().as_raw() as _)
-                    .unwrap() as _
+                    .context("querying Vulkan graphics device")? as _
             }
         );
 
+        Ok(Self::from_handle(vk_instance, handle))
+    }
+
+    /// Picks the first device with a graphics queue, which includes software
+    /// implementations such as lavapipe
+    pub fn headless(vk_instance: &Arc<VkInstance>) -> Result<Arc<PhysicalDevice>> {
+        let handle = unsafe {
+            vk_instance
+                .handle
+                .enumerate_physical_devices()
+                .context("enumerating physical devices")?
+                .into_iter()
+                .find(|physical_device| {
+                    vk_instance
+                        .handle
+                        .get_physical_device_queue_family_properties(*physical_device)
+                        .iter()
+                        .any(|info| info.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+                })
+                .ok_or(Error::NoGraphicsQueue)?
+        };
+
+        Ok(Self::from_handle(vk_instance, handle))
+    }
+
+    fn from_handle(vk_instance: &VkInstance, handle: vk::PhysicalDevice) -> Arc<PhysicalDevice> {
+        let (memory_properties, properties) = unsafe {
+            (vk_instance.handle.get_physical_device_memory_properties(handle),
+             vk_instance.handle.get_physical_device_properties(handle))
+        };
+
         Arc::new(PhysicalDevice {
-            handle
+            handle,
+            memory_properties,
+            properties
         })
     }
+
+    /// First of `candidates` usable as an optimally tiled depth attachment
+    pub fn find_depth_format(&self,
+                             vk_instance: &VkInstance,
+                             candidates: &[vk::Format]
+    ) -> Option<vk::Format> {
+        candidates.iter().copied().find(|format| {
+            let properties = unsafe {
+                vk_instance
+                    .handle
+                    .get_physical_device_format_properties(self.handle, *format)
+            };
+            properties
+                .optimal_tiling_features
+                .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+        })
+    }
+
+    /// Whether optimally tiled images of `format` can be downsampled with a
+    /// linear blit, as mip generation does
+    pub fn supports_linear_blit(&self, vk_instance: &VkInstance, format: vk::Format) -> bool {
+        let properties = unsafe {
+            vk_instance
+                .handle
+                .get_physical_device_format_properties(self.handle, format)
+        };
+        properties.optimal_tiling_features.contains(
+            vk::FormatFeatureFlags::BLIT_SRC
+                | vk::FormatFeatureFlags::BLIT_DST
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR
+        )
+    }
+
+    pub fn find_memory_type_index(&self,
+                                  memory_requirements: &vk::MemoryRequirements,
+                                  flags: vk::MemoryPropertyFlags
+    ) -> Option<u32> {
+        self.memory_properties.memory_types[..self.memory_properties.memory_type_count as _]
+            .iter()
+            .enumerate()
+            .find(|(index, memory_type)| {
+                (1 << index) & memory_requirements.memory_type_bits != 0
+                    && memory_type.property_flags & flags == flags
+            })
+            .map(|(index, _memory_type)| index as _)
+    }
 }
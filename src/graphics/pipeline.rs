@@ -1,66 +1,354 @@
 use ash::{vk::{self}};
-use std::sync::{Arc};
+use glam::{Mat4, Vec4};
+use std::{
+    marker::PhantomData,
+    mem,
+    sync::{Arc},
+};
+
+use crate::{
+    assets::model::{Vertex},
+    error::{Context, Result},
+    graphics::{
+        mesh::{VertexLayout},
+        shader_module::ShaderModule,
+        vk_base::VkBase
+    }
+};
+
+/// Writes the fragment color as is
+pub const BLEND_OPAQUE: vk::PipelineColorBlendAttachmentState = vk::PipelineColorBlendAttachmentState {
+    blend_enable: vk::FALSE,
+    src_color_blend_factor: vk::BlendFactor::ONE,
+    dst_color_blend_factor: vk::BlendFactor::ZERO,
+    color_blend_op: vk::BlendOp::ADD,
+    src_alpha_blend_factor: vk::BlendFactor::ONE,
+    dst_alpha_blend_factor: vk::BlendFactor::ZERO,
+    alpha_blend_op: vk::BlendOp::ADD,
+    color_write_mask: vk::ColorComponentFlags::RGBA,
+};
+
+/// Blends non-premultiplied colors over the attachment by their alpha
+pub const BLEND_ALPHA: vk::PipelineColorBlendAttachmentState = vk::PipelineColorBlendAttachmentState {
+    blend_enable: vk::TRUE,
+    src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+    dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+    color_blend_op: vk::BlendOp::ADD,
+    src_alpha_blend_factor: vk::BlendFactor::ONE,
+    dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+    alpha_blend_op: vk::BlendOp::ADD,
+    color_write_mask: vk::ColorComponentFlags::RGBA,
+};
 
-use crate::graphics::{
-    device::Device,
-    render_pass::RenderPass,
-    shader_module::ShaderModule
+/// Opaque, leaving the cleared alpha of the library render pass untouched
+const BLEND_OPAQUE_RGB: vk::PipelineColorBlendAttachmentState = vk::PipelineColorBlendAttachmentState {
+    color_write_mask: vk::ColorComponentFlags::from_raw(
+        vk::ColorComponentFlags::R.as_raw() | vk::ColorComponentFlags::G.as_raw() | vk::ColorComponentFlags::B.as_raw()
+    ),
+    ..BLEND_OPAQUE
 };
 
+/// Push constants of the mesh pipeline:
+///
+/// ```glsl
+/// layout(push_constant) uniform Object {
+///     mat4 model;
+///     vec4 base_color;
+/// } object;
+/// ```
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MeshPushConstants {
+    pub model: Mat4,
+    pub base_color: Vec4,
+}
+
+impl MeshPushConstants {
+    pub const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::from_raw(
+        vk::ShaderStageFlags::VERTEX.as_raw() | vk::ShaderStageFlags::FRAGMENT.as_raw()
+    );
+}
+
+/// A graphics pipeline and its layout, destroyed on drop
 pub struct Pipeline {
     pub handle: ash::vk::Pipeline,
     pub pipeline_layout: ash::vk::PipelineLayout,
+    // Held so the device outlives the pipeline
+    vk_base: Arc<VkBase>,
 }
 
 impl Pipeline {
-    pub fn new(device: &Arc<Device>,
-               render_pass: &Arc<RenderPass>
-    ) -> Arc<Pipeline> {
+    /// Draws a hard-coded triangle without vertex input, 1m in front of the
+    /// origin. Its shaders are in `shaders/`, compiled by the build script.
+    pub fn triangle(vk_base: &Arc<VkBase>,
+                    render_pass: vk::RenderPass,
+                    set_layouts: &[vk::DescriptorSetLayout],
+    ) -> Result<Pipeline> {
+        let vert = ShaderModule::new(vk_base, include_bytes!(concat!(env!("OUT_DIR"), "/shaders/triangle.vert.spv")))?;
+        let frag = ShaderModule::new(vk_base, include_bytes!(concat!(env!("OUT_DIR"), "/shaders/triangle.frag.spv")))?;
+
+        Self::triangle_builder(render_pass, set_layouts)
+            .vertex_shader(&vert)
+            .fragment_shader(&frag)
+            .build(vk_base)
+    }
+
+    /// Draws `assets::model::Vertex` meshes lit by a fixed light, colored by
+    /// `MeshPushConstants::base_color`
+    pub fn mesh(vk_base: &Arc<VkBase>,
+                render_pass: vk::RenderPass,
+                set_layouts: &[vk::DescriptorSetLayout],
+    ) -> Result<Pipeline> {
+        let vert = ShaderModule::new(vk_base, include_bytes!(concat!(env!("OUT_DIR"), "/shaders/mesh.vert.spv")))?;
+        let frag = ShaderModule::new(vk_base, include_bytes!(concat!(env!("OUT_DIR"), "/shaders/mesh.frag.spv")))?;
+
+        Self::mesh_builder(render_pass, set_layouts)
+            .vertex_shader(&vert)
+            .fragment_shader(&frag)
+            .build(vk_base)
+    }
+
+    /// `triangle` without its shaders
+    pub fn triangle_builder<'a>(render_pass: vk::RenderPass,
+                                set_layouts: &[vk::DescriptorSetLayout],
+    ) -> GraphicsPipelineBuilder<'a> {
+        GraphicsPipelineBuilder::new(render_pass)
+            .set_layouts(set_layouts)
+            .blend_attachments(&[BLEND_OPAQUE_RGB])
+    }
+
+    /// `mesh` without its shaders
+    pub fn mesh_builder<'a>(render_pass: vk::RenderPass,
+                            set_layouts: &[vk::DescriptorSetLayout],
+    ) -> GraphicsPipelineBuilder<'a> {
+        GraphicsPipelineBuilder::new(render_pass)
+            .vertex_layout::<Vertex>(0)
+            .push_constants::<MeshPushConstants>(MeshPushConstants::STAGES)
+            .set_layouts(set_layouts)
+            .blend_attachments(&[BLEND_OPAQUE_RGB])
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        unsafe {
+            let device = &self.vk_base.device.handle;
+            device.destroy_pipeline(self.handle, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+/// Describes a graphics pipeline. The defaults suit the library `RenderPass`,
+/// multiview included: a triangle list, no culling, depth tested and written
+/// with `VkBase::depth_range`'s compare op, one opaque color attachment, one
+/// sample and a dynamic viewport and scissor. Borrows its shader modules.
+#[derive(Clone, Debug)]
+pub struct GraphicsPipelineBuilder<'a> {
+    render_pass: vk::RenderPass,
+    subpass: u32,
+    stages: Vec<(vk::ShaderStageFlags, vk::ShaderModule)>,
+    /// Keeps the modules in `stages` alive until `build`
+    modules: PhantomData<&'a ShaderModule>,
+    vertex_bindings: Vec<vk::VertexInputBindingDescription>,
+    vertex_attributes: Vec<vk::VertexInputAttributeDescription>,
+    topology: vk::PrimitiveTopology,
+    polygon_mode: vk::PolygonMode,
+    cull_mode: vk::CullModeFlags,
+    front_face: vk::FrontFace,
+    depth_test: bool,
+    depth_write: bool,
+    depth_compare_op: Option<vk::CompareOp>,
+    stencil: Option<(vk::StencilOpState, vk::StencilOpState)>,
+    blend_attachments: Vec<vk::PipelineColorBlendAttachmentState>,
+    samples: vk::SampleCountFlags,
+    dynamic_states: Vec<vk::DynamicState>,
+    push_constant_ranges: Vec<vk::PushConstantRange>,
+    set_layouts: Vec<vk::DescriptorSetLayout>,
+}
+
+impl<'a> GraphicsPipelineBuilder<'a> {
+    pub fn new(render_pass: vk::RenderPass) -> Self {
+        GraphicsPipelineBuilder {
+            render_pass,
+            subpass: 0,
+            stages: Vec::new(),
+            modules: PhantomData,
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            depth_test: true,
+            depth_write: true,
+            depth_compare_op: None,
+            stencil: None,
+            blend_attachments: vec![BLEND_OPAQUE],
+            samples: vk::SampleCountFlags::TYPE_1,
+            dynamic_states: vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR],
+            push_constant_ranges: Vec::new(),
+            set_layouts: Vec::new(),
+        }
+    }
+
+    pub fn subpass(mut self, subpass: u32) -> Self {
+        self.subpass = subpass;
+        self
+    }
+
+    /// Adds a stage running `module`'s `main`
+    pub fn shader(mut self, stage: vk::ShaderStageFlags, module: &'a ShaderModule) -> Self {
+        self.stages.push((stage, module.handle));
+        self
+    }
+
+    pub fn vertex_shader(self, module: &'a ShaderModule) -> Self {
+        self.shader(vk::ShaderStageFlags::VERTEX, module)
+    }
+
+    pub fn fragment_shader(self, module: &'a ShaderModule) -> Self {
+        self.shader(vk::ShaderStageFlags::FRAGMENT, module)
+    }
+
+    /// Reads vertices of type `V` from vertex buffer `binding`
+    pub fn vertex_layout<V: VertexLayout>(self, binding: u32) -> Self {
+        self.vertex_input(V::binding(binding), &V::attributes(binding))
+    }
+
+    /// Adds a vertex buffer binding, e.g. a `Mesh`'s `binding` and `attributes`
+    pub fn vertex_input(mut self,
+                        binding: vk::VertexInputBindingDescription,
+                        attributes: &[vk::VertexInputAttributeDescription],
+    ) -> Self {
+        self.vertex_bindings.push(binding);
+        self.vertex_attributes.extend_from_slice(attributes);
+        self
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags, front_face: vk::FrontFace) -> Self {
+        self.cull_mode = cull_mode;
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn depth(mut self, test: bool, write: bool) -> Self {
+        self.depth_test = test;
+        self.depth_write = write;
+        self
+    }
+
+    /// Overrides the compare op matching `VkBase::depth_range`
+    pub fn depth_compare_op(mut self, compare_op: vk::CompareOp) -> Self {
+        self.depth_compare_op = Some(compare_op);
+        self
+    }
+
+    /// Enables the stencil test. The depth format needs a stencil aspect.
+    pub fn stencil(mut self, front: vk::StencilOpState, back: vk::StencilOpState) -> Self {
+        self.stencil = Some((front, back));
+        self
+    }
+
+    /// One state per color attachment of the subpass, see `BLEND_OPAQUE` and
+    /// `BLEND_ALPHA`
+    pub fn blend_attachments(mut self, attachments: &[vk::PipelineColorBlendAttachmentState]) -> Self {
+        self.blend_attachments = attachments.to_vec();
+        self
+    }
+
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Adds to the default dynamic viewport and scissor
+    pub fn dynamic_state(mut self, state: vk::DynamicState) -> Self {
+        if !self.dynamic_states.contains(&state) {
+            self.dynamic_states.push(state);
+        }
+        self
+    }
+
+    pub fn push_constant_range(mut self, range: vk::PushConstantRange) -> Self {
+        self.push_constant_ranges.push(range);
+        self
+    }
+
+    /// A push constant range covering a `T` at offset 0
+    pub fn push_constants<T: Copy>(self, stages: vk::ShaderStageFlags) -> Self {
+        self.push_constant_range(vk::PushConstantRange {
+            stage_flags: stages,
+            offset: 0,
+            size: mem::size_of::<T>() as u32,
+        })
+    }
+
+    /// Layouts of sets 0, 1, ... in order
+    pub fn set_layouts(mut self, set_layouts: &[vk::DescriptorSetLayout]) -> Self {
+        self.set_layouts = set_layouts.to_vec();
+        self
+    }
+
+    pub fn build(&self, vk_base: &Arc<VkBase>) -> Result<Pipeline> {
+        assert!(!self.stages.is_empty(), "a graphics pipeline needs shader stages");
+        let device = &vk_base.device.handle;
+
+        let stages = self
+            .stages
+            .iter()
+            .map(|&(stage, module)| vk::PipelineShaderStageCreateInfo {
+                stage,
+                module,
+                p_name: c"main".as_ptr(),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        let noop_stencil_state = vk::StencilOpState {
+            fail_op: vk::StencilOp::KEEP,
+            pass_op: vk::StencilOp::KEEP,
+            depth_fail_op: vk::StencilOp::KEEP,
+            compare_op: vk::CompareOp::ALWAYS,
+            compare_mask: 0,
+            write_mask: 0,
+            reference: 0,
+        };
+        let (front, back) = self.stencil.unwrap_or((noop_stencil_state, noop_stencil_state));
+
         unsafe {
             let pipeline_layout = device
-                .handle
                 .create_pipeline_layout(
-                    &vk::PipelineLayoutCreateInfo::builder().set_layouts(&[]),
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&self.set_layouts)
+                        .push_constant_ranges(&self.push_constant_ranges),
                     None,
                 )
-                .unwrap();
-
-            let noop_stencil_state = vk::StencilOpState {
-                fail_op: vk::StencilOp::KEEP,
-                pass_op: vk::StencilOp::KEEP,
-                depth_fail_op: vk::StencilOp::KEEP,
-                compare_op: vk::CompareOp::ALWAYS,
-                compare_mask: 0,
-                write_mask: 0,
-                reference: 0,
-            };
-
-            let vert_module = ShaderModule::new(device, include_bytes!("triangle.vert.spv"));
-            let frag_module = ShaderModule::new(device, include_bytes!("triangle.frag.spv"));
+                .context("creating pipeline layout")?;
 
             let handle = device
-                .handle
                 .create_graphics_pipelines(
-                    vk::PipelineCache::null(),
+                    vk_base.pipeline_cache.handle,
                     &[vk::GraphicsPipelineCreateInfo::builder()
-                        .stages(&[
-                            vk::PipelineShaderStageCreateInfo {
-                                stage: vk::ShaderStageFlags::VERTEX,
-                                module: vert_module,
-                                p_name: b"main\0".as_ptr() as _,
-                                ..Default::default()
-                            },
-                            vk::PipelineShaderStageCreateInfo {
-                                stage: vk::ShaderStageFlags::FRAGMENT,
-                                module: frag_module,
-                                p_name: b"main\0".as_ptr() as _,
-                                ..Default::default()
-                            },
-                        ])
-                        .vertex_input_state(&vk::PipelineVertexInputStateCreateInfo::default())
+                        .stages(&stages)
+                        .vertex_input_state(
+                            &vk::PipelineVertexInputStateCreateInfo::builder()
+                                .vertex_binding_descriptions(&self.vertex_bindings)
+                                .vertex_attribute_descriptions(&self.vertex_attributes),
+                        )
                         .input_assembly_state(
                             &vk::PipelineInputAssemblyStateCreateInfo::builder()
-                                .topology(vk::PrimitiveTopology::TRIANGLE_LIST),
+                                .topology(self.topology),
                         )
                         .viewport_state(
                             &vk::PipelineViewportStateCreateInfo::builder()
@@ -69,56 +357,54 @@ impl Pipeline {
                         )
                         .rasterization_state(
                             &vk::PipelineRasterizationStateCreateInfo::builder()
-                                .cull_mode(vk::CullModeFlags::NONE)
-                                .polygon_mode(vk::PolygonMode::FILL)
+                                .cull_mode(self.cull_mode)
+                                .front_face(self.front_face)
+                                .polygon_mode(self.polygon_mode)
                                 .line_width(1.0),
                         )
                         .multisample_state(
                             &vk::PipelineMultisampleStateCreateInfo::builder()
-                                .rasterization_samples(vk::SampleCountFlags::TYPE_1),
+                                .rasterization_samples(self.samples),
                         )
                         .depth_stencil_state(
                             &vk::PipelineDepthStencilStateCreateInfo::builder()
-                                .depth_test_enable(false)
-                                .depth_write_enable(false)
-                                .front(noop_stencil_state)
-                                .back(noop_stencil_state),
+                                .depth_test_enable(self.depth_test)
+                                .depth_write_enable(self.depth_write)
+                                .depth_compare_op(
+                                    self.depth_compare_op.unwrap_or_else(|| vk_base.depth_range.compare_op())
+                                )
+                                .stencil_test_enable(self.stencil.is_some())
+                                .front(front)
+                                .back(back),
                         )
                         .color_blend_state(
-                            &vk::PipelineColorBlendStateCreateInfo::builder().attachments(&[
-                                vk::PipelineColorBlendAttachmentState {
-                                    blend_enable: vk::TRUE,
-                                    src_color_blend_factor: vk::BlendFactor::ONE,
-                                    dst_color_blend_factor: vk::BlendFactor::ZERO,
-                                    color_blend_op: vk::BlendOp::ADD,
-                                    color_write_mask: vk::ColorComponentFlags::R
-                                        | vk::ColorComponentFlags::G
-                                        | vk::ColorComponentFlags::B,
-                                    ..Default::default()
-                                },
-                            ]),
+                            &vk::PipelineColorBlendStateCreateInfo::builder()
+                                .attachments(&self.blend_attachments),
                         )
                         .dynamic_state(
-                            &vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&[
-                                vk::DynamicState::VIEWPORT,
-                                vk::DynamicState::SCISSOR,
-                            ]),
+                            &vk::PipelineDynamicStateCreateInfo::builder()
+                                .dynamic_states(&self.dynamic_states),
                         )
                         .layout(pipeline_layout)
-                        .render_pass(render_pass.handle)
-                        .subpass(0)
+                        .render_pass(self.render_pass)
+                        .subpass(self.subpass)
                         .build()],
                     None,
                 )
-                .unwrap()[0];
+                .map_err(|(_, result)| result)
+                .context("creating graphics pipeline");
 
-            device.handle.destroy_shader_module(vert_module, None);
-            device.handle.destroy_shader_module(frag_module, None);
-
-            Arc::new(Pipeline {
-                handle,
-                pipeline_layout,
-            })
+            match handle {
+                Ok(handle) => Ok(Pipeline {
+                    handle: handle[0],
+                    pipeline_layout,
+                    vk_base: vk_base.clone(),
+                }),
+                Err(e) => {
+                    device.destroy_pipeline_layout(pipeline_layout, None);
+                    Err(e)
+                }
+            }
         }
     }
 }
@@ -0,0 +1,133 @@
+//! A `vk::PipelineCache` persisted between runs, so pipelines compiled once
+//! aren't compiled again on the next launch.
+//!
+//! The file is only used if its header matches the physical device's vendor,
+//! device and cache UUID, since drivers reject or misbehave on caches written
+//! by another device or driver version.
+
+use ash::{vk::{self}};
+use std::{
+    env,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::error::{Context, Result};
+
+const FILE_NAME: &str = "pipeline_cache.bin";
+
+/// Size of `VkPipelineCacheHeaderVersionOne`
+const HEADER_LENGTH: usize = 32;
+
+pub struct PipelineCache {
+    pub handle: vk::PipelineCache,
+    /// Where `save` writes the cache, `None` for a cache kept in memory
+    pub path: Option<PathBuf>,
+    /// Whether the cache was seeded from the file at `path`
+    pub loaded: bool,
+    device: ash::Device,
+}
+
+impl PipelineCache {
+    /// Seeds the cache from `path` if it holds a cache for this device,
+    /// otherwise starts empty
+    pub fn new(device: &ash::Device,
+               properties: &vk::PhysicalDeviceProperties,
+               path: Option<PathBuf>,
+    ) -> Result<PipelineCache> {
+        let initial_data = match path.as_deref().map(fs::read) {
+            Some(Ok(data)) if header_matches(&data, properties) => data,
+            Some(Ok(_)) => {
+                println!("Ignoring pipeline cache {}, it was written by another device or driver",
+                         path.as_deref().unwrap_or(Path::new("")).display());
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+
+        let handle = unsafe {
+            device
+                .create_pipeline_cache(
+                    &vk::PipelineCacheCreateInfo::builder().initial_data(&initial_data),
+                    None,
+                )
+                .context("creating pipeline cache")?
+        };
+
+        Ok(PipelineCache {
+            handle,
+            path,
+            loaded: !initial_data.is_empty(),
+            device: device.clone(),
+        })
+    }
+
+    /// The cache contents, header included
+    pub fn data(&self) -> Result<Vec<u8>> {
+        unsafe {
+            self.device
+                .get_pipeline_cache_data(self.handle)
+                .context("reading pipeline cache data")
+        }
+    }
+
+    /// Writes the cache to `path`, replacing the previous file only once the
+    /// new one is complete. Does nothing for an in-memory cache.
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let data = self.data()?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("creating pipeline cache directory")?;
+        }
+        let partial = path.with_extension("partial");
+        fs::write(&partial, data).context("writing pipeline cache")?;
+        fs::rename(&partial, path).context("replacing pipeline cache")
+    }
+
+    /// Called by `VkBase` before it destroys the device
+    pub fn destroy(&self) {
+        unsafe {
+            self.device.destroy_pipeline_cache(self.handle, None);
+        }
+    }
+}
+
+/// Whether `data` starts with a version one header written for the device
+/// with `properties`
+pub fn header_matches(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+    if data.len() < HEADER_LENGTH {
+        return false;
+    }
+    // Always little-endian, whatever the host byte order
+    let u32_at = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+    u32_at(0) as usize >= HEADER_LENGTH
+        && u32_at(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && u32_at(8) == properties.vendor_id
+        && u32_at(12) == properties.device_id
+        && data[16..32] == properties.pipeline_cache_uuid
+}
+
+/// `pipeline_cache.bin` in a directory named after the application under the
+/// platform's per-user data directory. `None` where there is no such
+/// directory, e.g. on Android, where apps pass their internal data path.
+pub fn default_path(application_name: &str) -> Option<PathBuf> {
+    let data_dir = if cfg!(target_os = "android") {
+        None
+    } else if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+
+    data_dir.map(|dir| dir.join(application_name).join(FILE_NAME))
+}
@@ -1,48 +1,74 @@
 use ash::{vk::{self}};
 use std::sync::{Arc};
 
-use crate::graphics::{
-    device::Device,
+use crate::{
+    error::{Context, Result},
+    graphics::{
+        device::Device,
+    }
 };
 
 pub const COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
-pub const VIEW_COUNT: u32 = 2;
 
 pub struct RenderPass {
     pub handle: ash::vk::RenderPass
 }
 
 impl RenderPass {
-    pub fn new(device: &Arc<Device>) -> Arc<RenderPass> {
-        let view_mask = !(!0 << VIEW_COUNT);
+    pub fn new(device: &Arc<Device>,
+               view_count: u32,
+               depth_format: vk::Format,
+    ) -> Result<Arc<RenderPass>> {
+        let view_mask = !(!0 << view_count);
 
         unsafe {
             let handle = device
                 .handle
                 .create_render_pass(
                     &vk::RenderPassCreateInfo::builder()
-                        .attachments(&[vk::AttachmentDescription {
-                            format: COLOR_FORMAT,
-                            samples: vk::SampleCountFlags::TYPE_1,
-                            load_op: vk::AttachmentLoadOp::CLEAR,
-                            store_op: vk::AttachmentStoreOp::STORE,
-                            initial_layout: vk::ImageLayout::UNDEFINED,
-                            final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-                            ..Default::default()
-                        }])
+                        .attachments(&[
+                            vk::AttachmentDescription {
+                                format: COLOR_FORMAT,
+                                samples: vk::SampleCountFlags::TYPE_1,
+                                load_op: vk::AttachmentLoadOp::CLEAR,
+                                store_op: vk::AttachmentStoreOp::STORE,
+                                initial_layout: vk::ImageLayout::UNDEFINED,
+                                final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                                ..Default::default()
+                            },
+                            // Stored so the depth can be handed to the compositor
+                            vk::AttachmentDescription {
+                                format: depth_format,
+                                samples: vk::SampleCountFlags::TYPE_1,
+                                load_op: vk::AttachmentLoadOp::CLEAR,
+                                store_op: vk::AttachmentStoreOp::STORE,
+                                stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+                                stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+                                initial_layout: vk::ImageLayout::UNDEFINED,
+                                final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                                ..Default::default()
+                            },
+                        ])
                         .subpasses(&[vk::SubpassDescription::builder()
                             .color_attachments(&[vk::AttachmentReference {
                                 attachment: 0,
                                 layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
                             }])
+                            .depth_stencil_attachment(&vk::AttachmentReference {
+                                attachment: 1,
+                                layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                            })
                             .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
                             .build()])
                         .dependencies(&[vk::SubpassDependency {
                             src_subpass: vk::SUBPASS_EXTERNAL,
                             dst_subpass: 0,
-                            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
                             ..Default::default()
                         }])
                         .push_next(
@@ -52,11 +78,11 @@ impl RenderPass {
                         ),
                     None,
                 )
-                .unwrap();
+                .context("creating render pass")?;
 
-            Arc::new(RenderPass {
+            Ok(Arc::new(RenderPass {
                 handle
-            })
+            }))
         }
     }
 }
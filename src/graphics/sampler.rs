@@ -0,0 +1,96 @@
+//! Samplers shared by every texture sampled the same way.
+
+use ash::{vk::{self}};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::error::{Context, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerDesc {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        SamplerDesc {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+        }
+    }
+}
+
+/// Creates one `vk::Sampler` per distinct `SamplerDesc`. Owned by `VkBase`,
+/// which destroys the samplers along with the device.
+pub struct SamplerCache {
+    device: ash::Device,
+    samplers: Mutex<HashMap<SamplerDesc, vk::Sampler>>,
+}
+
+impl SamplerCache {
+    pub fn new(device: &ash::Device) -> SamplerCache {
+        SamplerCache {
+            device: device.clone(),
+            samplers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<SamplerDesc, vk::Sampler>> {
+        self.samplers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The sampler for `desc`, created on first use. Samples every mip level.
+    pub fn get(&self, desc: &SamplerDesc) -> Result<vk::Sampler> {
+        let mut samplers = self.lock();
+        if let Some(&sampler) = samplers.get(desc) {
+            return Ok(sampler);
+        }
+
+        let sampler = unsafe {
+            self.device
+                .create_sampler(
+                    &vk::SamplerCreateInfo::builder()
+                        .mag_filter(desc.mag_filter)
+                        .min_filter(desc.min_filter)
+                        .mipmap_mode(desc.mipmap_mode)
+                        .address_mode_u(desc.address_mode_u)
+                        .address_mode_v(desc.address_mode_v)
+                        .address_mode_w(desc.address_mode_u)
+                        .min_lod(0.0)
+                        .max_lod(vk::LOD_CLAMP_NONE),
+                    None,
+                )
+                .context("creating sampler")?
+        };
+        samplers.insert(*desc, sampler);
+
+        Ok(sampler)
+    }
+
+    /// Number of distinct samplers created so far
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Destroys every sampler. Called by `VkBase` once the device is idle.
+    pub fn destroy(&self) {
+        for (_, sampler) in self.lock().drain() {
+            unsafe {
+                self.device.destroy_sampler(sampler, None);
+            }
+        }
+    }
+}
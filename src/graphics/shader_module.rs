@@ -1,5 +1,7 @@
 use std::{
+    fs,
     io::Cursor,
+    path::Path,
     sync::{Arc}
 };
 
@@ -8,23 +10,126 @@ use ash::{
     vk::{self}
 };
 
-use crate::graphics::{
-    device::Device
+use crate::{
+    error::{Context, Error, Result},
+    graphics::{
+        vk_base::VkBase
+    }
+};
+
+pub use xrrs_shader::{
+    shader_kind, validate_spirv, CompileError, CompiledShader, Diagnostic, ShaderCompiler, ShaderLanguage, ShaderStage,
 };
 
-pub struct ShaderModule {}
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// Magic number, version, generator, id bound and schema
+const SPIRV_HEADER_WORDS: usize = 5;
+
+pub struct ShaderModule {
+    pub handle: vk::ShaderModule,
+    // Held so the device outlives the module
+    vk_base: Arc<VkBase>,
+}
 
 impl ShaderModule {
-    pub fn new(device: &Arc<Device>, path: &[u8]) -> ash::vk::ShaderModule {
-        let code = read_spv(&mut Cursor::new(path)).unwrap();
+    /// `spirv` holds a SPIR-V binary, e.g. from `include_bytes!`
+    pub fn new(vk_base: &Arc<VkBase>, spirv: &[u8]) -> Result<ShaderModule> {
+        let code = read_spv(&mut Cursor::new(spirv)).context("reading SPIR-V")?;
+        Self::from_words(vk_base, &code)
+    }
+
+    /// Loads a SPIR-V binary at runtime, e.g. to iterate on shaders without
+    /// rebuilding the app. GLSL and WGSL files, named as described in
+    /// `shader_kind`, are compiled first without any defines.
+    ///
+    /// Binaries that `validate_spirv` rejects are still loaded, with a
+    /// warning, since naga doesn't read every valid module.
+    pub fn from_file(vk_base: &Arc<VkBase>, path: impl AsRef<Path>) -> Result<ShaderModule> {
+        let path = path.as_ref();
+        if shader_kind(path).is_some() {
+            return Self::compile_file(vk_base, &ShaderCompiler::new(), path);
+        }
+
+        let spirv = fs::read(path).context("reading shader file")?;
+        let code = read_spv(&mut Cursor::new(spirv)).context("reading SPIR-V")?;
+        check_structure(&code)?;
+        if let Err(e) = validate_spirv(&code) {
+            println!("Warning: {} may not be valid SPIR-V: {}", path.display(), e);
+        }
+        Self::create(vk_base, &code)
+    }
+
+    /// Compiles a GLSL or WGSL file with `compiler`'s defines and include
+    /// directories
+    pub fn compile_file(vk_base: &Arc<VkBase>,
+                        compiler: &ShaderCompiler,
+                        path: impl AsRef<Path>,
+    ) -> Result<ShaderModule> {
+        let shader = compiler.compile_file(path)?;
+        // The compiler validated the module before writing it
+        Self::create(vk_base, &shader.spirv)
+    }
+
+    /// `code` holds SPIR-V words, e.g. `CompiledShader::spirv`. Only the
+    /// header and instruction lengths are checked, see `validate_spirv` for a
+    /// full check.
+    pub fn from_words(vk_base: &Arc<VkBase>, code: &[u32]) -> Result<ShaderModule> {
+        check_structure(code)?;
+        Self::create(vk_base, code)
+    }
 
+    fn create(vk_base: &Arc<VkBase>, code: &[u32]) -> Result<ShaderModule> {
         unsafe {
-            let shader_module = device
+            let handle = vk_base
+                .device
                 .handle
-                .create_shader_module(&vk::ShaderModuleCreateInfo::builder().code(&code), None)
-                .unwrap();
+                .create_shader_module(&vk::ShaderModuleCreateInfo::builder().code(code), None)
+                .context("creating shader module")?;
 
-            shader_module
+            Ok(ShaderModule {
+                handle,
+                vk_base: vk_base.clone(),
+            })
         }
     }
 }
+
+impl Drop for ShaderModule {
+    fn drop(&mut self) {
+        unsafe {
+            self.vk_base.device.handle.destroy_shader_module(self.handle, None);
+        }
+    }
+}
+
+/// Checks what the driver relies on to walk a module: the header, a SPIR-V
+/// 1.x version and instructions that end where the module does
+fn check_structure(code: &[u32]) -> Result<()> {
+    let invalid = |reason: String| Err(Error::InvalidSpirv(reason));
+
+    if code.len() < SPIRV_HEADER_WORDS {
+        return invalid(format!("{} words is too short for a header", code.len()));
+    }
+    if code[0] != SPIRV_MAGIC {
+        return invalid("missing magic number".to_owned());
+    }
+    let (major, minor) = (code[1] >> 16, (code[1] >> 8) & 0xff);
+    if major != 1 || code[1] & 0xff00_00ff != 0 {
+        return invalid(format!("unknown version {}.{}", major, minor));
+    }
+    if code[3] == 0 {
+        return invalid("id bound of 0".to_owned());
+    }
+
+    let mut offset = SPIRV_HEADER_WORDS;
+    while offset < code.len() {
+        let word_count = (code[offset] >> 16) as usize;
+        if word_count == 0 || offset + word_count > code.len() {
+            return invalid(format!("instruction at word {} claims {} words", offset, word_count));
+        }
+        offset += word_count;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,9 @@
+// `CameraUniforms`, bound at set 0, binding 0 by the library pipelines. The
+// arrays have room for `camera::MAX_VIEWS` views.
+struct Camera {
+    view: array<mat4x4<f32>, 2>,
+    projection: array<mat4x4<f32>, 2>,
+    view_projection: array<mat4x4<f32>, 2>,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
@@ -0,0 +1,8 @@
+// A fixed light from above, so models are readable without any scene lighting
+const LIGHT_DIRECTION = vec3(0.3, 1.0, 0.5);
+
+@fragment
+fn main(@location(0) normal: vec3<f32>, @location(1) color: vec4<f32>) -> @location(0) vec4<f32> {
+    let light = 0.3 + 0.7 * max(dot(normalize(normal), normalize(LIGHT_DIRECTION)), 0.0);
+    return vec4(color.rgb * light, color.a);
+}
@@ -0,0 +1,42 @@
+#include "camera.wgsl"
+
+// `MeshPushConstants`
+struct Object {
+    model: mat4x4<f32>,
+    base_color: vec4<f32>,
+}
+
+var<push_constant> object: Object;
+
+// `assets::model::Vertex`
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) tex_coord: vec2<f32>,
+    @location(3) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn main(
+    vertex: VertexInput,
+#ifdef MULTIVIEW
+    @builtin(view_index) view_index: i32,
+#endif
+) -> VertexOutput {
+#ifndef MULTIVIEW
+    let view_index = 0;
+#endif
+    let model = object.model;
+
+    var out: VertexOutput;
+    out.position = camera.view_projection[view_index] * model * vec4(vertex.position, 1.0);
+    out.normal = mat3x3(model[0].xyz, model[1].xyz, model[2].xyz) * vertex.normal;
+    out.color = vertex.color * object.base_color;
+    return out;
+}
@@ -0,0 +1,34 @@
+#include "camera.wgsl"
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+}
+
+// A triangle 40cm wide, 1m in front of the origin at about eye height
+@vertex
+fn main(
+    @builtin(vertex_index) vertex_index: u32,
+#ifdef MULTIVIEW
+    @builtin(view_index) view_index: i32,
+#endif
+) -> VertexOutput {
+#ifndef MULTIVIEW
+    let view_index = 0;
+#endif
+    var positions = array(
+        vec3(-0.2, 1.4, -1.0),
+        vec3(0.2, 1.4, -1.0),
+        vec3(0.0, 1.8, -1.0),
+    );
+    var colors = array(
+        vec3(1.0, 0.0, 0.0),
+        vec3(0.0, 1.0, 0.0),
+        vec3(0.0, 0.0, 1.0),
+    );
+
+    var out: VertexOutput;
+    out.position = camera.view_projection[view_index] * vec4(positions[vertex_index], 1.0);
+    out.color = colors[vertex_index];
+    return out;
+}
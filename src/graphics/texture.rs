@@ -0,0 +1,304 @@
+//! Sampled 2D textures loaded from PNG, JPEG or KTX2 files, or raw RGBA8
+//! pixels.
+//!
+//! Missing mip levels are generated on the GPU by blitting each level down
+//! from the previous one, in the same submission as the upload. KTX2 files
+//! that store their own mip chain are uploaded as is.
+
+use ash::{vk::{self}};
+use std::{
+    fs,
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    error::{Context, Error, Result},
+    graphics::{
+        memory::Image,
+        upload::UploadBatch,
+        vk_base::VkBase
+    }
+};
+
+const KTX2_MAGIC: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Formats with both an sRGB and a linear variant, as (UNORM, SRGB)
+const SRGB_PAIRS: &[(vk::Format, vk::Format)] = &[
+    (vk::Format::R8G8B8A8_UNORM, vk::Format::R8G8B8A8_SRGB),
+    (vk::Format::B8G8R8A8_UNORM, vk::Format::B8G8R8A8_SRGB),
+    (vk::Format::R8G8B8_UNORM, vk::Format::R8G8B8_SRGB),
+    (vk::Format::B8G8R8_UNORM, vk::Format::B8G8R8_SRGB),
+    (vk::Format::BC1_RGB_UNORM_BLOCK, vk::Format::BC1_RGB_SRGB_BLOCK),
+    (vk::Format::BC1_RGBA_UNORM_BLOCK, vk::Format::BC1_RGBA_SRGB_BLOCK),
+    (vk::Format::BC2_UNORM_BLOCK, vk::Format::BC2_SRGB_BLOCK),
+    (vk::Format::BC3_UNORM_BLOCK, vk::Format::BC3_SRGB_BLOCK),
+    (vk::Format::BC7_UNORM_BLOCK, vk::Format::BC7_SRGB_BLOCK),
+    (vk::Format::ETC2_R8G8B8_UNORM_BLOCK, vk::Format::ETC2_R8G8B8_SRGB_BLOCK),
+    (vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK, vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK),
+    (vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK, vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK),
+    (vk::Format::ASTC_4X4_UNORM_BLOCK, vk::Format::ASTC_4X4_SRGB_BLOCK),
+    (vk::Format::ASTC_5X5_UNORM_BLOCK, vk::Format::ASTC_5X5_SRGB_BLOCK),
+    (vk::Format::ASTC_6X6_UNORM_BLOCK, vk::Format::ASTC_6X6_SRGB_BLOCK),
+    (vk::Format::ASTC_8X8_UNORM_BLOCK, vk::Format::ASTC_8X8_SRGB_BLOCK),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Colors, decoded from sRGB when sampled
+    Srgb,
+    /// Data such as normals or roughness, sampled as stored
+    Linear,
+}
+
+/// The variant of `format` in `color_space`. Formats without an sRGB variant
+/// are returned unchanged.
+pub fn with_color_space(format: vk::Format, color_space: ColorSpace) -> vk::Format {
+    SRGB_PAIRS
+        .iter()
+        .find(|(unorm, srgb)| format == *unorm || format == *srgb)
+        .map_or(format, |&(unorm, srgb)| match color_space {
+            ColorSpace::Srgb => srgb,
+            ColorSpace::Linear => unorm,
+        })
+}
+
+/// Length of a full mip chain down to 1x1
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// A sampled image and a view of all its mip levels, in
+/// SHADER_READ_ONLY_OPTIMAL once the upload completes
+pub struct Texture {
+    pub image: Image,
+    pub view: vk::ImageView,
+    // Held so the device outlives the view
+    vk_base: Arc<VkBase>,
+}
+
+impl Texture {
+    pub fn from_file(batch: &mut UploadBatch,
+                     path: impl AsRef<Path>,
+                     color_space: ColorSpace,
+    ) -> Result<Texture> {
+        let bytes = fs::read(path).context("reading texture")?;
+        Texture::from_bytes(batch, &bytes, color_space)
+    }
+
+    /// Loads a KTX2 file, or any PNG or JPEG image with a full mip chain
+    pub fn from_bytes(batch: &mut UploadBatch, bytes: &[u8], color_space: ColorSpace) -> Result<Texture> {
+        if bytes.starts_with(&KTX2_MAGIC) {
+            return Texture::from_ktx2(batch, bytes, color_space);
+        }
+
+        let image = image::load_from_memory(bytes).map_err(Error::Image)?.to_rgba8();
+        Texture::from_rgba8(batch, image.width(), image.height(), image.as_raw(), color_space, true)
+    }
+
+    /// Uploads tightly packed RGBA8 pixels, generating the rest of the mip
+    /// chain if `mipmaps` is set. `pixels` has to hold exactly
+    /// `width * height` texels.
+    pub fn from_rgba8(batch: &mut UploadBatch,
+                      width: u32,
+                      height: u32,
+                      pixels: &[u8],
+                      color_space: ColorSpace,
+                      mipmaps: bool,
+    ) -> Result<Texture> {
+        let expected = width as usize * height as usize * 4;
+        if pixels.len() != expected {
+            return Err(Error::TextureSizeMismatch { len: pixels.len(), expected });
+        }
+
+        let format = with_color_space(vk::Format::R8G8B8A8_UNORM, color_space);
+        let mip_levels = if mipmaps { mip_level_count(width, height) } else { 1 };
+        Texture::new(batch, format, vk::Extent2D { width, height }, mip_levels, &[pixels])
+    }
+
+    /// Uploads the levels stored in a KTX2 file, or generates them when the
+    /// file has a level count of 0. Only 2D textures with a Vulkan format and
+    /// no supercompression are supported.
+    pub fn from_ktx2(batch: &mut UploadBatch, bytes: &[u8], color_space: ColorSpace) -> Result<Texture> {
+        let reader = ktx2::Reader::new(bytes).map_err(Error::Ktx2)?;
+        let header = reader.header();
+        if header.supercompression_scheme.is_some() {
+            return Err(Error::UnsupportedTexture("supercompressed KTX2"));
+        }
+        if header.layer_count > 1 || header.face_count > 1 || header.pixel_depth > 1 {
+            return Err(Error::UnsupportedTexture("KTX2 arrays, cube maps and 3D textures"));
+        }
+        let format = header.format.ok_or(Error::UnsupportedTexture("KTX2 without a Vulkan format"))?;
+        let format = with_color_space(vk::Format::from_raw(format.value() as i32), color_space);
+
+        let extent = vk::Extent2D { width: header.pixel_width, height: header.pixel_height.max(1) };
+        let levels = reader.levels().map(|level| level.data).collect::<Vec<_>>();
+        let mip_levels = if header.level_count == 0 {
+            mip_level_count(extent.width, extent.height)
+        } else {
+            levels.len() as u32
+        };
+        Texture::new(batch, format, extent, mip_levels, &levels)
+    }
+
+    /// Uploads `levels`. If that is a single level and `mip_levels` asks for
+    /// more, the rest are blitted from it, unless the format can't be
+    /// linearly blitted, in which case the texture keeps a single level.
+    fn new(batch: &mut UploadBatch,
+           format: vk::Format,
+           extent: vk::Extent2D,
+           mip_levels: u32,
+           levels: &[&[u8]],
+    ) -> Result<Texture> {
+        let vk_base = batch.vk_base();
+        let generate = levels.len() == 1
+            && mip_levels > 1
+            && vk_base.physical_device.supports_linear_blit(&vk_base.vk_instance, format);
+        let (mip_levels, usage, layout) = if generate {
+            (mip_levels,
+             vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_SRC,
+             vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        } else {
+            (levels.len() as u32, vk::ImageUsageFlags::SAMPLED, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        };
+
+        let image = batch.image(
+            &vk::ImageCreateInfo::builder()
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(format)
+                .extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+                .mip_levels(mip_levels)
+                .array_layers(1)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(usage)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE),
+            levels,
+            layout,
+        )?;
+        if generate {
+            cmd_generate_mips(batch, &image)?;
+        }
+
+        let view = unsafe {
+            vk_base
+                .device
+                .handle
+                .create_image_view(
+                    &vk::ImageViewCreateInfo::builder()
+                        .image(image.handle)
+                        .view_type(vk::ImageViewType::TYPE_2D)
+                        .format(format)
+                        .subresource_range(vk::ImageSubresourceRange {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            base_mip_level: 0,
+                            level_count: mip_levels,
+                            base_array_layer: 0,
+                            layer_count: 1,
+                        }),
+                    None,
+                )
+                .context("creating texture image view")?
+        };
+
+        Ok(Texture { image, view, vk_base: vk_base.clone() })
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe {
+            self.vk_base.device.handle.destroy_image_view(self.view, None);
+        }
+    }
+}
+
+/// Fills mip levels 1.. of an image whose level 0 was just uploaded and is in
+/// TRANSFER_DST_OPTIMAL, leaving every level in SHADER_READ_ONLY_OPTIMAL
+fn cmd_generate_mips(batch: &mut UploadBatch, image: &Image) -> Result<()> {
+    let cmd_buffer = batch.cmd_buffer()?;
+    let device = &batch.vk_base().device.handle;
+
+    let barrier = |level: u32,
+                   old_layout: vk::ImageLayout,
+                   new_layout: vk::ImageLayout,
+                   src_access_mask: vk::AccessFlags,
+                   dst_access_mask: vk::AccessFlags,
+                   dst_stage: vk::PipelineStageFlags| unsafe {
+        device.cmd_pipeline_barrier(
+            cmd_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            dst_stage,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[vk::ImageMemoryBarrier::builder()
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image.handle)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: level,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                })
+                .build()],
+        );
+    };
+    let corner = |level: u32| vk::Offset3D {
+        x: (image.extent.width >> level).max(1) as i32,
+        y: (image.extent.height >> level).max(1) as i32,
+        z: 1,
+    };
+    let layers = |level: u32| vk::ImageSubresourceLayers {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        mip_level: level,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+
+    for level in 1..image.mip_levels {
+        let src = level - 1;
+        barrier(src,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::TRANSFER_READ,
+                vk::PipelineStageFlags::TRANSFER);
+        unsafe {
+            device.cmd_blit_image(
+                cmd_buffer,
+                image.handle,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                image.handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[vk::ImageBlit {
+                    src_subresource: layers(src),
+                    src_offsets: [vk::Offset3D::default(), corner(src)],
+                    dst_subresource: layers(level),
+                    dst_offsets: [vk::Offset3D::default(), corner(level)],
+                }],
+                vk::Filter::LINEAR,
+            );
+        }
+        barrier(src,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::TRANSFER_READ,
+                vk::AccessFlags::SHADER_READ,
+                vk::PipelineStageFlags::ALL_COMMANDS);
+    }
+    barrier(image.mip_levels - 1,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+            vk::PipelineStageFlags::ALL_COMMANDS);
+
+    Ok(())
+}
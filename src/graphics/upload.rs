@@ -0,0 +1,631 @@
+//! Uploads CPU data into device-local buffers and images.
+//!
+//! Data is copied into a small ring of persistently mapped staging buffers and
+//! from there into its destination by transfer commands. All uploads recorded
+//! on one `UploadBatch` go to the GPU in as few submissions as the staging
+//! buffers allow, and the returned `UploadTicket` waits for all of them.
+
+use ash::{vk::{self}};
+use std::{
+    collections::VecDeque,
+    mem,
+    ptr,
+    slice,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    error::{Context, Error, Result},
+    graphics::{
+        command_pool::CommandPool,
+        device::Device,
+        memory::{Allocation, Buffer, Image, MemoryLocation, ResourceKind},
+        vk_base::VkBase
+    }
+};
+
+pub const STAGING_BUFFER_SIZE: vk::DeviceSize = 16 * 1024 * 1024;
+
+const STAGING_BUFFER_COUNT: usize = 3;
+
+/// Alignment of every staged chunk. Covers `bufferOffset` alignment for image
+/// copies of formats with 1, 2, 4, 8 and 16 byte texel blocks, the others are
+/// aligned to a multiple of their block size in `write_image`.
+const STAGING_ALIGNMENT: vk::DeviceSize = 16;
+
+struct StagingBuffer {
+    buffer: vk::Buffer,
+    allocation: Allocation,
+    size: vk::DeviceSize,
+    used: vk::DeviceSize,
+    cmd_buffer: vk::CommandBuffer,
+    fence: vk::Fence,
+    /// Set while the fence will be signalled by a submission
+    submitted: bool,
+    serial: u64,
+}
+
+// The mapping is only written while the state lock is held
+unsafe impl Send for StagingBuffer {}
+
+impl StagingBuffer {
+    fn new(vk_base: &VkBase, command_pool: vk::CommandPool, size: vk::DeviceSize) -> Result<StagingBuffer> {
+        let device = &vk_base.device.handle;
+
+        unsafe {
+            let buffer = device
+                .create_buffer(
+                    &vk::BufferCreateInfo::builder()
+                        .size(size)
+                        .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+                        .sharing_mode(vk::SharingMode::EXCLUSIVE),
+                    None,
+                )
+                .context("creating staging buffer")?;
+
+            let memory_requirements = device.get_buffer_memory_requirements(buffer);
+            let allocation = match vk_base.allocator.allocate(
+                &memory_requirements,
+                MemoryLocation::HostVisible,
+                ResourceKind::Linear,
+            ) {
+                Ok(allocation) => allocation,
+                Err(e) => {
+                    device.destroy_buffer(buffer, None);
+                    return Err(e);
+                }
+            };
+
+            let mut staging = StagingBuffer {
+                buffer,
+                allocation,
+                size,
+                used: 0,
+                cmd_buffer: vk::CommandBuffer::null(),
+                fence: vk::Fence::null(),
+                submitted: false,
+                serial: 0,
+            };
+
+            let created = (|| {
+                device
+                    .bind_buffer_memory(buffer, staging.allocation.memory, staging.allocation.offset)
+                    .context("binding staging buffer memory")?;
+                staging.cmd_buffer = device
+                    .allocate_command_buffers(
+                        &vk::CommandBufferAllocateInfo::builder()
+                            .command_pool(command_pool)
+                            .command_buffer_count(1),
+                    )
+                    .context("allocating upload command buffer")?[0];
+                staging.fence = device
+                    .create_fence(&vk::FenceCreateInfo::default(), None)
+                    .context("creating upload fence")?;
+                Ok(())
+            })();
+
+            match created {
+                Ok(()) => Ok(staging),
+                Err(e) => {
+                    staging.destroy(vk_base, command_pool);
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    fn wait(&self, vk_base: &VkBase) -> Result<()> {
+        if self.submitted {
+            vk_base.device.wait_for_fences(&vec![self.fence], u64::MAX)?;
+        }
+        Ok(())
+    }
+
+    fn is_complete(&self, vk_base: &VkBase) -> Result<bool> {
+        if !self.submitted {
+            return Ok(true);
+        }
+        unsafe {
+            vk_base.device.handle
+                .get_fence_status(self.fence)
+                .context("querying upload fence")
+        }
+    }
+
+    fn destroy(self, vk_base: &VkBase, command_pool: vk::CommandPool) {
+        let device = &vk_base.device.handle;
+        unsafe {
+            device.destroy_fence(self.fence, None);
+            if self.cmd_buffer != vk::CommandBuffer::null() {
+                device.free_command_buffers(command_pool, &[self.cmd_buffer]);
+            }
+            device.destroy_buffer(self.buffer, None);
+        }
+        vk_base.allocator.free(&self.allocation);
+    }
+}
+
+struct UploaderState {
+    /// Least recently submitted first
+    ring: VecDeque<StagingBuffer>,
+    /// One-off buffers for uploads bigger than `STAGING_BUFFER_SIZE`
+    oversized: Vec<StagingBuffer>,
+    next_serial: u64,
+}
+
+impl UploaderState {
+    fn find(&self, serial: u64) -> Option<&StagingBuffer> {
+        self.ring
+            .iter()
+            .chain(self.oversized.iter())
+            .find(|staging| staging.submitted && staging.serial == serial)
+    }
+}
+
+/// Staging buffers shared by every upload on a `VkBase`. Batches may be
+/// recorded on any thread: the staging buffers and the command pool their
+/// command buffers come from are only used while the lock is held, and
+/// submissions take the device's queue lock.
+pub struct Uploader {
+    state: Mutex<UploaderState>,
+    /// Separate from `VkBase::command_pool`, which the render thread records
+    /// into without a lock
+    command_pool: Arc<CommandPool>,
+}
+
+impl Uploader {
+    pub fn new(device: &Arc<Device>) -> Result<Uploader> {
+        Ok(Uploader {
+            state: Mutex::new(UploaderState {
+                ring: VecDeque::new(),
+                oversized: Vec::new(),
+                next_serial: 0,
+            }),
+            command_pool: CommandPool::new(device)?,
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, UploaderState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Waits for outstanding uploads and frees the staging buffers and the
+    /// command pool. Called by `VkBase` before it destroys the device.
+    pub fn destroy(&self, vk_base: &VkBase) {
+        let mut guard = self.lock();
+        let state = &mut *guard;
+        let staging_buffers = state.ring.drain(..).chain(state.oversized.drain(..)).collect::<Vec<_>>();
+        for staging in staging_buffers {
+            let _ = staging.wait(vk_base);
+            staging.destroy(vk_base, self.command_pool.handle);
+        }
+        vk_base.device.destroy_command_pool(self.command_pool.handle);
+    }
+}
+
+/// Records uploads until `submit` is called. Uploads recorded on a batch that
+/// is dropped without submitting are still submitted. Holds the uploader lock,
+/// so tickets can't be waited on while a batch is alive on the same thread.
+pub struct UploadBatch<'a> {
+    vk_base: &'a Arc<VkBase>,
+    state: MutexGuard<'a, UploaderState>,
+    current: Option<StagingBuffer>,
+    serials: Vec<u64>,
+}
+
+impl<'a> UploadBatch<'a> {
+    pub fn new(vk_base: &'a Arc<VkBase>) -> UploadBatch<'a> {
+        UploadBatch {
+            vk_base,
+            state: vk_base.uploader.lock(),
+            current: None,
+            serials: Vec::new(),
+        }
+    }
+
+    pub fn vk_base(&self) -> &'a Arc<VkBase> {
+        self.vk_base
+    }
+
+    /// Creates a device-local buffer holding `data`. `TRANSFER_DST` is added
+    /// to `usage`.
+    pub fn buffer<T: Copy>(&mut self, data: &[T], usage: vk::BufferUsageFlags) -> Result<Buffer> {
+        let buffer = Buffer::new(self.vk_base,
+                                 mem::size_of_val(data) as vk::DeviceSize,
+                                 usage | vk::BufferUsageFlags::TRANSFER_DST,
+                                 MemoryLocation::DeviceLocal
+        )?;
+        self.write_buffer(&buffer, 0, data)?;
+
+        Ok(buffer)
+    }
+
+    /// Copies `data` into `dst` at byte `offset`
+    pub fn write_buffer<T: Copy>(&mut self, dst: &Buffer, offset: vk::DeviceSize, data: &[T]) -> Result<()> {
+        let bytes = as_bytes(data);
+        let size = bytes.len() as vk::DeviceSize;
+        if !offset.checked_add(size).is_some_and(|end| end <= dst.size) {
+            return Err(Error::UploadOutOfBounds { offset, len: size, size: dst.size });
+        }
+        if size == 0 {
+            return Ok(());
+        }
+
+        let (cmd_buffer, src, src_offset) = self.stage(&[bytes], STAGING_ALIGNMENT)?;
+        let device = &self.vk_base.device.handle;
+
+        unsafe {
+            device.cmd_copy_buffer(
+                cmd_buffer,
+                src,
+                dst.handle,
+                &[vk::BufferCopy { src_offset: src_offset[0], dst_offset: offset, size }],
+            );
+            device.cmd_pipeline_barrier(
+                cmd_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[vk::BufferMemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .buffer(dst.handle)
+                    .offset(offset)
+                    .size(size)
+                    .build()],
+                &[],
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Creates a device-local image from `levels`, see `write_image`.
+    /// `TRANSFER_DST` is added to the usage.
+    pub fn image(&mut self,
+                 info: &vk::ImageCreateInfo,
+                 levels: &[&[u8]],
+                 final_layout: vk::ImageLayout,
+    ) -> Result<Image> {
+        let mut info = *info;
+        info.usage |= vk::ImageUsageFlags::TRANSFER_DST;
+        let image = Image::new(self.vk_base, &info, MemoryLocation::DeviceLocal)?;
+        self.write_image(&image, levels, final_layout)?;
+
+        Ok(image)
+    }
+
+    /// Replaces the contents of a color image. `levels[i]` holds every array
+    /// layer of mip level `i`, tightly packed. Mip levels past `levels.len()`
+    /// are left undefined. Afterwards the whole image is in `final_layout`.
+    ///
+    /// More levels than `dst` has are an error, and so are levels shorter than
+    /// their size in `dst.format`, for the formats `texel_block` knows.
+    pub fn write_image(&mut self,
+                       dst: &Image,
+                       levels: &[&[u8]],
+                       final_layout: vk::ImageLayout,
+    ) -> Result<()> {
+        if levels.len() > dst.mip_levels as usize {
+            return Err(Error::TooManyImageLevels { levels: levels.len(), mip_levels: dst.mip_levels });
+        }
+        let mut alignment = STAGING_ALIGNMENT;
+        if let Some(block) = texel_block(dst.format) {
+            for (level, data) in levels.iter().enumerate() {
+                let expected = level_size(dst, level as u32, block);
+                if (data.len() as u64) < expected {
+                    return Err(Error::ImageLevelTooShort { level: level as u32, len: data.len(), expected });
+                }
+            }
+            // `bufferOffset` has to be a multiple of the block size, e.g. 3
+            // bytes for R8G8B8
+            alignment = lcm(STAGING_ALIGNMENT, block.2);
+        }
+
+        let (cmd_buffer, src, offsets) = self.stage(levels, alignment)?;
+        let device = &self.vk_base.device.handle;
+        let range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: dst.mip_levels,
+            base_array_layer: 0,
+            layer_count: dst.array_layers,
+        };
+
+        let regions = offsets
+            .iter()
+            .enumerate()
+            .map(|(level, &offset)| vk::BufferImageCopy {
+                buffer_offset: offset,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level as u32,
+                    base_array_layer: 0,
+                    layer_count: dst.array_layers,
+                },
+                image_offset: vk::Offset3D::default(),
+                image_extent: vk::Extent3D {
+                    width: (dst.extent.width >> level).max(1),
+                    height: (dst.extent.height >> level).max(1),
+                    depth: (dst.extent.depth >> level).max(1),
+                },
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                cmd_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::empty())
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .old_layout(vk::ImageLayout::UNDEFINED)
+                    .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(dst.handle)
+                    .subresource_range(range)
+                    .build()],
+            );
+
+            if !regions.is_empty() {
+                device.cmd_copy_buffer_to_image(
+                    cmd_buffer,
+                    src,
+                    dst.handle,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &regions,
+                );
+            }
+
+            device.cmd_pipeline_barrier(
+                cmd_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE)
+                    .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .new_layout(final_layout)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(dst.handle)
+                    .subresource_range(range)
+                    .build()],
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Command buffer recording uploads, for work that has to happen in the
+    /// same submission, e.g. generating mip levels
+    pub fn cmd_buffer(&mut self) -> Result<vk::CommandBuffer> {
+        if self.current.is_none() {
+            self.current = Some(self.acquire(0)?);
+        }
+        Ok(self.current.as_ref().expect("a staging buffer was just acquired").cmd_buffer)
+    }
+
+    /// Submits everything recorded so far
+    pub fn submit(mut self) -> Result<UploadTicket> {
+        self.flush()?;
+
+        Ok(UploadTicket {
+            serials: mem::take(&mut self.serials),
+            vk_base: self.vk_base.clone(),
+        })
+    }
+
+    /// Copies `chunks` into staging memory, each at a multiple of `alignment`,
+    /// and returns the command buffer to record copies from them into along
+    /// with the staging buffer and chunk offsets
+    fn stage(&mut self,
+             chunks: &[&[u8]],
+             alignment: vk::DeviceSize,
+    ) -> Result<(vk::CommandBuffer, vk::Buffer, Vec<vk::DeviceSize>)> {
+        let size = chunks
+            .iter()
+            .map(|chunk| align_up(chunk.len() as vk::DeviceSize, alignment))
+            .sum::<vk::DeviceSize>();
+
+        let fits = self
+            .current
+            .as_ref()
+            .map_or(false, |staging| align_up(staging.used, alignment) + size <= staging.size);
+        if !fits {
+            self.flush()?;
+            self.current = Some(self.acquire(size)?);
+        }
+
+        let staging = self.current.as_mut().expect("a staging buffer was just acquired");
+        let mapped = staging.allocation.mapped_ptr().expect("staging memory is host visible");
+        let mut offset = align_up(staging.used, alignment);
+        let mut offsets = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            unsafe {
+                ptr::copy_nonoverlapping(chunk.as_ptr(), mapped.add(offset as usize), chunk.len());
+            }
+            offsets.push(offset);
+            offset = align_up(offset + chunk.len() as vk::DeviceSize, alignment);
+        }
+        staging.used = offset;
+
+        Ok((staging.cmd_buffer, staging.buffer, offsets))
+    }
+
+    /// Takes a staging buffer with room for `size` bytes and begins its
+    /// command buffer
+    fn acquire(&mut self, size: vk::DeviceSize) -> Result<StagingBuffer> {
+        let vk_base: &'a VkBase = self.vk_base;
+        let command_pool = vk_base.uploader.command_pool.handle;
+
+        // Reclaim finished one-off buffers
+        let mut index = 0;
+        while index < self.state.oversized.len() {
+            if self.state.oversized[index].is_complete(vk_base)? {
+                self.state.oversized.swap_remove(index).destroy(vk_base, command_pool);
+            } else {
+                index += 1;
+            }
+        }
+
+        let staging = if size > STAGING_BUFFER_SIZE {
+            StagingBuffer::new(vk_base, command_pool, size)?
+        } else {
+            let front_idle = match self.state.ring.front() {
+                Some(front) => front.is_complete(vk_base)?,
+                None => false,
+            };
+            if !front_idle && self.state.ring.len() < STAGING_BUFFER_COUNT {
+                StagingBuffer::new(vk_base, command_pool, STAGING_BUFFER_SIZE)?
+            } else {
+                self.state.ring.pop_front().expect("the ring is full or has an idle buffer")
+            }
+        };
+
+        let begun = staging.wait(vk_base).and_then(|_| unsafe {
+            vk_base.device.handle
+                .reset_fences(&[staging.fence])
+                .context("resetting upload fence")?;
+            vk_base.device.begin_command_buffer(staging.cmd_buffer)
+        });
+        if let Err(e) = begun {
+            staging.destroy(vk_base, command_pool);
+            return Err(e);
+        }
+
+        Ok(StagingBuffer { used: 0, submitted: false, ..staging })
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let mut staging = match self.current.take() {
+            Some(staging) => staging,
+            None => return Ok(()),
+        };
+
+        let device = &self.vk_base.device;
+        let submitted = device
+            .end_command_buffer(staging.cmd_buffer)
+            .and_then(|_| device.queue_submit(staging.cmd_buffer, staging.fence));
+
+        if submitted.is_ok() {
+            self.state.next_serial += 1;
+            staging.serial = self.state.next_serial;
+            staging.submitted = true;
+            self.serials.push(staging.serial);
+        }
+        if staging.size > STAGING_BUFFER_SIZE {
+            self.state.oversized.push(staging);
+        } else {
+            self.state.ring.push_back(staging);
+        }
+
+        submitted
+    }
+}
+
+impl Drop for UploadBatch<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            println!("Error submitting uploads: {}", e);
+        }
+    }
+}
+
+/// Completion of the submissions of one `UploadBatch`
+pub struct UploadTicket {
+    serials: Vec<u64>,
+    vk_base: Arc<VkBase>,
+}
+
+impl UploadTicket {
+    /// Blocks until the uploads have completed on the GPU
+    pub fn wait(&self) -> Result<()> {
+        let state = self.vk_base.uploader.lock();
+        for serial in &self.serials {
+            // Staging buffers are only reused after their submission completed
+            if let Some(staging) = state.find(*serial) {
+                staging.wait(&self.vk_base)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_complete(&self) -> Result<bool> {
+        let state = self.vk_base.uploader.lock();
+        for serial in &self.serials {
+            if let Some(staging) = state.find(*serial) {
+                if !staging.is_complete(&self.vk_base)? {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Width, height and size in bytes of one texel block of the uncompressed and
+/// compressed color formats textures are loaded in. `None` for other formats.
+pub fn texel_block(format: vk::Format) -> Option<(u32, u32, u64)> {
+    use vk::Format as F;
+    Some(match format {
+        F::R8_UNORM | F::R8_SRGB => (1, 1, 1),
+        F::R8G8_UNORM | F::R8G8_SRGB => (1, 1, 2),
+        F::R8G8B8_UNORM | F::R8G8B8_SRGB | F::B8G8R8_UNORM | F::B8G8R8_SRGB => (1, 1, 3),
+        F::R8G8B8A8_UNORM | F::R8G8B8A8_SRGB | F::B8G8R8A8_UNORM | F::B8G8R8A8_SRGB => (1, 1, 4),
+        F::R16G16B16A16_SFLOAT => (1, 1, 8),
+        F::R32G32B32A32_SFLOAT => (1, 1, 16),
+        F::BC1_RGB_UNORM_BLOCK | F::BC1_RGB_SRGB_BLOCK
+        | F::BC1_RGBA_UNORM_BLOCK | F::BC1_RGBA_SRGB_BLOCK
+        | F::ETC2_R8G8B8_UNORM_BLOCK | F::ETC2_R8G8B8_SRGB_BLOCK
+        | F::ETC2_R8G8B8A1_UNORM_BLOCK | F::ETC2_R8G8B8A1_SRGB_BLOCK => (4, 4, 8),
+        F::BC2_UNORM_BLOCK | F::BC2_SRGB_BLOCK
+        | F::BC3_UNORM_BLOCK | F::BC3_SRGB_BLOCK
+        | F::BC7_UNORM_BLOCK | F::BC7_SRGB_BLOCK
+        | F::ETC2_R8G8B8A8_UNORM_BLOCK | F::ETC2_R8G8B8A8_SRGB_BLOCK
+        | F::ASTC_4X4_UNORM_BLOCK | F::ASTC_4X4_SRGB_BLOCK => (4, 4, 16),
+        F::ASTC_5X5_UNORM_BLOCK | F::ASTC_5X5_SRGB_BLOCK => (5, 5, 16),
+        F::ASTC_6X6_UNORM_BLOCK | F::ASTC_6X6_SRGB_BLOCK => (6, 6, 16),
+        F::ASTC_8X8_UNORM_BLOCK | F::ASTC_8X8_SRGB_BLOCK => (8, 8, 16),
+        _ => return None,
+    })
+}
+
+/// Bytes of every array layer of mip `level` of `image`, tightly packed
+fn level_size(image: &Image, level: u32, (block_width, block_height, block_bytes): (u32, u32, u64)) -> u64 {
+    let blocks = |extent: u32, block: u32| (extent >> level).max(1).div_ceil(block);
+    blocks(image.extent.width, block_width) as u64
+        * blocks(image.extent.height, block_height) as u64
+        * (image.extent.depth >> level).max(1) as u64
+        * image.array_layers as u64
+        * block_bytes
+}
+
+fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data)) }
+}
+
+fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    value.div_ceil(alignment) * alignment
+}
+
+fn lcm(a: vk::DeviceSize, b: vk::DeviceSize) -> vk::DeviceSize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    a / x * b
+}
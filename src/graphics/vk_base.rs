@@ -1,14 +1,34 @@
-use std::sync::{Arc};
-
-use crate::graphics::{
-    command_buffer::CommandBuffer,
-    command_pool::CommandPool,
-    device::Device,
-    fence::Fence,
-    vk_instance::VkInstance,
-    physical_device::PhysicalDevice,
+use ash::{vk::{self}};
+use std::{
+    path::PathBuf,
+    sync::{Arc},
 };
 
+use crate::{
+    error::{Error, Result},
+    graphics::{
+        command_buffer::CommandBuffer,
+        command_pool::CommandPool,
+        descriptor::DescriptorLayoutCache,
+        device::Device,
+        fence::Fence,
+        memory::Allocator,
+        pipeline_cache::PipelineCache,
+        upload::{UploadBatch, Uploader},
+        vk_instance::VkInstance,
+        physical_device::PhysicalDevice,
+        sampler::SamplerCache,
+    },
+    math::DepthRange
+};
+
+/// Depth formats tried in order when none are configured
+pub const DEPTH_FORMATS: &[vk::Format] = &[
+    vk::Format::D32_SFLOAT,
+    vk::Format::D24_UNORM_S8_UINT,
+    vk::Format::D16_UNORM,
+];
+
 pub struct VkBase {
     pub command_buffers: Arc<CommandBuffer>,
     pub command_pool: Arc<CommandPool>,
@@ -16,37 +36,104 @@ pub struct VkBase {
     pub fences: Arc<Fence>,
     pub vk_instance: Arc<VkInstance>,
     pub physical_device: Arc<PhysicalDevice>,
+    pub allocator: Allocator,
+    pub uploader: Uploader,
+    pub samplers: SamplerCache,
+    pub descriptor_layouts: DescriptorLayoutCache,
+    /// Used by every pipeline the crate creates, saved when `VkBase` drops
+    pub pipeline_cache: PipelineCache,
+    /// Format of the depth attachments of the library render pass
+    pub depth_format: vk::Format,
+    pub depth_range: DepthRange,
 }
 
 impl VkBase {
-    pub fn new(xr_instance: &openxr::Instance, system_id: openxr::SystemId) -> Arc<VkBase> {
-        let vk_instance = VkInstance::new(&xr_instance, system_id);
+    /// `depth_formats` lists acceptable depth formats in order of preference.
+    /// The pipeline cache is loaded from and saved to `pipeline_cache_path`,
+    /// or only kept in memory if it is `None`.
+    pub fn new(xr_instance: &openxr::Instance,
+               system_id: openxr::SystemId,
+               depth_range: DepthRange,
+               depth_formats: &[vk::Format],
+               pipeline_cache_path: Option<PathBuf>,
+    ) -> Result<Arc<VkBase>> {
+        let vk_instance = VkInstance::new(&xr_instance, system_id)?;
 
         let physical_device = PhysicalDevice::new(&xr_instance,
                                                   &vk_instance,
                                                   system_id
-        );
+        )?;
 
         let device = Device::new(&xr_instance,
                                  &vk_instance,
                                  &physical_device,
                                  system_id
-        );
+        )?;
+
+        Self::from_device(vk_instance, physical_device, device, depth_range, depth_formats, pipeline_cache_path)
+    }
+
+    /// Creates Vulkan objects directly through ash, for rendering without an
+    /// OpenXR session. Works with software implementations such as lavapipe.
+    pub fn headless() -> Result<Arc<VkBase>> {
+        let vk_instance = VkInstance::headless()?;
+
+        let physical_device = PhysicalDevice::headless(&vk_instance)?;
+
+        let device = Device::headless(&vk_instance, &physical_device)?;
+
+        Self::from_device(vk_instance, physical_device, device, DepthRange::default(), DEPTH_FORMATS, None)
+    }
 
-        let command_pool = CommandPool::new(&device);
+    fn from_device(vk_instance: Arc<VkInstance>,
+                   physical_device: Arc<PhysicalDevice>,
+                   device: Arc<Device>,
+                   depth_range: DepthRange,
+                   depth_formats: &[vk::Format],
+                   pipeline_cache_path: Option<PathBuf>,
+    ) -> Result<Arc<VkBase>> {
+        let depth_format = physical_device
+            .find_depth_format(&vk_instance, depth_formats)
+            .ok_or(Error::NoSupportedDepthFormat)?;
 
-        let command_buffers = CommandBuffer::new(&device, &command_pool);
+        let allocator = Allocator::new(&device.handle, &physical_device);
 
-        let fences = Fence::new(&device);
+        let command_pool = CommandPool::new(&device)?;
 
-        Arc::new(VkBase {
+        let command_buffers = CommandBuffer::new(&device, &command_pool)?;
+
+        let fences = Fence::new(&device)?;
+
+        let uploader = Uploader::new(&device)?;
+
+        let samplers = SamplerCache::new(&device.handle);
+
+        let descriptor_layouts = DescriptorLayoutCache::new(&device.handle);
+
+        let pipeline_cache = PipelineCache::new(&device.handle, &physical_device.properties, pipeline_cache_path)?;
+
+        Ok(Arc::new(VkBase {
             command_buffers: command_buffers,
             command_pool: command_pool,
             device: device,
             fences: fences,
             vk_instance: vk_instance,
             physical_device: physical_device,
-        })
+            allocator: allocator,
+            uploader: uploader,
+            samplers: samplers,
+            descriptor_layouts: descriptor_layouts,
+            pipeline_cache: pipeline_cache,
+            depth_format: depth_format,
+            depth_range: depth_range,
+        }))
+    }
+}
+
+impl VkBase {
+    /// Starts recording uploads into device-local memory
+    pub fn begin_upload(self: &Arc<Self>) -> UploadBatch<'_> {
+        UploadBatch::new(self)
     }
 }
 
@@ -54,7 +141,15 @@ impl Drop for VkBase {
     fn drop(&mut self) {
         println!("Dropping VkBase");
 
-        self.device.device_wait_idle();
+        let _ = self.device.device_wait_idle();
+        self.uploader.destroy(self);
+        self.samplers.destroy();
+        self.descriptor_layouts.destroy();
+        if let Err(e) = self.pipeline_cache.save() {
+            println!("Error saving pipeline cache: {}", e);
+        }
+        self.pipeline_cache.destroy();
+        self.allocator.free_all();
         self.device.destroy_fences(&self.fences.handle);
         self.device.destroy_command_pool(self.command_pool.handle);
         self.device.destroy_device();
@@ -2,15 +2,19 @@ use std::sync::{Arc};
 
 use ash::{vk::{self, Handle}};
 
+use crate::error::{Context, Result};
+
 pub struct VkInstance {
+    // Keeps the Vulkan loader loaded for as long as the instance lives
+    pub entry: ash::Entry,
     pub handle: ash::Instance
 }
 
 impl VkInstance {
     pub fn new(xr_instance: &openxr::Instance,
                system_id: openxr::SystemId,
-    ) -> Arc<VkInstance> {
-        let entry = unsafe { ash::Entry::load().unwrap() };
+    ) -> Result<Arc<VkInstance>> {
+        let entry = unsafe { ash::Entry::load()? };
 
         let api_version = vk::make_api_version(0, 1, 1, 0);
         let application_info = vk::ApplicationInfo::builder()
@@ -27,9 +31,9 @@ impl VkInstance {
                         &vk::InstanceCreateInfo::builder().application_info(&application_info) as *const _
                             as *const _,
                     )
-                    .expect("OpenXR error creating Vulkan instance")
+                    .context("creating Vulkan instance")?
                     .map_err(vk::Result::from_raw)
-                    .expect("Vulkan error creating Vulkan instance");
+                    .context("creating Vulkan instance")?;
 
                 ash::Instance::load(
                     entry.static_fn(),
@@ -38,9 +42,35 @@ impl VkInstance {
             }
         };
 
-        Arc::new(VkInstance {
+        Ok(Arc::new(VkInstance {
+            entry,
+            handle
+        }))
+    }
+
+    /// Creates the instance directly through ash, without an OpenXR runtime
+    pub fn headless() -> Result<Arc<VkInstance>> {
+        let entry = unsafe { ash::Entry::load()? };
+
+        let api_version = vk::make_api_version(0, 1, 1, 0);
+        let application_info = vk::ApplicationInfo::builder()
+            .application_version(0)
+            .engine_version(0)
+            .api_version(api_version);
+
+        let handle = unsafe {
+            entry
+                .create_instance(
+                    &vk::InstanceCreateInfo::builder().application_info(&application_info),
+                    None,
+                )
+                .context("creating Vulkan instance")?
+        };
+
+        Ok(Arc::new(VkInstance {
+            entry,
             handle
-        })
+        }))
     }
 
     pub fn destroy_instance(&self) {
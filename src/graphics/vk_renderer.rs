@@ -1,57 +1,101 @@
-use std::sync::{Arc};
+use std::{
+    path::{Path},
+    sync::{Arc},
+};
 
 use ash::{vk::self};
+use glam::{Mat4, Vec4};
 
 use crate::{
+    assets::model::{Model, ModelData},
+    error::{Error, Result},
     graphics::{
+        camera::{CameraBuffers, MAX_VIEWS},
+        capture::CaptureRequest,
         framebuffers::Framebuffers,
-        pipeline::Pipeline,
+        hot_reload::{ReloadablePipeline, ShaderWatcher},
+        pipeline::{MeshPushConstants, Pipeline},
         render_pass::RenderPass,
-        vk_base::VkBase
+        shader_module::ShaderCompiler,
+        vk_base::VkBase,
+        PIPELINE_DEPTH
     },
     Renderer,
-    xr::{swapchain::Swapchain}
+    xr::{frame::FrameContext, swapchain::Swapchain}
 };
 
 pub struct VkRenderer {
-    pub pipeline: Arc<Pipeline>,
+    pub pipeline: Pipeline,
+    pub mesh_pipeline: Pipeline,
+    /// Drawn every frame at their own origin with `mesh_pipeline`
+    pub models: Vec<Model>,
     pub render_pass: Arc<RenderPass>,
     pub framebuffers: Arc<Framebuffers>,
     pub vk_base: Arc<VkBase>,
-    pub frame: usize
+    pub frame: usize,
+    pub pending_capture: Option<CaptureRequest>,
+    pub camera: CameraBuffers,
+    /// Drawn instead of `pipeline` and `mesh_pipeline` once `watch_shaders`
+    /// was called
+    watched_shaders: Option<WatchedShaders>,
 }
 
-const PIPELINE_DEPTH: u32 = 2;
+struct WatchedShaders {
+    watcher: ShaderWatcher,
+    pipeline: ReloadablePipeline,
+    mesh_pipeline: ReloadablePipeline,
+}
 
 impl Renderer for VkRenderer {
-    fn new(vk_base: Arc<VkBase>, swapchain: &Swapchain) -> Self {
-        let render_pass = RenderPass::new(&vk_base.device);
+    fn new(vk_base: Arc<VkBase>, swapchain: &Swapchain) -> Result<Self> {
+        if swapchain.view_count as usize > MAX_VIEWS {
+            return Err(Error::TooManyViews { view_count: swapchain.view_count, max_views: MAX_VIEWS as u32 });
+        }
+        let render_pass = RenderPass::new(&vk_base.device, swapchain.view_count, vk_base.depth_format)?;
 
-        let pipeline = Pipeline::new(&vk_base.device, &render_pass);
+        let camera = CameraBuffers::new(&vk_base)?;
 
-        let framebuffers = Framebuffers::new(&swapchain, &vk_base.device, &render_pass);
+        let pipeline = Pipeline::triangle(&vk_base, render_pass.handle, &[camera.descriptor_set_layout])?;
+        let mesh_pipeline = Pipeline::mesh(&vk_base, render_pass.handle, &[camera.descriptor_set_layout])?;
+
+        let framebuffers = Framebuffers::new(&swapchain, &vk_base, &render_pass)?;
 
         let frame = 0;
 
-        VkRenderer {
+        Ok(VkRenderer {
             pipeline,
+            mesh_pipeline,
+            models: Vec::new(),
             render_pass,
             framebuffers,
             vk_base,
-            frame
-        }
+            frame,
+            pending_capture: None,
+            camera,
+            watched_shaders: None,
+        })
     }
 
-    fn draw(&mut self, swapchain: &mut Swapchain) {
+    fn draw(&mut self, swapchain: &mut Swapchain, frame: &FrameContext) -> Result<()> {
+        self.reload_changed_shaders();
+        self.camera.update(self.frame, frame)?;
+
         let cmd_buffer = self.vk_base.command_buffers.handle[self.frame];
-        self.vk_base.device.begin_command_buffer(cmd_buffer);
+        self.vk_base.device.begin_command_buffer(cmd_buffer)?;
 
-        let image_index = swapchain.handle.acquire_image().unwrap();
-        let framebuffer = self.framebuffers.handle[image_index as usize].framebuffer;
+        {
+            let _queue = self.vk_base.device.lock_queue();
+            swapchain.acquire_image()?;
+        }
+        let framebuffer = self.framebuffers
+            .current(swapchain)
+            .expect("swapchain images were just acquired")
+            .framebuffer;
         self.vk_base.device.cmd_begin_render_pass(cmd_buffer,
                                           self.render_pass.handle,
                                           framebuffer,
-                                          swapchain.resolution
+                                          swapchain.resolution,
+                                          self.vk_base.depth_range.clear_depth()
         );
         let viewports = vk::Viewport {
             x: 0.0,
@@ -66,18 +110,157 @@ impl Renderer for VkRenderer {
             extent: swapchain.resolution,
         };
         self.vk_base.device.cmd_set_viewport_and_scissor(cmd_buffer, viewports, scissors);
-        self.vk_base.device.cmd_bind_pipeline(cmd_buffer, self.pipeline.handle);
+        let (pipeline, _) = self.pipelines();
+        self.vk_base.device.cmd_bind_pipeline(cmd_buffer, pipeline.handle);
+        self.camera.cmd_bind(cmd_buffer, pipeline.pipeline_layout, self.frame);
         self.vk_base.device.cmd_draw(cmd_buffer, 3, 1, 0, 0);
+        self.cmd_draw_models(cmd_buffer);
         self.vk_base.device.cmd_end_render_pass(cmd_buffer);
 
-        self.vk_base.device.end_command_buffer(cmd_buffer);
+        self.vk_base.device.end_command_buffer(cmd_buffer)?;
 
-        self.vk_base.device.queue_submit(cmd_buffer, self.vk_base.fences.handle[self.frame]);
+        self.vk_base.device.queue_submit(cmd_buffer, self.vk_base.fences.handle[self.frame])?;
 
-        self.vk_base.device.wait_for_fences(&[self.vk_base.fences.handle[self.frame]].to_vec(), u64::MAX);
-        self.vk_base.device.reset_fences(self.vk_base.fences.handle[self.frame]);
+        self.vk_base.device.wait_for_fences(&[self.vk_base.fences.handle[self.frame]].to_vec(), u64::MAX)?;
+        self.vk_base.device.reset_fences(self.vk_base.fences.handle[self.frame])?;
+
+        if let Some(request) = self.pending_capture.take() {
+            match swapchain
+                .capture(&self.vk_base, request.view)
+                .and_then(|image| image.save_png(&request.path))
+            {
+                Ok(()) => println!("Captured frame to {}", request.path.display()),
+                Err(e) => println!("Error capturing frame: {}", e),
+            }
+        }
 
         self.frame = (self.frame + 1) % PIPELINE_DEPTH as usize;
+
+        Ok(())
+    }
+}
+
+impl VkRenderer {
+    /// Saves the next frame drawn to `request.path` as a PNG
+    pub fn capture_next_frame(&mut self, request: CaptureRequest) {
+        self.pending_capture = Some(request);
+    }
+
+    /// Adds a model to draw from the next frame on, returning its index in `models`
+    pub fn add_model(&mut self, model: Model) -> usize {
+        self.models.push(model);
+        self.models.len() - 1
+    }
+
+    /// Loads, uploads and adds an OBJ model. See `ModelData::from_obj_file`.
+    pub fn load_obj(&mut self, path: impl AsRef<Path>) -> Result<usize> {
+        let data = ModelData::from_obj_file(path)?;
+        for warning in &data.warnings {
+            println!("Warning loading OBJ: {}", warning);
+        }
+
+        let mut batch = self.vk_base.begin_upload();
+        let model = data.upload(&mut batch)?;
+        batch.submit()?.wait()?;
+
+        Ok(self.add_model(model))
+    }
+
+    /// Compiles the triangle and mesh shaders from their sources in `dir`,
+    /// e.g. `src/graphics/shaders` of a checkout of this crate, and rebuilds
+    /// their pipeline on the next frame whenever a source changes. A shader
+    /// that fails to compile keeps the previous pipeline.
+    pub fn watch_shaders(&mut self, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+        let set_layouts = [self.camera.descriptor_set_layout];
+        let compiler = ShaderCompiler::new().define("MULTIVIEW", "1");
+        let stages = |name: &str| [
+            (vk::ShaderStageFlags::VERTEX, dir.join(format!("{}.vert.wgsl", name))),
+            (vk::ShaderStageFlags::FRAGMENT, dir.join(format!("{}.frag.wgsl", name))),
+        ];
+
+        let pipeline = ReloadablePipeline::new(&self.vk_base,
+                                               Pipeline::triangle_builder(self.render_pass.handle, &set_layouts),
+                                               compiler.clone(),
+                                               &stages("triangle")
+        )?;
+        let mesh_pipeline = ReloadablePipeline::new(&self.vk_base,
+                                                    Pipeline::mesh_builder(self.render_pass.handle, &set_layouts),
+                                                    compiler,
+                                                    &stages("mesh")
+        )?;
+
+        let mut watcher = ShaderWatcher::new()?;
+        pipeline.watch(&mut watcher)?;
+        mesh_pipeline.watch(&mut watcher)?;
+
+        self.watched_shaders = Some(WatchedShaders {
+            watcher,
+            pipeline,
+            mesh_pipeline,
+        });
+        Ok(())
+    }
+
+    fn reload_changed_shaders(&mut self) {
+        if let Some(watched) = &mut self.watched_shaders {
+            let changed = watched.watcher.changed();
+            for pipeline in [&mut watched.pipeline, &mut watched.mesh_pipeline] {
+                if pipeline.reload_changed(&self.vk_base, &changed) {
+                    // Sources may include new files now
+                    if let Err(e) = pipeline.watch(&mut watched.watcher) {
+                        println!("Error watching shader files: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The triangle and mesh pipelines to draw with
+    fn pipelines(&self) -> (&Pipeline, &Pipeline) {
+        match &self.watched_shaders {
+            Some(watched) => (&watched.pipeline, &watched.mesh_pipeline),
+            None => (&self.pipeline, &self.mesh_pipeline),
+        }
+    }
+
+    /// Draws every mesh node of `models` with its material's base color.
+    /// Textures aren't bound yet.
+    fn cmd_draw_models(&self, cmd_buffer: vk::CommandBuffer) {
+        if self.models.is_empty() {
+            return;
+        }
+        let device = &self.vk_base.device;
+        let (_, mesh_pipeline) = self.pipelines();
+        device.cmd_bind_pipeline(cmd_buffer, mesh_pipeline.handle);
+        self.camera.cmd_bind(cmd_buffer, mesh_pipeline.pipeline_layout, self.frame);
+
+        for model in &self.models {
+            let mut stack = model.roots.iter().map(|&root| (root, Mat4::IDENTITY)).collect::<Vec<_>>();
+            while let Some((index, parent_transform)) = stack.pop() {
+                let node = &model.nodes[index];
+                let transform = parent_transform * node.transform;
+                stack.extend(node.children.iter().map(|&child| (child, transform)));
+
+                let mesh = match node.mesh.and_then(|mesh| model.meshes[mesh].as_ref()) {
+                    Some(mesh) => mesh,
+                    None => continue,
+                };
+                device.cmd_bind_mesh(cmd_buffer, &mesh.mesh);
+                for (range, material) in mesh.mesh.ranges.iter().zip(&mesh.materials) {
+                    let constants = MeshPushConstants {
+                        model: transform,
+                        base_color: material.map_or(Vec4::ONE, |material| model.materials[material].base_color_factor),
+                    };
+                    device.cmd_push_constants(cmd_buffer,
+                                              mesh_pipeline.pipeline_layout,
+                                              MeshPushConstants::STAGES,
+                                              &constants
+                    );
+                    device.cmd_draw_mesh_range(cmd_buffer, &mesh.mesh, *range, 1);
+                }
+            }
+        }
     }
 }
 
@@ -85,13 +268,10 @@ impl Drop for VkRenderer {
     fn drop(&mut self) {
         println!("Dropping VkRenderer");
 
-        self.vk_base.device.device_wait_idle();
+        let _ = self.vk_base.device.device_wait_idle();
         for framebuffer in &self.framebuffers.handle {
-            self.vk_base.device.destroy_framebuffer(framebuffer.framebuffer);
-            self.vk_base.device.destroy_image_view(framebuffer.color);
+            framebuffer.destroy(&self.vk_base);
         }
         self.vk_base.device.destroy_render_pass(self.render_pass.handle);
-        self.vk_base.device.destroy_pipeline(self.pipeline.handle);
-        self.vk_base.device.destroy_pipeline_layout(self.pipeline.pipeline_layout);
     }
 }
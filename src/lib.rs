@@ -1,14 +1,23 @@
 use std::sync::{Arc};
 
 use crate::graphics::{vk_base::VkBase};
-use crate::xr::{swapchain::Swapchain};
+use crate::xr::{frame::FrameContext, swapchain::Swapchain};
 
 pub mod app;
+pub mod assets;
+pub mod error;
 pub mod graphics;
+pub mod math;
 pub mod xr;
 
+pub use crate::error::{Error, Result};
+pub use ash;
+
+// Lets derive macros name `::xrrs` paths from inside this crate too
+extern crate self as xrrs;
+
 pub trait Renderer {
-    fn new(vk_base: Arc<VkBase>, swapchain: &Swapchain) -> Self;
+    fn new(vk_base: Arc<VkBase>, swapchain: &Swapchain) -> Result<Self> where Self: Sized;
 
-    fn draw(&mut self, swapchain: &mut Swapchain);
+    fn draw(&mut self, swapchain: &mut Swapchain, frame: &FrameContext) -> Result<()>;
 }
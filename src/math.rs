@@ -0,0 +1,78 @@
+use ash::{vk::{self}};
+use glam::{Mat4, Quat, Vec3};
+use openxr as xr;
+
+/// Where depth maps to in clip space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthRange {
+    pub near: f32,
+    /// `f32::INFINITY` gives an infinite far plane
+    pub far: f32,
+    /// Maps `near` to 1.0 and `far` to 0.0 for better depth precision. Depth
+    /// tests then need `GREATER` instead of `LESS` and a clear value of 0.0.
+    pub reverse_z: bool,
+}
+
+impl DepthRange {
+    /// Depth buffer value of the far plane
+    pub fn clear_depth(&self) -> f32 {
+        if self.reverse_z { 0.0 } else { 1.0 }
+    }
+
+    pub fn compare_op(&self) -> vk::CompareOp {
+        if self.reverse_z {
+            vk::CompareOp::GREATER_OR_EQUAL
+        } else {
+            vk::CompareOp::LESS_OR_EQUAL
+        }
+    }
+}
+
+impl Default for DepthRange {
+    fn default() -> Self {
+        DepthRange {
+            near: 0.05,
+            far: 100.0,
+            reverse_z: false,
+        }
+    }
+}
+
+pub fn pose_to_mat4(pose: &xr::Posef) -> Mat4 {
+    Mat4::from_rotation_translation(
+        Quat::from_xyzw(pose.orientation.x, pose.orientation.y, pose.orientation.z, pose.orientation.w),
+        Vec3::new(pose.position.x, pose.position.y, pose.position.z),
+    )
+}
+
+/// Transforms from the pose's reference space into eye space
+pub fn view_from_pose(pose: &xr::Posef) -> Mat4 {
+    pose_to_mat4(pose).inverse()
+}
+
+/// Asymmetric perspective projection for Vulkan clip space (y down, depth in
+/// 0..1), following `XrMatrix4x4f_CreateProjectionFov` from the OpenXR SDK
+pub fn projection_from_fov(fov: &xr::Fovf, depth: DepthRange) -> Mat4 {
+    let tan_left = fov.angle_left.tan();
+    let tan_right = fov.angle_right.tan();
+    let tan_down = fov.angle_down.tan();
+    let tan_up = fov.angle_up.tan();
+
+    let tan_width = tan_right - tan_left;
+    let tan_height = tan_down - tan_up;
+
+    let DepthRange { near, far, reverse_z } = depth;
+    let (z_scale, z_offset) = match (far.is_finite(), reverse_z) {
+        (true, false) => (-far / (far - near), -(far * near) / (far - near)),
+        (true, true) => (near / (far - near), (far * near) / (far - near)),
+        (false, false) => (-1.0, -near),
+        (false, true) => (0.0, near),
+    };
+
+    Mat4::from_cols_array(&[
+        2.0 / tan_width, 0.0, 0.0, 0.0,
+        0.0, 2.0 / tan_height, 0.0, 0.0,
+        (tan_right + tan_left) / tan_width, (tan_up + tan_down) / tan_height, z_scale, -1.0,
+        0.0, 0.0, z_offset, 0.0,
+    ])
+}
@@ -1,55 +1,543 @@
 use openxr as xr;
+use std::collections::{HashMap};
+
+use crate::{
+    error::{Context, Error, Result},
+    xr::{
+        bindings::{Input, BUILTIN_PROFILES},
+        frame::TrackedPose,
+    }
+};
+
+pub const LEFT_HAND: &str = "/user/hand/left";
+pub const RIGHT_HAND: &str = "/user/hand/right";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActionType {
+    Bool,
+    Float,
+    Vector2,
+    Pose,
+    Haptic,
+}
+
+#[derive(Clone, Debug)]
+pub struct ActionDesc {
+    pub name: String,
+    pub localized_name: String,
+    pub action_type: ActionType,
+    /// Top level user paths, e.g. `LEFT_HAND`, the action's state can be
+    /// queried for separately
+    pub subaction_paths: Vec<String>,
+    /// Bound on every built-in interaction profile that has this input. Must
+    /// suit `action_type`, see `Input::accepts`.
+    pub input: Option<Input>,
+}
+
+impl ActionDesc {
+    pub fn new(name: &str, localized_name: &str, action_type: ActionType) -> Self {
+        ActionDesc {
+            name: name.to_owned(),
+            localized_name: localized_name.to_owned(),
+            action_type,
+            subaction_paths: Vec::new(),
+            input: None,
+        }
+    }
+
+    pub fn input(mut self, input: Input) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    /// Declares the action for both hands
+    pub fn hands(mut self) -> Self {
+        self.subaction_paths = vec![LEFT_HAND.to_owned(), RIGHT_HAND.to_owned()];
+        self
+    }
+
+    pub fn subaction_path(mut self, path: &str) -> Self {
+        self.subaction_paths.push(path.to_owned());
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ActionSetDesc {
+    pub name: String,
+    pub localized_name: String,
+    pub priority: u32,
+    pub actions: Vec<ActionDesc>,
+}
+
+impl ActionSetDesc {
+    pub fn new(name: &str, localized_name: &str, priority: u32) -> Self {
+        ActionSetDesc {
+            name: name.to_owned(),
+            localized_name: localized_name.to_owned(),
+            priority,
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn action(mut self, action: ActionDesc) -> Self {
+        self.actions.push(action);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BindingDesc {
+    pub interaction_profile: String,
+    /// `"<action set>/<action>"`
+    pub action: String,
+    pub path: String,
+}
+
+/// Declares every action set, action and suggested binding of an app. Actions
+/// are referred to as `"<action set>/<action>"`.
+#[derive(Clone, Debug, Default)]
+pub struct ActionManifest {
+    pub action_sets: Vec<ActionSetDesc>,
+    pub bindings: Vec<BindingDesc>,
+}
+
+impl ActionManifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn action_set(mut self, action_set: ActionSetDesc) -> Self {
+        self.action_sets.push(action_set);
+        self
+    }
+
+    pub fn binding(mut self, interaction_profile: &str, action: &str, path: &str) -> Self {
+        self.bindings.push(BindingDesc {
+            interaction_profile: interaction_profile.to_owned(),
+            action: action.to_owned(),
+            path: path.to_owned(),
+        });
+        self
+    }
+
+    /// Grip poses of both hands
+    pub fn hand_poses() -> Self {
+        ActionManifest::new().action_set(
+            ActionSetDesc::new("input", "input pose information", 0)
+                .action(
+                    ActionDesc::new("left_hand", "Left Hand Controller", ActionType::Pose)
+                        .subaction_path(LEFT_HAND)
+                        .input(Input::GripPose),
+                )
+                .action(
+                    ActionDesc::new("right_hand", "Right Hand Controller", ActionType::Pose)
+                        .subaction_path(RIGHT_HAND)
+                        .input(Input::GripPose),
+                ),
+        )
+    }
+
+    /// Binding paths per interaction profile: the built-in tables for actions
+    /// declared with an `Input`, followed by the explicit bindings. An
+    /// explicit binding replaces the built-in ones of its action and profile.
+    pub fn suggested_bindings(&self) -> Vec<(String, Vec<(String, String)>)> {
+        let mut suggestions: Vec<(String, Vec<(String, String)>)> = Vec::new();
+
+        for profile in BUILTIN_PROFILES {
+            let mut bindings = Vec::new();
+            for set in &self.action_sets {
+                for action in &set.actions {
+                    let input = match action.input {
+                        Some(input) => input,
+                        None => continue,
+                    };
+                    let name = format!("{}/{}", set.name, action.name);
+                    if self
+                        .bindings
+                        .iter()
+                        .any(|b| b.interaction_profile == profile.path && b.action == name)
+                    {
+                        continue;
+                    }
+
+                    for path in profile.paths(input) {
+                        let allowed = action.subaction_paths.is_empty()
+                            || action.subaction_paths.iter().any(|subaction_path| {
+                                path.strip_prefix(subaction_path.as_str())
+                                    .map_or(false, |rest| rest.starts_with('/'))
+                            });
+                        if allowed {
+                            bindings.push((name.clone(), path.to_string()));
+                        }
+                    }
+                }
+            }
+
+            if !bindings.is_empty() {
+                suggestions.push((profile.path.to_owned(), bindings));
+            }
+        }
+
+        for binding in &self.bindings {
+            let pair = (binding.action.clone(), binding.path.clone());
+            match suggestions.iter_mut().find(|(profile, _)| *profile == binding.interaction_profile) {
+                Some((_, bindings)) => bindings.push(pair),
+                None => suggestions.push((binding.interaction_profile.clone(), vec![pair])),
+            }
+        }
+
+        suggestions
+    }
+}
+
+pub enum TypedAction {
+    Bool(xr::Action<bool>),
+    Float(xr::Action<f32>),
+    Vector2(xr::Action<xr::Vector2f>),
+    Pose(xr::Action<xr::Posef>),
+    Haptic(xr::Action<xr::Haptic>),
+}
+
+impl TypedAction {
+    pub fn action_type(&self) -> ActionType {
+        match self {
+            TypedAction::Bool(_) => ActionType::Bool,
+            TypedAction::Float(_) => ActionType::Float,
+            TypedAction::Vector2(_) => ActionType::Vector2,
+            TypedAction::Pose(_) => ActionType::Pose,
+            TypedAction::Haptic(_) => ActionType::Haptic,
+        }
+    }
+
+    fn binding(&self, path: xr::Path) -> xr::Binding<'_> {
+        match self {
+            TypedAction::Bool(action) => xr::Binding::new(action, path),
+            TypedAction::Float(action) => xr::Binding::new(action, path),
+            TypedAction::Vector2(action) => xr::Binding::new(action, path),
+            TypedAction::Pose(action) => xr::Binding::new(action, path),
+            TypedAction::Haptic(action) => xr::Binding::new(action, path),
+        }
+    }
+}
+
+/// Space of a pose action, one per subaction path it was declared with
+pub struct PoseSpace {
+    pub action: String,
+    pub subaction_path: Option<String>,
+    pub space: openxr::Space,
+}
 
 pub struct Action {
-    pub action_set: openxr::ActionSet,
-    pub left_action: openxr::Action<xr::Posef>,
-    pub right_action: openxr::Action<xr::Posef>,
+    pub session: openxr::Session<xr::Vulkan>,
+    pub action_sets: Vec<openxr::ActionSet>,
+    /// Keyed by `"<action set>/<action>"`
+    pub actions: HashMap<String, TypedAction>,
+    pub subaction_paths: HashMap<String, openxr::Path>,
+    pub pose_spaces: Vec<PoseSpace>,
+    pub manifest: ActionManifest,
 }
 
 impl Action {
     pub fn new(xr_instance: &openxr::Instance,
-               session: &openxr::Session<xr::Vulkan>
-    ) -> Self {
-        let action_set = xr_instance
-            .create_action_set("input", "input pose information", 0)
-            .unwrap();
+               session: &openxr::Session<xr::Vulkan>,
+               manifest: &ActionManifest,
+    ) -> Result<Self> {
+        let mut action_sets = Vec::new();
+        let mut actions = HashMap::new();
+        let mut subaction_paths = HashMap::new();
+        let mut pose_spaces = Vec::new();
 
-        let right_action = action_set
-            .create_action::<xr::Posef>("right_hand", "Right Hand Controller", &[])
-            .unwrap();
+        for set_desc in &manifest.action_sets {
+            let action_set = xr_instance
+                .create_action_set(&set_desc.name, &set_desc.localized_name, set_desc.priority)
+                .context("creating action set")?;
 
-        let left_action = action_set
-            .create_action::<xr::Posef>("left_hand", "Left Hand Controller", &[])
-            .unwrap();
+            for desc in &set_desc.actions {
+                let full_name = format!("{}/{}", set_desc.name, desc.name);
+                if let Some(input) = desc.input {
+                    if !input.accepts(desc.action_type) {
+                        return Err(Error::InputTypeMismatch {
+                            action: full_name,
+                            action_type: desc.action_type,
+                            input,
+                        });
+                    }
+                }
 
-        xr_instance
-            .suggest_interaction_profile_bindings(
-                xr_instance
-                    .string_to_path("/interaction_profiles/khr/simple_controller")
-                    .unwrap(),
-                &[
-                    xr::Binding::new(
-                        &right_action,
-                        xr_instance
-                            .string_to_path("/user/hand/right/input/grip/pose")
-                            .unwrap(),
+                let mut paths = Vec::new();
+                for path in &desc.subaction_paths {
+                    let xr_path = xr_instance
+                        .string_to_path(path)
+                        .context("creating subaction path")?;
+                    subaction_paths.insert(path.clone(), xr_path);
+                    paths.push(xr_path);
+                }
+
+                let name = &desc.name;
+                let localized_name = &desc.localized_name;
+                let action = match desc.action_type {
+                    ActionType::Bool => TypedAction::Bool(
+                        action_set.create_action(name, localized_name, &paths).context("creating action")?,
+                    ),
+                    ActionType::Float => TypedAction::Float(
+                        action_set.create_action(name, localized_name, &paths).context("creating action")?,
+                    ),
+                    ActionType::Vector2 => TypedAction::Vector2(
+                        action_set.create_action(name, localized_name, &paths).context("creating action")?,
                     ),
-                    xr::Binding::new(
-                        &left_action,
-                        xr_instance
-                            .string_to_path("/user/hand/left/input/grip/pose")
-                            .unwrap(),
+                    ActionType::Pose => TypedAction::Pose(
+                        action_set.create_action(name, localized_name, &paths).context("creating action")?,
                     ),
-                ],
-            )
-            .unwrap();
+                    ActionType::Haptic => TypedAction::Haptic(
+                        action_set.create_action(name, localized_name, &paths).context("creating action")?,
+                    ),
+                };
+
+                if let TypedAction::Pose(pose_action) = &action {
+                    let mut targets = desc
+                        .subaction_paths
+                        .iter()
+                        .map(|path| (Some(path.clone()), subaction_paths[path]))
+                        .collect::<Vec<_>>();
+                    if targets.is_empty() {
+                        targets.push((None, xr::Path::NULL));
+                    }
+
+                    for (subaction_path, xr_path) in targets {
+                        let space = pose_action
+                            .create_space(session.clone(), xr_path, xr::Posef::IDENTITY)
+                            .context("creating action space")?;
+                        pose_spaces.push(PoseSpace {
+                            action: full_name.clone(),
+                            subaction_path,
+                            space,
+                        });
+                    }
+                }
+
+                actions.insert(full_name, action);
+            }
+
+            action_sets.push(action_set);
+        }
+
+        for (profile, suggested) in manifest.suggested_bindings() {
+            let mut bindings = Vec::new();
+            for (name, path) in &suggested {
+                let action = actions
+                    .get(name)
+                    .ok_or_else(|| Error::UnknownAction(name.clone()))?;
+                let path = xr_instance
+                    .string_to_path(path)
+                    .context("creating binding path")?;
+                bindings.push(action.binding(path));
+            }
+
+            let profile_path = xr_instance
+                .string_to_path(&profile)
+                .context("creating interaction profile path")?;
+            let result = xr_instance.suggest_interaction_profile_bindings(profile_path, &bindings);
+            match result {
+                // Older runtimes may not know every built-in profile. Any
+                // other unsupported path is still an error.
+                Err(xr::sys::Result::ERROR_PATH_UNSUPPORTED)
+                    if !manifest.bindings.iter().any(|b| b.interaction_profile == profile)
+                        && !profile_supported(xr_instance, profile_path, &bindings) =>
+                {
+                    println!("Interaction profile {} not supported by runtime", profile);
+                }
+                result => result.context("suggesting interaction profile bindings")?,
+            }
+        }
+
+        let attached = action_sets.iter().collect::<Vec<_>>();
+        session.attach_action_sets(&attached).context("attaching action sets")?;
+
+        Ok(Self {
+            session: session.clone(),
+            action_sets: action_sets,
+            actions: actions,
+            subaction_paths: subaction_paths,
+            pose_spaces: pose_spaces,
+            manifest: manifest.clone(),
+        })
+    }
+
+    /// Updates the state of every action set. Called once per frame by
+    /// `XRRenderer::update_frame`.
+    pub fn sync(&self) -> Result<()> {
+        let active_sets = self
+            .action_sets
+            .iter()
+            .map(xr::ActiveActionSet::new)
+            .collect::<Vec<_>>();
+
+        self.session.sync_actions(&active_sets).context("syncing actions")
+    }
+
+    /// Locates every pose action relative to `base`. Must be called after
+    /// `sync` for the poses to reflect the current frame.
+    pub fn locate_poses(&self, base: &xr::Space, time: xr::Time) -> Result<Vec<TrackedPose>> {
+        self.pose_spaces
+            .iter()
+            .map(|pose_space| {
+                let (location, velocity) = pose_space
+                    .space
+                    .relate(base, time)
+                    .context("locating action space")?;
+                let location_flags = location.location_flags;
+                let velocity_flags = velocity.velocity_flags;
+
+                Ok(TrackedPose {
+                    action: pose_space.action.clone(),
+                    subaction_path: pose_space.subaction_path.clone(),
+                    pose: location.pose,
+                    position_valid: location_flags.contains(xr::SpaceLocationFlags::POSITION_VALID),
+                    orientation_valid: location_flags.contains(xr::SpaceLocationFlags::ORIENTATION_VALID),
+                    position_tracked: location_flags.contains(xr::SpaceLocationFlags::POSITION_TRACKED),
+                    orientation_tracked: location_flags.contains(xr::SpaceLocationFlags::ORIENTATION_TRACKED),
+                    linear_velocity: velocity_flags
+                        .contains(xr::SpaceVelocityFlags::LINEAR_VALID)
+                        .then(|| velocity.linear_velocity),
+                    angular_velocity: velocity_flags
+                        .contains(xr::SpaceVelocityFlags::ANGULAR_VALID)
+                        .then(|| velocity.angular_velocity),
+                })
+            })
+            .collect()
+    }
+
+    /// Interaction profile the runtime picked for a top level user path such
+    /// as `LEFT_HAND`, or `None` if nothing is bound to it yet
+    pub fn current_interaction_profile(&self, top_level_path: &str) -> Result<Option<String>> {
+        let instance = self.session.instance();
+        let top_level_path = instance
+            .string_to_path(top_level_path)
+            .context("creating top level user path")?;
+        let profile = self
+            .session
+            .current_interaction_profile(top_level_path)
+            .context("getting current interaction profile")?;
+
+        if profile == xr::Path::NULL {
+            return Ok(None);
+        }
+        instance
+            .path_to_string(profile)
+            .map(Some)
+            .context("converting interaction profile path")
+    }
+
+    pub fn get(&self, name: &str) -> Result<&TypedAction> {
+        self.actions
+            .get(name)
+            .ok_or_else(|| Error::UnknownAction(name.to_owned()))
+    }
+
+    fn subaction_path(&self, subaction_path: Option<&str>) -> Result<xr::Path> {
+        match subaction_path {
+            None => Ok(xr::Path::NULL),
+            Some(path) => self
+                .subaction_paths
+                .get(path)
+                .copied()
+                .ok_or_else(|| Error::UnknownSubactionPath(path.to_owned())),
+        }
+    }
 
-        session.attach_action_sets(&[&action_set]).unwrap();
+    fn type_mismatch(&self, name: &str, expected: ActionType) -> Error {
+        Error::ActionTypeMismatch {
+            action: name.to_owned(),
+            expected,
+            actual: self.actions[name].action_type(),
+        }
+    }
 
-        Self {
-            action_set: action_set,
-            right_action: right_action,
-            left_action: left_action,
+    pub fn bool_state(&self, name: &str, subaction_path: Option<&str>) -> Result<xr::ActionState<bool>> {
+        match self.get(name)? {
+            TypedAction::Bool(action) => action
+                .state(&self.session, self.subaction_path(subaction_path)?)
+                .context("getting boolean action state"),
+            _ => Err(self.type_mismatch(name, ActionType::Bool)),
         }
     }
+
+    pub fn float_state(&self, name: &str, subaction_path: Option<&str>) -> Result<xr::ActionState<f32>> {
+        match self.get(name)? {
+            TypedAction::Float(action) => action
+                .state(&self.session, self.subaction_path(subaction_path)?)
+                .context("getting float action state"),
+            _ => Err(self.type_mismatch(name, ActionType::Float)),
+        }
+    }
+
+    pub fn vector2_state(&self,
+                         name: &str,
+                         subaction_path: Option<&str>,
+    ) -> Result<xr::ActionState<xr::Vector2f>> {
+        match self.get(name)? {
+            TypedAction::Vector2(action) => action
+                .state(&self.session, self.subaction_path(subaction_path)?)
+                .context("getting vector2 action state"),
+            _ => Err(self.type_mismatch(name, ActionType::Vector2)),
+        }
+    }
+
+    /// Whether a pose action is bound to a tracked source
+    pub fn pose_active(&self, name: &str, subaction_path: Option<&str>) -> Result<bool> {
+        match self.get(name)? {
+            TypedAction::Pose(action) => action
+                .is_active(&self.session, self.subaction_path(subaction_path)?)
+                .context("getting pose action state"),
+            _ => Err(self.type_mismatch(name, ActionType::Pose)),
+        }
+    }
+
+    /// `duration` of `xr::Duration::MIN_HAPTIC` gives a short pulse, a
+    /// `frequency` of 0.0 leaves the frequency to the runtime
+    pub fn apply_haptic_feedback(&self,
+                                 name: &str,
+                                 subaction_path: Option<&str>,
+                                 duration: xr::Duration,
+                                 frequency: f32,
+                                 amplitude: f32,
+    ) -> Result<()> {
+        match self.get(name)? {
+            TypedAction::Haptic(action) => action
+                .apply_feedback(
+                    &self.session,
+                    self.subaction_path(subaction_path)?,
+                    &xr::HapticVibration::new()
+                        .duration(duration)
+                        .frequency(frequency)
+                        .amplitude(amplitude),
+                )
+                .context("applying haptic feedback"),
+            _ => Err(self.type_mismatch(name, ActionType::Haptic)),
+        }
+    }
+
+    pub fn stop_haptic_feedback(&self, name: &str, subaction_path: Option<&str>) -> Result<()> {
+        match self.get(name)? {
+            TypedAction::Haptic(action) => action
+                .stop_feedback(&self.session, self.subaction_path(subaction_path)?)
+                .context("stopping haptic feedback"),
+            _ => Err(self.type_mismatch(name, ActionType::Haptic)),
+        }
+    }
+}
+
+/// Whether the runtime knows `profile` at all, when it rejected `bindings` as
+/// a whole. A runtime without the profile rejects every binding on its own too,
+/// while one with it accepts at least the bindings it supports.
+fn profile_supported(xr_instance: &openxr::Instance,
+                     profile: xr::Path,
+                     bindings: &[xr::Binding<'_>],
+) -> bool {
+    bindings.iter().any(|binding| {
+        xr_instance
+            .suggest_interaction_profile_bindings(profile, std::slice::from_ref(binding))
+            .is_ok()
+    })
 }
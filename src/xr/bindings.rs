@@ -0,0 +1,254 @@
+use crate::xr::action::ActionType;
+
+/// Controller inputs an action can be bound to on every built-in interaction
+/// profile that has them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Input {
+    GripPose,
+    AimPose,
+    /// Analog trigger value, or the click on controllers without one
+    Trigger,
+    TriggerClick,
+    /// Analog grip/squeeze value, or the click on controllers without one
+    Squeeze,
+    /// Thumbstick, or the trackpad on controllers without one
+    Thumbstick,
+    ThumbstickClick,
+    Menu,
+    /// A or X on controllers with face buttons, trackpad click otherwise
+    PrimaryButton,
+    /// B or Y on controllers with face buttons
+    SecondaryButton,
+    Haptic,
+}
+
+impl Input {
+    /// Whether an action of `action_type` can be bound to this input on every
+    /// built-in profile that has it
+    pub fn accepts(self, action_type: ActionType) -> bool {
+        BUILTIN_PROFILES
+            .iter()
+            .flat_map(|profile| profile.paths(self))
+            .all(|path| path_accepts(path, action_type))
+    }
+}
+
+pub struct InteractionProfile {
+    pub path: &'static str,
+    /// Full binding paths for each supported input. Inputs only present on one
+    /// hand list just that hand's path.
+    pub bindings: &'static [(Input, &'static [&'static str])],
+    /// Valid binding paths the table above doesn't use
+    pub other_paths: &'static [&'static str],
+}
+
+impl InteractionProfile {
+    pub fn paths(&self, input: Input) -> &'static [&'static str] {
+        self.bindings
+            .iter()
+            .find(|(i, _)| *i == input)
+            .map(|(_, paths)| *paths)
+            .unwrap_or(&[])
+    }
+
+    /// Whether `path` names an input or output of this profile. Paths may
+    /// leave out the last component, e.g. `.../input/trigger` for the value.
+    pub fn has_path(&self, path: &str) -> bool {
+        self.bindings
+            .iter()
+            .flat_map(|(_, paths)| paths.iter())
+            .chain(self.other_paths)
+            .any(|known| {
+                known
+                    .strip_prefix(path)
+                    .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+            })
+    }
+}
+
+pub fn builtin_profile(path: &str) -> Option<&'static InteractionProfile> {
+    BUILTIN_PROFILES.iter().find(|profile| profile.path == path)
+}
+
+/// Whether an action of `action_type` can be bound to `path`, going by the
+/// path's last component
+pub fn path_accepts(path: &str, action_type: ActionType) -> bool {
+    let component = path.rsplit('/').next().unwrap_or("");
+    let is_pose = component == "pose";
+    let is_haptic = path.contains("/output/");
+    let is_vector2 = component == "thumbstick" || component == "trackpad";
+
+    match action_type {
+        ActionType::Pose => is_pose,
+        ActionType::Haptic => is_haptic,
+        ActionType::Vector2 => is_vector2,
+        ActionType::Bool | ActionType::Float => !is_pose && !is_haptic && !is_vector2,
+    }
+}
+
+macro_rules! both_hands {
+    ($component:literal) => {
+        &[
+            concat!("/user/hand/left/input/", $component),
+            concat!("/user/hand/right/input/", $component),
+        ]
+    };
+}
+
+const HAPTIC: &[&str] = &["/user/hand/left/output/haptic", "/user/hand/right/output/haptic"];
+
+pub const KHR_SIMPLE_CONTROLLER: InteractionProfile = InteractionProfile {
+    path: "/interaction_profiles/khr/simple_controller",
+    bindings: &[
+        (Input::GripPose, both_hands!("grip/pose")),
+        (Input::AimPose, both_hands!("aim/pose")),
+        (Input::Trigger, both_hands!("select/click")),
+        (Input::TriggerClick, both_hands!("select/click")),
+        (Input::Menu, both_hands!("menu/click")),
+        (Input::Haptic, HAPTIC),
+    ],
+    other_paths: &[],
+};
+
+pub const OCULUS_TOUCH_CONTROLLER: InteractionProfile = InteractionProfile {
+    path: "/interaction_profiles/oculus/touch_controller",
+    bindings: &[
+        (Input::GripPose, both_hands!("grip/pose")),
+        (Input::AimPose, both_hands!("aim/pose")),
+        (Input::Trigger, both_hands!("trigger/value")),
+        (Input::TriggerClick, both_hands!("trigger/value")),
+        (Input::Squeeze, both_hands!("squeeze/value")),
+        (Input::Thumbstick, both_hands!("thumbstick")),
+        (Input::ThumbstickClick, both_hands!("thumbstick/click")),
+        (Input::Menu, &["/user/hand/left/input/menu/click"]),
+        (Input::PrimaryButton, &["/user/hand/left/input/x/click", "/user/hand/right/input/a/click"]),
+        (Input::SecondaryButton, &["/user/hand/left/input/y/click", "/user/hand/right/input/b/click"]),
+        (Input::Haptic, HAPTIC),
+    ],
+    other_paths: &[
+        "/user/hand/left/input/x/touch",
+        "/user/hand/left/input/y/touch",
+        "/user/hand/right/input/a/touch",
+        "/user/hand/right/input/b/touch",
+        "/user/hand/right/input/system/click",
+        "/user/hand/left/input/trigger/touch",
+        "/user/hand/right/input/trigger/touch",
+        "/user/hand/left/input/thumbstick/x",
+        "/user/hand/left/input/thumbstick/y",
+        "/user/hand/right/input/thumbstick/x",
+        "/user/hand/right/input/thumbstick/y",
+        "/user/hand/left/input/thumbstick/touch",
+        "/user/hand/right/input/thumbstick/touch",
+        "/user/hand/left/input/thumbrest/touch",
+        "/user/hand/right/input/thumbrest/touch",
+    ],
+};
+
+pub const VALVE_INDEX_CONTROLLER: InteractionProfile = InteractionProfile {
+    path: "/interaction_profiles/valve/index_controller",
+    bindings: &[
+        (Input::GripPose, both_hands!("grip/pose")),
+        (Input::AimPose, both_hands!("aim/pose")),
+        (Input::Trigger, both_hands!("trigger/value")),
+        (Input::TriggerClick, both_hands!("trigger/click")),
+        (Input::Squeeze, both_hands!("squeeze/value")),
+        (Input::Thumbstick, both_hands!("thumbstick")),
+        (Input::ThumbstickClick, both_hands!("thumbstick/click")),
+        (Input::PrimaryButton, both_hands!("a/click")),
+        (Input::SecondaryButton, both_hands!("b/click")),
+        (Input::Haptic, HAPTIC),
+    ],
+    other_paths: &[
+        "/user/hand/left/input/system/click",
+        "/user/hand/right/input/system/click",
+        "/user/hand/left/input/system/touch",
+        "/user/hand/right/input/system/touch",
+        "/user/hand/left/input/a/touch",
+        "/user/hand/right/input/a/touch",
+        "/user/hand/left/input/b/touch",
+        "/user/hand/right/input/b/touch",
+        "/user/hand/left/input/squeeze/force",
+        "/user/hand/right/input/squeeze/force",
+        "/user/hand/left/input/trigger/touch",
+        "/user/hand/right/input/trigger/touch",
+        "/user/hand/left/input/thumbstick/x",
+        "/user/hand/left/input/thumbstick/y",
+        "/user/hand/right/input/thumbstick/x",
+        "/user/hand/right/input/thumbstick/y",
+        "/user/hand/left/input/thumbstick/touch",
+        "/user/hand/right/input/thumbstick/touch",
+        "/user/hand/left/input/trackpad/x",
+        "/user/hand/left/input/trackpad/y",
+        "/user/hand/right/input/trackpad/x",
+        "/user/hand/right/input/trackpad/y",
+        "/user/hand/left/input/trackpad/force",
+        "/user/hand/right/input/trackpad/force",
+        "/user/hand/left/input/trackpad/touch",
+        "/user/hand/right/input/trackpad/touch",
+    ],
+};
+
+pub const HTC_VIVE_CONTROLLER: InteractionProfile = InteractionProfile {
+    path: "/interaction_profiles/htc/vive_controller",
+    bindings: &[
+        (Input::GripPose, both_hands!("grip/pose")),
+        (Input::AimPose, both_hands!("aim/pose")),
+        (Input::Trigger, both_hands!("trigger/value")),
+        (Input::TriggerClick, both_hands!("trigger/click")),
+        (Input::Squeeze, both_hands!("squeeze/click")),
+        (Input::Thumbstick, both_hands!("trackpad")),
+        (Input::ThumbstickClick, both_hands!("trackpad/click")),
+        (Input::Menu, both_hands!("menu/click")),
+        (Input::PrimaryButton, both_hands!("trackpad/click")),
+        (Input::Haptic, HAPTIC),
+    ],
+    other_paths: &[
+        "/user/hand/left/input/system/click",
+        "/user/hand/right/input/system/click",
+        "/user/hand/left/input/trackpad/x",
+        "/user/hand/left/input/trackpad/y",
+        "/user/hand/right/input/trackpad/x",
+        "/user/hand/right/input/trackpad/y",
+        "/user/hand/left/input/trackpad/touch",
+        "/user/hand/right/input/trackpad/touch",
+    ],
+};
+
+pub const MICROSOFT_MOTION_CONTROLLER: InteractionProfile = InteractionProfile {
+    path: "/interaction_profiles/microsoft/motion_controller",
+    bindings: &[
+        (Input::GripPose, both_hands!("grip/pose")),
+        (Input::AimPose, both_hands!("aim/pose")),
+        (Input::Trigger, both_hands!("trigger/value")),
+        (Input::TriggerClick, both_hands!("trigger/value")),
+        (Input::Squeeze, both_hands!("squeeze/click")),
+        (Input::Thumbstick, both_hands!("thumbstick")),
+        (Input::ThumbstickClick, both_hands!("thumbstick/click")),
+        (Input::Menu, both_hands!("menu/click")),
+        (Input::PrimaryButton, both_hands!("trackpad/click")),
+        (Input::Haptic, HAPTIC),
+    ],
+    other_paths: &[
+        "/user/hand/left/input/thumbstick/x",
+        "/user/hand/left/input/thumbstick/y",
+        "/user/hand/right/input/thumbstick/x",
+        "/user/hand/right/input/thumbstick/y",
+        "/user/hand/left/input/trackpad",
+        "/user/hand/right/input/trackpad",
+        "/user/hand/left/input/trackpad/x",
+        "/user/hand/left/input/trackpad/y",
+        "/user/hand/right/input/trackpad/x",
+        "/user/hand/right/input/trackpad/y",
+        "/user/hand/left/input/trackpad/touch",
+        "/user/hand/right/input/trackpad/touch",
+    ],
+};
+
+/// Suggested for every action declared with an `Input`
+pub const BUILTIN_PROFILES: &[InteractionProfile] = &[
+    KHR_SIMPLE_CONTROLLER,
+    OCULUS_TOUCH_CONTROLLER,
+    VALVE_INDEX_CONTROLLER,
+    HTC_VIVE_CONTROLLER,
+    MICROSOFT_MOTION_CONTROLLER,
+];
@@ -0,0 +1,77 @@
+use glam::Mat4;
+use openxr as xr;
+
+use crate::math::{self, DepthRange};
+
+/// One located view, usually an eye, with matrices ready for a shader
+#[derive(Clone, Copy, Debug)]
+pub struct EyeView {
+    pub pose: xr::Posef,
+    pub fov: xr::Fovf,
+    /// Stage space to eye space
+    pub view: Mat4,
+    pub projection: Mat4,
+}
+
+impl EyeView {
+    pub fn new(view: &xr::View, depth: DepthRange) -> Self {
+        EyeView {
+            pose: view.pose,
+            fov: view.fov,
+            view: math::view_from_pose(&view.pose),
+            projection: math::projection_from_fov(&view.fov, depth),
+        }
+    }
+
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection * self.view
+    }
+}
+
+/// Location of one pose action's space for the frame being rendered
+#[derive(Clone, Debug)]
+pub struct TrackedPose {
+    /// `"<action set>/<action>"`
+    pub action: String,
+    pub subaction_path: Option<String>,
+    /// In stage space. Only meaningful where the matching `*_valid` flag is set.
+    pub pose: xr::Posef,
+    pub position_valid: bool,
+    pub orientation_valid: bool,
+    /// Unset while the runtime only infers the pose, e.g. from the last
+    /// tracked pose or a body model
+    pub position_tracked: bool,
+    pub orientation_tracked: bool,
+    /// `None` if the runtime can't report it this frame
+    pub linear_velocity: Option<xr::Vector3f>,
+    pub angular_velocity: Option<xr::Vector3f>,
+}
+
+/// Per-frame input handed to `Renderer::draw`
+#[derive(Clone, Debug)]
+pub struct FrameContext {
+    pub predicted_display_time: xr::Time,
+    /// One per swapchain array layer, in view configuration order
+    pub views: Vec<EyeView>,
+    pub depth: DepthRange,
+    pub poses: Vec<TrackedPose>,
+}
+
+impl FrameContext {
+    pub fn pose(&self, action: &str, subaction_path: Option<&str>) -> Option<&TrackedPose> {
+        self.poses
+            .iter()
+            .find(|pose| pose.action == action && pose.subaction_path.as_deref() == subaction_path)
+    }
+}
+
+impl Default for FrameContext {
+    fn default() -> Self {
+        FrameContext {
+            predicted_display_time: xr::Time::from_nanos(0),
+            views: Vec::new(),
+            depth: DepthRange::default(),
+            poses: Vec::new(),
+        }
+    }
+}
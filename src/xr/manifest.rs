@@ -0,0 +1,291 @@
+//! Loads an `ActionManifest` from a TOML file:
+//!
+//! ```toml
+//! [[action_sets]]
+//! name = "gameplay"
+//! localized_name = "Gameplay"
+//!
+//! [[action_sets.actions]]
+//! name = "fire"
+//! localized_name = "Fire"
+//! type = "float"
+//! subaction_paths = ["/user/hand/left", "/user/hand/right"]
+//! input = "trigger"
+//!
+//! [[bindings]]
+//! profile = "/interaction_profiles/valve/index_controller"
+//! action = "gameplay/fire"
+//! paths = ["/user/hand/right/input/trigger/click"]
+//! ```
+//!
+//! `input` and `bindings` are both optional, see `ActionDesc::input` and
+//! `ActionManifest::binding`. Every problem found is reported with its line.
+
+use serde::Deserialize;
+use std::{
+    collections::{HashSet},
+    fmt,
+    fs,
+    ops::Range,
+    path::{Path},
+};
+use toml::Spanned;
+
+use crate::{
+    error::{Context, Error, Result},
+    xr::{
+        action::{ActionDesc, ActionManifest, ActionSetDesc, ActionType},
+        bindings::{builtin_profile, path_accepts, Input},
+    }
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestDiagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ManifestDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFile {
+    #[serde(default)]
+    action_sets: Vec<ActionSetEntry>,
+    #[serde(default)]
+    bindings: Vec<BindingEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ActionSetEntry {
+    name: Spanned<String>,
+    localized_name: String,
+    #[serde(default)]
+    priority: u32,
+    #[serde(default)]
+    actions: Vec<ActionEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ActionEntry {
+    name: Spanned<String>,
+    localized_name: String,
+    #[serde(rename = "type")]
+    action_type: Spanned<String>,
+    #[serde(default)]
+    subaction_paths: Vec<Spanned<String>>,
+    input: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BindingEntry {
+    profile: Spanned<String>,
+    action: Spanned<String>,
+    paths: Vec<Spanned<String>>,
+}
+
+struct Validator<'a> {
+    source: &'a str,
+    diagnostics: Vec<ManifestDiagnostic>,
+}
+
+impl Validator<'_> {
+    fn error(&mut self, span: Range<usize>, message: String) {
+        let before = &self.source[..span.start.min(self.source.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        self.diagnostics.push(ManifestDiagnostic { line, column, message });
+    }
+}
+
+impl ActionManifest {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<ActionManifest> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).context("reading action manifest")?;
+
+        ActionManifest::from_toml(&source).map_err(|diagnostics| Error::Manifest {
+            file: path.to_owned(),
+            diagnostics,
+        })
+    }
+
+    /// Parses and validates a manifest, returning every problem found
+    pub fn from_toml(source: &str) -> std::result::Result<ActionManifest, Vec<ManifestDiagnostic>> {
+        let mut validator = Validator {
+            source,
+            diagnostics: Vec::new(),
+        };
+
+        let file: ManifestFile = match toml::from_str(source) {
+            Ok(file) => file,
+            Err(e) => {
+                validator.error(e.span().unwrap_or(0..0), e.message().to_owned());
+                return Err(validator.diagnostics);
+            }
+        };
+
+        let mut manifest = ActionManifest::new();
+        let mut set_names = HashSet::new();
+        let mut declared = Vec::new();
+
+        for set in file.action_sets {
+            if !set_names.insert(set.name.get_ref().clone()) {
+                validator.error(set.name.span(), format!("duplicate action set {}", set.name.get_ref()));
+            }
+
+            let mut set_desc = ActionSetDesc::new(set.name.get_ref(), &set.localized_name, set.priority);
+            let mut action_names = HashSet::new();
+
+            for action in set.actions {
+                let name = action.name.get_ref();
+                if !action_names.insert(name.clone()) {
+                    validator.error(
+                        action.name.span(),
+                        format!("duplicate action {}/{}", set.name.get_ref(), name),
+                    );
+                }
+
+                let action_type = match parse_action_type(action.action_type.get_ref()) {
+                    Some(action_type) => action_type,
+                    None => {
+                        validator.error(
+                            action.action_type.span(),
+                            format!("unknown action type {}", action.action_type.get_ref()),
+                        );
+                        continue;
+                    }
+                };
+
+                let mut desc = ActionDesc::new(name, &action.localized_name, action_type);
+                for subaction_path in &action.subaction_paths {
+                    if !subaction_path.get_ref().starts_with("/user/") {
+                        validator.error(
+                            subaction_path.span(),
+                            format!("invalid subaction path {}", subaction_path.get_ref()),
+                        );
+                    }
+                    desc = desc.subaction_path(subaction_path.get_ref());
+                }
+                if let Some(input) = &action.input {
+                    match parse_input(input.get_ref()) {
+                        Some(parsed) if parsed.accepts(action_type) => desc = desc.input(parsed),
+                        Some(_) => validator.error(
+                            input.span(),
+                            format!(
+                                "{:?} action {}/{} cannot be bound to input {}",
+                                action_type,
+                                set.name.get_ref(),
+                                name,
+                                input.get_ref()
+                            ),
+                        ),
+                        None => validator.error(input.span(), format!("unknown input {}", input.get_ref())),
+                    }
+                }
+
+                declared.push((format!("{}/{}", set.name.get_ref(), name), desc.clone()));
+                set_desc = set_desc.action(desc);
+            }
+
+            manifest = manifest.action_set(set_desc);
+        }
+
+        for binding in file.bindings {
+            let profile = binding.profile.get_ref();
+            let builtin = builtin_profile(profile);
+            if builtin.is_none() && !profile.starts_with("/interaction_profiles/") {
+                validator.error(binding.profile.span(), format!("invalid interaction profile {}", profile));
+            }
+
+            let action = match declared.iter().find(|(name, _)| name == binding.action.get_ref()) {
+                Some((_, action)) => action,
+                None => {
+                    validator.error(
+                        binding.action.span(),
+                        format!("unknown action {}", binding.action.get_ref()),
+                    );
+                    continue;
+                }
+            };
+
+            for path in &binding.paths {
+                let path_str = path.get_ref();
+                if let Some(builtin) = builtin {
+                    if !builtin.has_path(path_str) {
+                        validator.error(path.span(), format!("unknown path {} for {}", path_str, profile));
+                        continue;
+                    }
+                }
+                if !action.subaction_paths.is_empty()
+                    && !action.subaction_paths.iter().any(|subaction_path| {
+                        path_str
+                            .strip_prefix(subaction_path.as_str())
+                            .map_or(false, |rest| rest.starts_with('/'))
+                    })
+                {
+                    validator.error(
+                        path.span(),
+                        format!("path {} is outside the subaction paths of {}", path_str, binding.action.get_ref()),
+                    );
+                    continue;
+                }
+                if !path_accepts(path_str, action.action_type) {
+                    validator.error(
+                        path.span(),
+                        format!(
+                            "{:?} action {} cannot be bound to {}",
+                            action.action_type,
+                            binding.action.get_ref(),
+                            path_str
+                        ),
+                    );
+                    continue;
+                }
+
+                manifest = manifest.binding(profile, binding.action.get_ref(), path_str);
+            }
+        }
+
+        if validator.diagnostics.is_empty() {
+            Ok(manifest)
+        } else {
+            Err(validator.diagnostics)
+        }
+    }
+}
+
+fn parse_action_type(name: &str) -> Option<ActionType> {
+    Some(match name {
+        "bool" => ActionType::Bool,
+        "float" => ActionType::Float,
+        "vector2" => ActionType::Vector2,
+        "pose" => ActionType::Pose,
+        "haptic" => ActionType::Haptic,
+        _ => return None,
+    })
+}
+
+fn parse_input(name: &str) -> Option<Input> {
+    Some(match name {
+        "grip_pose" => Input::GripPose,
+        "aim_pose" => Input::AimPose,
+        "trigger" => Input::Trigger,
+        "trigger_click" => Input::TriggerClick,
+        "squeeze" => Input::Squeeze,
+        "thumbstick" => Input::Thumbstick,
+        "thumbstick_click" => Input::ThumbstickClick,
+        "menu" => Input::Menu,
+        "primary_button" => Input::PrimaryButton,
+        "secondary_button" => Input::SecondaryButton,
+        "haptic" => Input::Haptic,
+        _ => return None,
+    })
+}
@@ -0,0 +1,1056 @@
+//! In-process fake OpenXR runtime for headless tests.
+//!
+//! `MockRuntime::entry` hands out an `xr::Entry` whose `xrGetInstanceProcAddr`
+//! resolves to the functions in this module, so `App`, `XRRenderer` and
+//! friends run unmodified against it. Vulkan objects are still created through
+//! the application's Vulkan loader, so a software ICD such as lavapipe is
+//! enough to drive the whole frame loop on a machine without a GPU.
+//!
+//! The fake session walks through IDLE, READY, SYNCHRONIZED, VISIBLE and
+//! FOCUSED once the app begins it, requests an exit after a configurable
+//! number of frames, and records every state it delivers and every frame it
+//! receives so tests can assert on them afterwards. Suggested bindings are
+//! recorded too, and if `MockConfig::interaction_profile` was among them the
+//! runtime reports it as current for both hands once action sets are attached.
+//! XR_KHR_composition_layer_depth can be advertised, in which case depth
+//! chained onto projection views is recorded as well.
+
+use std::{
+    collections::VecDeque,
+    ffi::{c_char, CStr},
+    mem, ptr,
+    sync::{Mutex, MutexGuard},
+};
+
+use ash::{vk::{self, Handle}};
+use openxr as xr;
+use openxr::sys;
+
+const EXTENSIONS: &[&str] = &["XR_KHR_vulkan_enable", "XR_KHR_vulkan_enable2"];
+const DEPTH_EXTENSION: &str = "XR_KHR_composition_layer_depth";
+const SWAPCHAIN_IMAGE_COUNT: u32 = 3;
+const SYSTEM_ID: u64 = 1;
+
+static TEST_LOCK: Mutex<()> = Mutex::new(());
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+#[derive(Clone, Debug)]
+pub struct MockConfig {
+    pub resolution: vk::Extent2D,
+    pub view_count: u32,
+    pub blend_modes: Vec<xr::EnvironmentBlendMode>,
+    /// Frames submitted while SYNCHRONIZED before the session becomes visible
+    pub synchronized_frames: u32,
+    /// Number of frames after which the runtime asks the app to exit
+    pub exit_after_frames: Option<u32>,
+    pub display_period: xr::Duration,
+    /// Interaction profile the runtime picks if the app suggested bindings for it
+    pub interaction_profile: Option<String>,
+    /// Interaction profiles the runtime doesn't know, rejecting any bindings
+    /// suggested for them
+    pub unsupported_profiles: Vec<String>,
+    /// Stage space pose reported for every action space
+    pub controller_pose: xr::Posef,
+    /// Advertise XR_KHR_composition_layer_depth
+    pub composition_layer_depth: bool,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        MockConfig {
+            resolution: vk::Extent2D { width: 64, height: 64 },
+            view_count: 2,
+            blend_modes: vec![xr::EnvironmentBlendMode::OPAQUE],
+            synchronized_frames: 0,
+            exit_after_frames: Some(3),
+            display_period: xr::Duration::from_nanos(11_111_111),
+            interaction_profile: None,
+            unsupported_profiles: Vec::new(),
+            controller_pose: xr::Posef {
+                orientation: xr::Quaternionf::IDENTITY,
+                position: xr::Vector3f { x: 0.0, y: 1.2, z: -0.3 },
+            },
+            composition_layer_depth: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SubmittedView {
+    pub pose: xr::Posef,
+    pub fov: xr::Fovf,
+    pub image_array_index: u32,
+    pub image_rect: xr::Rect2Di,
+    /// Chained `XrCompositionLayerDepthInfoKHR`, if any
+    pub depth: Option<SubmittedDepth>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SubmittedDepth {
+    pub image_array_index: u32,
+    pub min_depth: f32,
+    pub max_depth: f32,
+    pub near_z: f32,
+    pub far_z: f32,
+}
+
+#[derive(Clone, Debug)]
+pub enum SubmittedLayer {
+    Projection { views: Vec<SubmittedView> },
+    Other(sys::StructureType),
+}
+
+#[derive(Clone, Debug)]
+pub struct SubmittedFrame {
+    pub display_time: xr::Time,
+    pub blend_mode: xr::EnvironmentBlendMode,
+    pub layers: Vec<SubmittedLayer>,
+}
+
+enum MockEvent {
+    SessionStateChanged(xr::SessionState),
+    InteractionProfileChanged,
+}
+
+struct MockVulkan {
+    instance: ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    device: Option<ash::Device>,
+}
+
+struct MockSwapchain {
+    handle: u64,
+    images: Vec<(vk::Image, vk::DeviceMemory)>,
+    next_image: u32,
+}
+
+struct State {
+    config: MockConfig,
+    next_handle: u64,
+    paths: Vec<String>,
+    vulkan: Option<MockVulkan>,
+    swapchains: Vec<MockSwapchain>,
+    session: Option<u64>,
+    session_state: xr::SessionState,
+    session_running: bool,
+    exit_requested: bool,
+    pending_events: VecDeque<MockEvent>,
+    suggested_bindings: Vec<(String, Vec<String>)>,
+    current_profile: Option<sys::Path>,
+    delivered_states: Vec<xr::SessionState>,
+    frame_index: u32,
+    frames: Vec<SubmittedFrame>,
+}
+
+impl State {
+    fn handle(&mut self) -> u64 {
+        self.next_handle += 1;
+        self.next_handle
+    }
+
+    fn transition(&mut self, state: xr::SessionState) {
+        self.session_state = state;
+        self.pending_events.push_back(MockEvent::SessionStateChanged(state));
+    }
+
+    fn request_exit(&mut self) -> sys::Result {
+        if !self.session_running {
+            return sys::Result::ERROR_SESSION_NOT_RUNNING;
+        }
+        if self.session_state == xr::SessionState::FOCUSED {
+            self.transition(xr::SessionState::VISIBLE);
+        }
+        if self.session_state == xr::SessionState::VISIBLE {
+            self.transition(xr::SessionState::SYNCHRONIZED);
+        }
+        self.transition(xr::SessionState::STOPPING);
+        self.exit_requested = true;
+        sys::Result::SUCCESS
+    }
+
+    fn view(&self, index: u32) -> xr::View {
+        let half_ipd = 0.032;
+        let x = if self.config.view_count == 1 {
+            0.0
+        } else if index == 0 {
+            -half_ipd
+        } else {
+            half_ipd
+        };
+        xr::View {
+            pose: xr::Posef {
+                orientation: xr::Quaternionf::IDENTITY,
+                position: xr::Vector3f { x, y: 1.6, z: 0.0 },
+            },
+            fov: xr::Fovf {
+                angle_left: -0.785,
+                angle_right: 0.785,
+                angle_up: 0.785,
+                angle_down: -0.785,
+            },
+        }
+    }
+}
+
+/// Handle to the fake runtime. Only one may exist at a time; creating a second
+/// one blocks until the first is dropped so tests can run in parallel safely.
+pub struct MockRuntime {
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl MockRuntime {
+    pub fn new(config: MockConfig) -> MockRuntime {
+        let guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        *lock_state() = Some(State {
+            config,
+            next_handle: 0,
+            paths: Vec::new(),
+            vulkan: None,
+            swapchains: Vec::new(),
+            session: None,
+            session_state: xr::SessionState::UNKNOWN,
+            session_running: false,
+            exit_requested: false,
+            pending_events: VecDeque::new(),
+            suggested_bindings: Vec::new(),
+            current_profile: None,
+            delivered_states: Vec::new(),
+            frame_index: 0,
+            frames: Vec::new(),
+        });
+
+        MockRuntime { _guard: guard }
+    }
+
+    pub fn entry(&self) -> xr::Entry {
+        unsafe {
+            xr::Entry::from_get_instance_proc_addr(get_instance_proc_addr)
+                .expect("mock runtime entry points are always available")
+        }
+    }
+
+    /// Predicted view pose and field of view the runtime reports for `index`
+    pub fn view(&self, index: u32) -> xr::View {
+        with_state(|state| state.view(index))
+    }
+
+    /// Session states in the order the application polled them
+    pub fn session_states(&self) -> Vec<xr::SessionState> {
+        with_state(|state| state.delivered_states.clone())
+    }
+
+    /// Binding paths per interaction profile, as last suggested by the app
+    pub fn suggested_bindings(&self) -> Vec<(String, Vec<String>)> {
+        with_state(|state| state.suggested_bindings.clone())
+    }
+
+    /// Frames in the order the application ended them
+    pub fn frames(&self) -> Vec<SubmittedFrame> {
+        with_state(|state| state.frames.clone())
+    }
+}
+
+impl Drop for MockRuntime {
+    fn drop(&mut self) {
+        *lock_state() = None;
+    }
+}
+
+/// Extensions advertised under the current config
+fn extensions() -> Vec<&'static str> {
+    let mut extensions = EXTENSIONS.to_vec();
+    if with_state(|state| state.config.composition_layer_depth) {
+        extensions.push(DEPTH_EXTENSION);
+    }
+    extensions
+}
+
+fn lock_state() -> MutexGuard<'static, Option<State>> {
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn with_state<T>(f: impl FnOnce(&mut State) -> T) -> T {
+    f(lock_state().as_mut().expect("mock runtime is not installed"))
+}
+
+unsafe fn write_array<T: Copy>(items: &[T], capacity: u32, count: *mut u32, out: *mut T) -> sys::Result {
+    *count = items.len() as u32;
+    if capacity == 0 {
+        return sys::Result::SUCCESS;
+    }
+    if (capacity as usize) < items.len() {
+        return sys::Result::ERROR_SIZE_INSUFFICIENT;
+    }
+    ptr::copy_nonoverlapping(items.as_ptr(), out, items.len());
+    sys::Result::SUCCESS
+}
+
+fn write_name(name: &str, out: &mut [c_char]) {
+    for (dst, src) in out.iter_mut().zip(name.bytes().chain(Some(0))) {
+        *dst = src as c_char;
+    }
+}
+
+unsafe extern "system" fn get_instance_proc_addr(
+    _instance: sys::Instance,
+    name: *const c_char,
+    function: *mut Option<sys::pfn::VoidFunction>,
+) -> sys::Result {
+    let name = CStr::from_ptr(name).to_bytes();
+    let f: *const () = match name {
+        b"xrGetInstanceProcAddr" => get_instance_proc_addr as *const (),
+        b"xrEnumerateApiLayerProperties" => enumerate_api_layer_properties as *const (),
+        b"xrEnumerateInstanceExtensionProperties" => enumerate_instance_extension_properties as *const (),
+        b"xrCreateInstance" => create_instance as *const (),
+        b"xrGetSystem" => get_system as *const (),
+        b"xrEnumerateViewConfigurations" => enumerate_view_configurations as *const (),
+        b"xrEnumerateViewConfigurationViews" => enumerate_view_configuration_views as *const (),
+        b"xrEnumerateEnvironmentBlendModes" => enumerate_environment_blend_modes as *const (),
+        b"xrGetVulkanGraphicsRequirementsKHR" => get_vulkan_graphics_requirements as *const (),
+        b"xrGetVulkanGraphicsRequirements2KHR" => get_vulkan_graphics_requirements as *const (),
+        b"xrCreateVulkanInstanceKHR" => create_vulkan_instance as *const (),
+        b"xrGetVulkanGraphicsDevice2KHR" => get_vulkan_graphics_device2 as *const (),
+        b"xrCreateVulkanDeviceKHR" => create_vulkan_device as *const (),
+        b"xrCreateSession" => create_session as *const (),
+        b"xrDestroySession" => destroy_session as *const (),
+        b"xrPollEvent" => poll_event as *const (),
+        b"xrBeginSession" => begin_session as *const (),
+        b"xrEndSession" => end_session as *const (),
+        b"xrRequestExitSession" => request_exit_session as *const (),
+        b"xrEnumerateSwapchainFormats" => enumerate_swapchain_formats as *const (),
+        b"xrCreateSwapchain" => create_swapchain as *const (),
+        b"xrDestroySwapchain" => destroy_swapchain as *const (),
+        b"xrEnumerateSwapchainImages" => enumerate_swapchain_images as *const (),
+        b"xrAcquireSwapchainImage" => acquire_swapchain_image as *const (),
+        b"xrWaitSwapchainImage" => success as *const (),
+        b"xrReleaseSwapchainImage" => success as *const (),
+        b"xrCreateReferenceSpace" => create_handle as *const (),
+        b"xrCreateActionSpace" => create_handle as *const (),
+        b"xrLocateSpace" => locate_space as *const (),
+        b"xrLocateViews" => locate_views as *const (),
+        b"xrWaitFrame" => wait_frame as *const (),
+        b"xrBeginFrame" => begin_frame as *const (),
+        b"xrEndFrame" => end_frame as *const (),
+        b"xrStringToPath" => string_to_path as *const (),
+        b"xrCreateActionSet" => create_handle as *const (),
+        b"xrCreateAction" => create_handle as *const (),
+        b"xrPathToString" => path_to_string as *const (),
+        b"xrSuggestInteractionProfileBindings" => suggest_interaction_profile_bindings as *const (),
+        b"xrAttachSessionActionSets" => attach_session_action_sets as *const (),
+        b"xrGetCurrentInteractionProfile" => get_current_interaction_profile as *const (),
+        b"xrSyncActions" => success as *const (),
+        b"xrDestroyInstance" | b"xrDestroySpace" | b"xrDestroyActionSet" | b"xrDestroyAction" => {
+            success as *const ()
+        }
+        // openxrs resolves every core function up front, so anything the mock
+        // does not implement still needs an entry point. Arguments are ignored,
+        // which is sound for the C calling convention.
+        _ => unsupported as *const (),
+    };
+    *function = Some(mem::transmute::<*const (), sys::pfn::VoidFunction>(f));
+    sys::Result::SUCCESS
+}
+
+unsafe extern "system" fn success() -> sys::Result {
+    sys::Result::SUCCESS
+}
+
+unsafe extern "system" fn unsupported() -> sys::Result {
+    sys::Result::ERROR_FUNCTION_UNSUPPORTED
+}
+
+/// Shared by every `xrCreate*` whose handle the mock does not need to track
+unsafe extern "system" fn create_handle(_parent: u64, _info: *const (), out: *mut u64) -> sys::Result {
+    *out = with_state(|state| state.handle());
+    sys::Result::SUCCESS
+}
+
+unsafe extern "system" fn enumerate_api_layer_properties(
+    _capacity: u32,
+    count: *mut u32,
+    _properties: *mut sys::ApiLayerProperties,
+) -> sys::Result {
+    *count = 0;
+    sys::Result::SUCCESS
+}
+
+unsafe extern "system" fn enumerate_instance_extension_properties(
+    _layer_name: *const c_char,
+    capacity: u32,
+    count: *mut u32,
+    properties: *mut sys::ExtensionProperties,
+) -> sys::Result {
+    let extensions = extensions();
+    *count = extensions.len() as u32;
+    if capacity == 0 {
+        return sys::Result::SUCCESS;
+    }
+    if (capacity as usize) < extensions.len() {
+        return sys::Result::ERROR_SIZE_INSUFFICIENT;
+    }
+    for (i, name) in extensions.iter().enumerate() {
+        let properties = &mut *properties.add(i);
+        properties.ty = sys::StructureType::EXTENSION_PROPERTIES;
+        properties.extension_version = 1;
+        write_name(name, &mut properties.extension_name);
+    }
+    sys::Result::SUCCESS
+}
+
+unsafe extern "system" fn create_instance(
+    info: *const sys::InstanceCreateInfo,
+    instance: *mut sys::Instance,
+) -> sys::Result {
+    let info = &*info;
+    let extensions = extensions();
+    for i in 0..info.enabled_extension_count as usize {
+        let name = CStr::from_ptr(*info.enabled_extension_names.add(i));
+        if !extensions.iter().any(|ext| ext.as_bytes() == name.to_bytes()) {
+            return sys::Result::ERROR_EXTENSION_NOT_PRESENT;
+        }
+    }
+    *instance = sys::Instance::from_raw(with_state(|state| state.handle()));
+    sys::Result::SUCCESS
+}
+
+unsafe extern "system" fn get_system(
+    _instance: sys::Instance,
+    info: *const sys::SystemGetInfo,
+    system_id: *mut sys::SystemId,
+) -> sys::Result {
+    if (*info).form_factor != xr::FormFactor::HEAD_MOUNTED_DISPLAY {
+        return sys::Result::ERROR_FORM_FACTOR_UNSUPPORTED;
+    }
+    *system_id = sys::SystemId::from_raw(SYSTEM_ID);
+    sys::Result::SUCCESS
+}
+
+unsafe extern "system" fn enumerate_view_configurations(
+    _instance: sys::Instance,
+    _system_id: sys::SystemId,
+    capacity: u32,
+    count: *mut u32,
+    types: *mut xr::ViewConfigurationType,
+) -> sys::Result {
+    let view_type = with_state(|state| match state.config.view_count {
+        1 => xr::ViewConfigurationType::PRIMARY_MONO,
+        _ => xr::ViewConfigurationType::PRIMARY_STEREO,
+    });
+    write_array(&[view_type], capacity, count, types)
+}
+
+unsafe extern "system" fn enumerate_view_configuration_views(
+    _instance: sys::Instance,
+    _system_id: sys::SystemId,
+    _view_type: xr::ViewConfigurationType,
+    capacity: u32,
+    count: *mut u32,
+    views: *mut sys::ViewConfigurationView,
+) -> sys::Result {
+    let (resolution, view_count) = with_state(|state| (state.config.resolution, state.config.view_count));
+    let view = sys::ViewConfigurationView {
+        ty: sys::StructureType::VIEW_CONFIGURATION_VIEW,
+        next: ptr::null_mut(),
+        recommended_image_rect_width: resolution.width,
+        max_image_rect_width: resolution.width,
+        recommended_image_rect_height: resolution.height,
+        max_image_rect_height: resolution.height,
+        recommended_swapchain_sample_count: 1,
+        max_swapchain_sample_count: 1,
+    };
+    write_array(&vec![view; view_count as usize], capacity, count, views)
+}
+
+unsafe extern "system" fn enumerate_environment_blend_modes(
+    _instance: sys::Instance,
+    _system_id: sys::SystemId,
+    _view_type: xr::ViewConfigurationType,
+    capacity: u32,
+    count: *mut u32,
+    modes: *mut xr::EnvironmentBlendMode,
+) -> sys::Result {
+    let blend_modes = with_state(|state| state.config.blend_modes.clone());
+    write_array(&blend_modes, capacity, count, modes)
+}
+
+unsafe extern "system" fn get_vulkan_graphics_requirements(
+    _instance: sys::Instance,
+    _system_id: sys::SystemId,
+    requirements: *mut sys::GraphicsRequirementsVulkanKHR,
+) -> sys::Result {
+    (*requirements).min_api_version_supported = sys::Version::new(1, 0, 0);
+    (*requirements).max_api_version_supported = sys::Version::new(1, 3, 0);
+    sys::Result::SUCCESS
+}
+
+unsafe extern "system" fn create_vulkan_instance(
+    _instance: sys::Instance,
+    info: *const sys::VulkanInstanceCreateInfoKHR,
+    vulkan_instance: *mut sys::platform::VkInstance,
+    vulkan_result: *mut sys::platform::VkResult,
+) -> sys::Result {
+    let info = &*info;
+    let entry = ash::Entry::from_static_fn(vk::StaticFn {
+        get_instance_proc_addr: mem::transmute(info.pfn_get_instance_proc_addr),
+    });
+    let create_info = &*(info.vulkan_create_info as *const vk::InstanceCreateInfo);
+
+    match entry.create_instance(create_info, None) {
+        Ok(instance) => {
+            *vulkan_instance = instance.handle().as_raw() as _;
+            *vulkan_result = vk::Result::SUCCESS.as_raw();
+            with_state(|state| {
+                state.vulkan = Some(MockVulkan {
+                    instance,
+                    physical_device: vk::PhysicalDevice::null(),
+                    device: None,
+                })
+            });
+        }
+        Err(e) => *vulkan_result = e.as_raw(),
+    }
+    sys::Result::SUCCESS
+}
+
+unsafe extern "system" fn get_vulkan_graphics_device2(
+    _instance: sys::Instance,
+    _info: *const sys::VulkanGraphicsDeviceGetInfoKHR,
+    physical_device: *mut sys::platform::VkPhysicalDevice,
+) -> sys::Result {
+    with_state(|state| {
+        let vulkan = match &mut state.vulkan {
+            Some(vulkan) => vulkan,
+            None => return sys::Result::ERROR_CALL_ORDER_INVALID,
+        };
+        match vulkan.instance.enumerate_physical_devices() {
+            Ok(devices) if !devices.is_empty() => {
+                vulkan.physical_device = devices[0];
+                *physical_device = devices[0].as_raw() as _;
+                sys::Result::SUCCESS
+            }
+            _ => sys::Result::ERROR_RUNTIME_FAILURE,
+        }
+    })
+}
+
+unsafe extern "system" fn create_vulkan_device(
+    _instance: sys::Instance,
+    info: *const sys::VulkanDeviceCreateInfoKHR,
+    vulkan_device: *mut sys::platform::VkDevice,
+    vulkan_result: *mut sys::platform::VkResult,
+) -> sys::Result {
+    let info = &*info;
+    let physical_device = vk::PhysicalDevice::from_raw(info.vulkan_physical_device as _);
+    let create_info = &*(info.vulkan_create_info as *const vk::DeviceCreateInfo);
+
+    with_state(|state| {
+        let vulkan = match &mut state.vulkan {
+            Some(vulkan) => vulkan,
+            None => return sys::Result::ERROR_CALL_ORDER_INVALID,
+        };
+        match vulkan.instance.create_device(physical_device, create_info, None) {
+            Ok(device) => {
+                *vulkan_device = device.handle().as_raw() as _;
+                *vulkan_result = vk::Result::SUCCESS.as_raw();
+                vulkan.device = Some(device);
+            }
+            Err(e) => *vulkan_result = e.as_raw(),
+        }
+        sys::Result::SUCCESS
+    })
+}
+
+unsafe extern "system" fn create_session(
+    _instance: sys::Instance,
+    _info: *const sys::SessionCreateInfo,
+    session: *mut sys::Session,
+) -> sys::Result {
+    with_state(|state| {
+        if state.vulkan.as_ref().and_then(|vulkan| vulkan.device.as_ref()).is_none() {
+            return sys::Result::ERROR_GRAPHICS_DEVICE_INVALID;
+        }
+        let handle = state.handle();
+        state.session = Some(handle);
+        state.transition(xr::SessionState::IDLE);
+        state.transition(xr::SessionState::READY);
+        *session = sys::Session::from_raw(handle);
+        sys::Result::SUCCESS
+    })
+}
+
+unsafe extern "system" fn destroy_session(_session: sys::Session) -> sys::Result {
+    with_state(|state| {
+        state.session = None;
+        state.pending_events.clear();
+    });
+    sys::Result::SUCCESS
+}
+
+unsafe extern "system" fn poll_event(
+    _instance: sys::Instance,
+    buffer: *mut sys::EventDataBuffer,
+) -> sys::Result {
+    with_state(|state| {
+        let (session, event) = match (state.session, state.pending_events.pop_front()) {
+            (Some(session), Some(event)) => (session, event),
+            _ => return sys::Result::EVENT_UNAVAILABLE,
+        };
+        let session_state = match event {
+            MockEvent::SessionStateChanged(session_state) => session_state,
+            MockEvent::InteractionProfileChanged => {
+                ptr::write(
+                    buffer as *mut sys::EventDataInteractionProfileChanged,
+                    sys::EventDataInteractionProfileChanged {
+                        ty: sys::StructureType::EVENT_DATA_INTERACTION_PROFILE_CHANGED,
+                        next: ptr::null(),
+                        session: sys::Session::from_raw(session),
+                    },
+                );
+                return sys::Result::SUCCESS;
+            }
+        };
+        state.delivered_states.push(session_state);
+        ptr::write(
+            buffer as *mut sys::EventDataSessionStateChanged,
+            sys::EventDataSessionStateChanged {
+                ty: sys::StructureType::EVENT_DATA_SESSION_STATE_CHANGED,
+                next: ptr::null(),
+                session: sys::Session::from_raw(session),
+                state: session_state,
+                time: xr::Time::from_nanos(0),
+            },
+        );
+        sys::Result::SUCCESS
+    })
+}
+
+unsafe extern "system" fn begin_session(
+    _session: sys::Session,
+    _info: *const sys::SessionBeginInfo,
+) -> sys::Result {
+    with_state(|state| {
+        if state.session_running {
+            return sys::Result::ERROR_SESSION_RUNNING;
+        }
+        if state.session_state != xr::SessionState::READY {
+            return sys::Result::ERROR_SESSION_NOT_READY;
+        }
+        state.session_running = true;
+        state.transition(xr::SessionState::SYNCHRONIZED);
+        if state.config.synchronized_frames == 0 {
+            state.transition(xr::SessionState::VISIBLE);
+            state.transition(xr::SessionState::FOCUSED);
+        }
+        sys::Result::SUCCESS
+    })
+}
+
+unsafe extern "system" fn end_session(_session: sys::Session) -> sys::Result {
+    with_state(|state| {
+        if !state.session_running {
+            return sys::Result::ERROR_SESSION_NOT_RUNNING;
+        }
+        if state.session_state != xr::SessionState::STOPPING {
+            return sys::Result::ERROR_SESSION_NOT_STOPPING;
+        }
+        state.session_running = false;
+        state.transition(xr::SessionState::IDLE);
+        if state.exit_requested {
+            state.transition(xr::SessionState::EXITING);
+        }
+        sys::Result::SUCCESS
+    })
+}
+
+unsafe extern "system" fn request_exit_session(_session: sys::Session) -> sys::Result {
+    with_state(|state| state.request_exit())
+}
+
+unsafe extern "system" fn enumerate_swapchain_formats(
+    _session: sys::Session,
+    capacity: u32,
+    count: *mut u32,
+    formats: *mut i64,
+) -> sys::Result {
+    let supported = [
+        vk::Format::R8G8B8A8_SRGB,
+        vk::Format::B8G8R8A8_SRGB,
+        vk::Format::D32_SFLOAT,
+        vk::Format::D24_UNORM_S8_UINT,
+        vk::Format::D16_UNORM,
+    ]
+    .iter()
+    .map(|format| format.as_raw() as i64)
+    .collect::<Vec<_>>();
+    write_array(&supported, capacity, count, formats)
+}
+
+unsafe extern "system" fn create_swapchain(
+    _session: sys::Session,
+    info: *const sys::SwapchainCreateInfo,
+    swapchain: *mut sys::Swapchain,
+) -> sys::Result {
+    let info = &*info;
+    let mut usage = vk::ImageUsageFlags::empty();
+    for (xr_usage, vk_usage) in [
+        (xr::SwapchainUsageFlags::COLOR_ATTACHMENT, vk::ImageUsageFlags::COLOR_ATTACHMENT),
+        (xr::SwapchainUsageFlags::DEPTH_STENCIL_ATTACHMENT, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT),
+        (xr::SwapchainUsageFlags::UNORDERED_ACCESS, vk::ImageUsageFlags::STORAGE),
+        (xr::SwapchainUsageFlags::TRANSFER_SRC, vk::ImageUsageFlags::TRANSFER_SRC),
+        (xr::SwapchainUsageFlags::TRANSFER_DST, vk::ImageUsageFlags::TRANSFER_DST),
+        (xr::SwapchainUsageFlags::SAMPLED, vk::ImageUsageFlags::SAMPLED),
+    ] {
+        if info.usage_flags.contains(xr_usage) {
+            usage |= vk_usage;
+        }
+    }
+
+    with_state(|state| {
+        let vulkan = match &state.vulkan {
+            Some(MockVulkan { instance, physical_device, device: Some(device) }) => {
+                (instance, *physical_device, device)
+            }
+            _ => return sys::Result::ERROR_GRAPHICS_DEVICE_INVALID,
+        };
+        let (instance, physical_device, device) = vulkan;
+        let memory_properties = instance.get_physical_device_memory_properties(physical_device);
+
+        let mut images = Vec::new();
+        for _ in 0..SWAPCHAIN_IMAGE_COUNT {
+            let image = match device.create_image(
+                &vk::ImageCreateInfo::builder()
+                    .image_type(vk::ImageType::TYPE_2D)
+                    .format(vk::Format::from_raw(info.format as _))
+                    .extent(vk::Extent3D { width: info.width, height: info.height, depth: 1 })
+                    .mip_levels(info.mip_count)
+                    .array_layers(info.array_size)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .tiling(vk::ImageTiling::OPTIMAL)
+                    .usage(usage)
+                    .sharing_mode(vk::SharingMode::EXCLUSIVE),
+                None,
+            ) {
+                Ok(image) => image,
+                Err(_) => return sys::Result::ERROR_SWAPCHAIN_FORMAT_UNSUPPORTED,
+            };
+            let requirements = device.get_image_memory_requirements(image);
+            let memory_type_index = memory_properties.memory_types
+                [..memory_properties.memory_type_count as usize]
+                .iter()
+                .enumerate()
+                .position(|(index, memory_type)| {
+                    (1 << index) & requirements.memory_type_bits != 0
+                        && memory_type.property_flags.contains(vk::MemoryPropertyFlags::DEVICE_LOCAL)
+                })
+                .unwrap_or(0) as u32;
+            let memory = match device.allocate_memory(
+                &vk::MemoryAllocateInfo::builder()
+                    .allocation_size(requirements.size)
+                    .memory_type_index(memory_type_index),
+                None,
+            ) {
+                Ok(memory) => memory,
+                Err(_) => return sys::Result::ERROR_OUT_OF_MEMORY,
+            };
+            if device.bind_image_memory(image, memory, 0).is_err() {
+                return sys::Result::ERROR_RUNTIME_FAILURE;
+            }
+            images.push((image, memory));
+        }
+
+        let handle = state.handle();
+        state.swapchains.push(MockSwapchain { handle, images, next_image: 0 });
+        *swapchain = sys::Swapchain::from_raw(handle);
+        sys::Result::SUCCESS
+    })
+}
+
+unsafe extern "system" fn destroy_swapchain(swapchain: sys::Swapchain) -> sys::Result {
+    with_state(|state| {
+        let index = match state.swapchains.iter().position(|s| s.handle == swapchain.into_raw()) {
+            Some(index) => index,
+            None => return sys::Result::ERROR_HANDLE_INVALID,
+        };
+        let removed = state.swapchains.remove(index);
+        if let Some(device) = state.vulkan.as_ref().and_then(|vulkan| vulkan.device.as_ref()) {
+            for (image, memory) in removed.images {
+                device.destroy_image(image, None);
+                device.free_memory(memory, None);
+            }
+        }
+        sys::Result::SUCCESS
+    })
+}
+
+unsafe extern "system" fn enumerate_swapchain_images(
+    swapchain: sys::Swapchain,
+    capacity: u32,
+    count: *mut u32,
+    images: *mut sys::SwapchainImageBaseHeader,
+) -> sys::Result {
+    with_state(|state| {
+        let swapchain = match state.swapchains.iter().find(|s| s.handle == swapchain.into_raw()) {
+            Some(swapchain) => swapchain,
+            None => return sys::Result::ERROR_HANDLE_INVALID,
+        };
+        let vulkan_images = swapchain
+            .images
+            .iter()
+            .map(|(image, _)| sys::SwapchainImageVulkanKHR {
+                ty: sys::StructureType::SWAPCHAIN_IMAGE_VULKAN_KHR,
+                next: ptr::null_mut(),
+                image: image.as_raw(),
+            })
+            .collect::<Vec<_>>();
+        write_array(&vulkan_images, capacity, count, images as *mut sys::SwapchainImageVulkanKHR)
+    })
+}
+
+unsafe extern "system" fn acquire_swapchain_image(
+    swapchain: sys::Swapchain,
+    _info: *const sys::SwapchainImageAcquireInfo,
+    index: *mut u32,
+) -> sys::Result {
+    with_state(|state| {
+        match state.swapchains.iter_mut().find(|s| s.handle == swapchain.into_raw()) {
+            Some(swapchain) => {
+                *index = swapchain.next_image;
+                swapchain.next_image = (swapchain.next_image + 1) % SWAPCHAIN_IMAGE_COUNT;
+                sys::Result::SUCCESS
+            }
+            None => sys::Result::ERROR_HANDLE_INVALID,
+        }
+    })
+}
+
+unsafe extern "system" fn locate_views(
+    _session: sys::Session,
+    _info: *const sys::ViewLocateInfo,
+    view_state: *mut sys::ViewState,
+    capacity: u32,
+    count: *mut u32,
+    views: *mut sys::View,
+) -> sys::Result {
+    (*view_state).view_state_flags = sys::ViewStateFlags::POSITION_VALID
+        | sys::ViewStateFlags::ORIENTATION_VALID
+        | sys::ViewStateFlags::POSITION_TRACKED
+        | sys::ViewStateFlags::ORIENTATION_TRACKED;
+    let located = with_state(|state| {
+        (0..state.config.view_count)
+            .map(|i| {
+                let view = state.view(i);
+                sys::View {
+                    ty: sys::StructureType::VIEW,
+                    next: ptr::null_mut(),
+                    pose: view.pose,
+                    fov: view.fov,
+                }
+            })
+            .collect::<Vec<_>>()
+    });
+    write_array(&located, capacity, count, views)
+}
+
+unsafe extern "system" fn locate_space(
+    _space: sys::Space,
+    _base_space: sys::Space,
+    _time: xr::Time,
+    location: *mut sys::SpaceLocation,
+) -> sys::Result {
+    (*location).location_flags = sys::SpaceLocationFlags::POSITION_VALID
+        | sys::SpaceLocationFlags::ORIENTATION_VALID
+        | sys::SpaceLocationFlags::POSITION_TRACKED
+        | sys::SpaceLocationFlags::ORIENTATION_TRACKED;
+    (*location).pose = with_state(|state| state.config.controller_pose);
+
+    let velocity = (*location).next as *mut sys::SpaceVelocity;
+    if !velocity.is_null() && (*velocity).ty == sys::StructureType::SPACE_VELOCITY {
+        (*velocity).velocity_flags = sys::SpaceVelocityFlags::LINEAR_VALID;
+        (*velocity).linear_velocity = xr::Vector3f { x: 0.0, y: 0.0, z: -1.0 };
+    }
+    sys::Result::SUCCESS
+}
+
+unsafe extern "system" fn wait_frame(
+    _session: sys::Session,
+    _info: *const sys::FrameWaitInfo,
+    frame_state: *mut sys::FrameState,
+) -> sys::Result {
+    with_state(|state| {
+        if !state.session_running {
+            return sys::Result::ERROR_SESSION_NOT_RUNNING;
+        }
+        let period = state.config.display_period.as_nanos();
+        (*frame_state).predicted_display_period = state.config.display_period;
+        (*frame_state).predicted_display_time = xr::Time::from_nanos(period * (state.frame_index as i64 + 1));
+        (*frame_state).should_render = matches!(
+            state.session_state,
+            xr::SessionState::VISIBLE | xr::SessionState::FOCUSED
+        ).into();
+        sys::Result::SUCCESS
+    })
+}
+
+unsafe extern "system" fn begin_frame(
+    _session: sys::Session,
+    _info: *const sys::FrameBeginInfo,
+) -> sys::Result {
+    with_state(|state| {
+        if !state.session_running {
+            return sys::Result::ERROR_SESSION_NOT_RUNNING;
+        }
+        sys::Result::SUCCESS
+    })
+}
+
+unsafe extern "system" fn end_frame(
+    _session: sys::Session,
+    info: *const sys::FrameEndInfo,
+) -> sys::Result {
+    let info = &*info;
+    let layers = (0..info.layer_count as usize)
+        .map(|i| {
+            let header = *info.layers.add(i);
+            match (*header).ty {
+                sys::StructureType::COMPOSITION_LAYER_PROJECTION => {
+                    let projection = &*(header as *const sys::CompositionLayerProjection);
+                    let views = (0..projection.view_count as usize)
+                        .map(|j| {
+                            let view = &*projection.views.add(j);
+                            let depth = view.next as *const sys::CompositionLayerDepthInfoKHR;
+                            let depth = (!depth.is_null()
+                                && (*depth).ty == sys::StructureType::COMPOSITION_LAYER_DEPTH_INFO_KHR)
+                                .then(|| SubmittedDepth {
+                                    image_array_index: (*depth).sub_image.image_array_index,
+                                    min_depth: (*depth).min_depth,
+                                    max_depth: (*depth).max_depth,
+                                    near_z: (*depth).near_z,
+                                    far_z: (*depth).far_z,
+                                });
+                            SubmittedView {
+                                pose: view.pose,
+                                fov: view.fov,
+                                image_array_index: view.sub_image.image_array_index,
+                                image_rect: view.sub_image.image_rect,
+                                depth,
+                            }
+                        })
+                        .collect();
+                    SubmittedLayer::Projection { views }
+                }
+                ty => SubmittedLayer::Other(ty),
+            }
+        })
+        .collect();
+
+    with_state(|state| {
+        if !state.session_running {
+            return sys::Result::ERROR_SESSION_NOT_RUNNING;
+        }
+        state.frames.push(SubmittedFrame {
+            display_time: info.display_time,
+            blend_mode: info.environment_blend_mode,
+            layers,
+        });
+        state.frame_index += 1;
+
+        if state.session_state == xr::SessionState::SYNCHRONIZED
+            && !state.exit_requested
+            && state.frame_index == state.config.synchronized_frames
+        {
+            state.transition(xr::SessionState::VISIBLE);
+            state.transition(xr::SessionState::FOCUSED);
+        }
+        if state.config.exit_after_frames == Some(state.frame_index) {
+            state.request_exit();
+        }
+        sys::Result::SUCCESS
+    })
+}
+
+unsafe extern "system" fn string_to_path(
+    _instance: sys::Instance,
+    path_string: *const c_char,
+    path: *mut sys::Path,
+) -> sys::Result {
+    let path_string = match CStr::from_ptr(path_string).to_str() {
+        Ok(path_string) if path_string.starts_with('/') => path_string.to_owned(),
+        _ => return sys::Result::ERROR_PATH_FORMAT_INVALID,
+    };
+    with_state(|state| {
+        let index = match state.paths.iter().position(|p| *p == path_string) {
+            Some(index) => index,
+            None => {
+                state.paths.push(path_string);
+                state.paths.len() - 1
+            }
+        };
+        *path = sys::Path::from_raw(index as u64 + 1);
+    });
+    sys::Result::SUCCESS
+}
+
+unsafe extern "system" fn path_to_string(
+    _instance: sys::Instance,
+    path: sys::Path,
+    capacity: u32,
+    count: *mut u32,
+    buffer: *mut c_char,
+) -> sys::Result {
+    with_state(|state| {
+        let bytes = match state.paths.get((path.into_raw() as usize).wrapping_sub(1)) {
+            Some(path) => path.bytes().chain(Some(0)).map(|b| b as c_char).collect::<Vec<_>>(),
+            None => return sys::Result::ERROR_PATH_INVALID,
+        };
+        write_array(&bytes, capacity, count, buffer)
+    })
+}
+
+unsafe extern "system" fn suggest_interaction_profile_bindings(
+    _instance: sys::Instance,
+    suggested: *const sys::InteractionProfileSuggestedBinding,
+) -> sys::Result {
+    let suggested = &*suggested;
+    let bindings = std::slice::from_raw_parts(
+        suggested.suggested_bindings,
+        suggested.count_suggested_bindings as usize,
+    );
+    with_state(|state| {
+        let path = |path: sys::Path| state.paths[path.into_raw() as usize - 1].clone();
+        let profile = path(suggested.interaction_profile);
+        if state.config.unsupported_profiles.contains(&profile) {
+            return sys::Result::ERROR_PATH_UNSUPPORTED;
+        }
+        let paths = bindings.iter().map(|binding| path(binding.binding)).collect();
+
+        state.suggested_bindings.retain(|(p, _)| *p != profile);
+        state.suggested_bindings.push((profile, paths));
+        sys::Result::SUCCESS
+    })
+}
+
+unsafe extern "system" fn attach_session_action_sets(
+    _session: sys::Session,
+    _info: *const sys::SessionActionSetsAttachInfo,
+) -> sys::Result {
+    with_state(|state| {
+        let profile = match &state.config.interaction_profile {
+            Some(profile) if state.suggested_bindings.iter().any(|(p, _)| p == profile) => profile,
+            _ => return,
+        };
+        let index = state.paths.iter().position(|p| p == profile).unwrap();
+        state.current_profile = Some(sys::Path::from_raw(index as u64 + 1));
+        state.pending_events.push_back(MockEvent::InteractionProfileChanged);
+    });
+    sys::Result::SUCCESS
+}
+
+unsafe extern "system" fn get_current_interaction_profile(
+    _session: sys::Session,
+    _top_level_user_path: sys::Path,
+    profile: *mut sys::InteractionProfileState,
+) -> sys::Result {
+    (*profile).interaction_profile = with_state(|state| state.current_profile.unwrap_or(sys::Path::NULL));
+    sys::Result::SUCCESS
+}
@@ -1,4 +1,9 @@
 pub mod action;
+pub mod bindings;
+pub mod frame;
+pub mod manifest;
+#[cfg(feature = "mock-runtime")]
+pub mod mock_runtime;
 pub mod space;
 pub mod swapchain;
 pub mod xr_base;
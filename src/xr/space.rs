@@ -1,18 +1,20 @@
 use openxr as xr;
 
+use crate::error::{Context, Result};
+
 pub struct Space {
     pub stage_space: openxr::Space
 }
 
 impl Space {
     pub fn new(session: &openxr::Session<xr::Vulkan>,
-    ) -> Self {
+    ) -> Result<Self> {
         let stage_space = session
             .create_reference_space(xr::ReferenceSpaceType::STAGE, xr::Posef::IDENTITY)
-            .unwrap();
+            .context("creating stage reference space")?;
 
-        Self {
+        Ok(Self {
             stage_space: stage_space
-        }
+        })
     }
 }
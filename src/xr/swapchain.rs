@@ -1,47 +1,221 @@
-use ash::{vk::self};
+use std::sync::{Arc};
+
+use ash::{vk::{self, Handle}};
 use openxr as xr;
 
+use crate::{
+    error::{Context, Error, Result},
+    graphics::{
+        capture::{self, CaptureView, CapturedImage},
+        offscreen::OffscreenImages,
+        vk_base::VkBase
+    }
+};
+
 const COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
-const VIEW_COUNT: u32 = 2;
-const VIEW_TYPE: xr::ViewConfigurationType = xr::ViewConfigurationType::PRIMARY_STEREO;
+
+pub enum SwapchainHandle {
+    Xr(xr::Swapchain<xr::Vulkan>),
+    /// Stands in for the OpenXR swapchain when rendering headless
+    Offscreen(OffscreenImages),
+}
 
 pub struct Swapchain {
     pub resolution: vk::Extent2D,
+    /// One array layer per view of the view configuration
+    pub view_count: u32,
+    pub handle: SwapchainHandle,
+    /// Index of the most recently acquired image
+    pub current_image: Option<u32>,
+    /// Depth images submitted with XR_KHR_composition_layer_depth, if enabled
+    pub depth: Option<DepthSwapchain>,
+}
+
+pub struct DepthSwapchain {
+    pub format: vk::Format,
     pub handle: xr::Swapchain<xr::Vulkan>,
+    pub current_image: Option<u32>,
 }
 
 impl Swapchain {
     pub fn new(instance: &openxr::Instance,
                system: openxr::SystemId,
                session: &openxr::Session<xr::Vulkan>,
-    ) -> Swapchain {
+               view_type: openxr::ViewConfigurationType,
+               depth_format: Option<vk::Format>,
+    ) -> Result<Swapchain> {
         let views = instance
-            .enumerate_view_configuration_views(system, VIEW_TYPE)
-            .unwrap();
+            .enumerate_view_configuration_views(system, view_type)
+            .context("enumerating view configuration views")?;
 
         let resolution = vk::Extent2D {
             width: views[0].recommended_image_rect_width,
             height: views[0].recommended_image_rect_height,
         };
+        let view_count = views.len() as u32;
 
         let handle = session
             .create_swapchain(&xr::SwapchainCreateInfo {
                 create_flags: xr::SwapchainCreateFlags::EMPTY,
                 usage_flags: xr::SwapchainUsageFlags::COLOR_ATTACHMENT
-                    | xr::SwapchainUsageFlags::SAMPLED,
+                    | xr::SwapchainUsageFlags::SAMPLED
+                    | xr::SwapchainUsageFlags::TRANSFER_SRC,
                 format: COLOR_FORMAT.as_raw() as _,
                 sample_count: 1,
                 width: resolution.width,
                 height: resolution.height,
                 face_count: 1,
-                array_size: VIEW_COUNT,
+                array_size: view_count,
                 mip_count: 1,
             })
-            .unwrap();
+            .context("creating swapchain")?;
+
+        let depth = match depth_format {
+            Some(format) => Some(DepthSwapchain {
+                format,
+                handle: session
+                    .create_swapchain(&xr::SwapchainCreateInfo {
+                        create_flags: xr::SwapchainCreateFlags::EMPTY,
+                        usage_flags: xr::SwapchainUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                        format: format.as_raw() as _,
+                        sample_count: 1,
+                        width: resolution.width,
+                        height: resolution.height,
+                        face_count: 1,
+                        array_size: view_count,
+                        mip_count: 1,
+                    })
+                    .context("creating depth swapchain")?,
+                current_image: None,
+            }),
+            None => None,
+        };
+
+        Ok(Swapchain {
+            resolution,
+            view_count,
+            handle: SwapchainHandle::Xr(handle),
+            current_image: None,
+            depth,
+        })
+    }
+
+    /// Creates a swapchain backed by offscreen images, for rendering without
+    /// an OpenXR session
+    pub fn offscreen(vk_base: &Arc<VkBase>,
+                     resolution: vk::Extent2D,
+                     view_count: u32,
+    ) -> Result<Swapchain> {
+        let images = OffscreenImages::new(vk_base, resolution, view_count)?;
 
-        Swapchain {
+        Ok(Swapchain {
             resolution,
-            handle,
+            view_count,
+            handle: SwapchainHandle::Offscreen(images),
+            current_image: None,
+            depth: None,
+        })
+    }
+
+    pub fn enumerate_images(&self) -> Result<Vec<vk::Image>> {
+        match &self.handle {
+            SwapchainHandle::Xr(handle) => Ok(handle
+                .enumerate_images()
+                .context("enumerating swapchain images")?
+                .into_iter()
+                .map(vk::Image::from_raw)
+                .collect()),
+            SwapchainHandle::Offscreen(images) => Ok(images.images.clone()),
+        }
+    }
+
+    /// Images of the depth swapchain, or `None` if depth isn't submitted
+    pub fn enumerate_depth_images(&self) -> Result<Option<Vec<vk::Image>>> {
+        match &self.depth {
+            Some(depth) => Ok(Some(depth
+                .handle
+                .enumerate_images()
+                .context("enumerating depth swapchain images")?
+                .into_iter()
+                .map(vk::Image::from_raw)
+                .collect())),
+            None => Ok(None),
+        }
+    }
+
+    /// Acquires the next color image, and the next depth image if there is a
+    /// depth swapchain. Returns the color image index.
+    pub fn acquire_image(&mut self) -> Result<u32> {
+        let index = match &mut self.handle {
+            SwapchainHandle::Xr(handle) => handle.acquire_image().context("acquiring swapchain image")?,
+            SwapchainHandle::Offscreen(images) => images.acquire_image(),
+        };
+        self.current_image = Some(index);
+
+        if let Some(depth) = &mut self.depth {
+            depth.current_image = Some(
+                depth.handle.acquire_image().context("acquiring depth swapchain image")?
+            );
+        }
+
+        Ok(index)
+    }
+
+    pub fn wait_image(&mut self) -> Result<()> {
+        if let Some(depth) = &mut self.depth {
+            depth.handle
+                .wait_image(xr::Duration::INFINITE)
+                .context("waiting for depth swapchain image")?;
+        }
+
+        match &mut self.handle {
+            SwapchainHandle::Xr(handle) => handle
+                .wait_image(xr::Duration::INFINITE)
+                .context("waiting for swapchain image"),
+            SwapchainHandle::Offscreen(_) => Ok(()),
+        }
+    }
+
+    pub fn release_image(&mut self) -> Result<()> {
+        if let Some(depth) = &mut self.depth {
+            depth.handle.release_image().context("releasing depth swapchain image")?;
+        }
+
+        match &mut self.handle {
+            SwapchainHandle::Xr(handle) => handle.release_image().context("releasing swapchain image"),
+            SwapchainHandle::Offscreen(_) => Ok(()),
+        }
+    }
+
+    /// Reads back the most recently acquired image. Rendering to it must have
+    /// completed, and for OpenXR swapchains it must not have been released yet.
+    pub fn capture(&self, vk_base: &VkBase, view: CaptureView) -> Result<CapturedImage> {
+        if let CaptureView::Layer(layer) = view {
+            if layer >= self.view_count {
+                return Err(Error::InvalidCaptureLayer { layer, view_count: self.view_count });
+            }
+        }
+
+        let image = self
+            .current_image
+            .map(|index| self.enumerate_images().map(|images| images[index as usize]))
+            .ok_or(Error::NoRenderedImage)??;
+
+        match view {
+            CaptureView::Layer(layer) => capture::capture_layer(vk_base, image, self.resolution, layer),
+            CaptureView::SideBySide => {
+                let layers = (0..self.view_count)
+                    .map(|layer| capture::capture_layer(vk_base, image, self.resolution, layer))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(CapturedImage::side_by_side(&layers))
+            }
+        }
+    }
+
+    pub fn xr_handle(&self) -> Option<&xr::Swapchain<xr::Vulkan>> {
+        match &self.handle {
+            SwapchainHandle::Xr(handle) => Some(handle),
+            SwapchainHandle::Offscreen(_) => None,
         }
     }
 }
@@ -1,71 +1,164 @@
+use ash::{vk::{self}};
 use openxr as xr;
-use std::sync::{Arc};
+use std::{
+    path::PathBuf,
+    sync::{Arc},
+};
+
+use crate::{
+    error::{Context, Error, Result},
+    graphics::vk_base::DEPTH_FORMATS,
+    xr::action::ActionManifest
+};
+
+/// Selects a single flag of an `xr::ExtensionSet`, e.g. `|e| &mut e.ext_hand_tracking`
+pub type ExtensionFlag = fn(&mut xr::ExtensionSet) -> &mut bool;
+
+#[derive(Clone)]
+pub struct XRConfig {
+    pub application_name: String,
+    pub application_version: u32,
+    pub engine_name: String,
+    pub engine_version: u32,
+    /// Extensions the instance cannot be created without
+    pub required_extensions: xr::ExtensionSet,
+    /// Extensions enabled only if the runtime advertises them
+    pub optional_extensions: Vec<ExtensionFlag>,
+    pub form_factor: xr::FormFactor,
+    pub view_type: xr::ViewConfigurationType,
+    /// `None` picks the runtime's preferred blend mode
+    pub blend_mode: Option<xr::EnvironmentBlendMode>,
+    /// `None` loads the system OpenXR loader
+    pub entry: Option<xr::Entry>,
+    pub actions: ActionManifest,
+    /// Acceptable depth buffer formats in order of preference
+    pub depth_formats: Vec<vk::Format>,
+    /// File the Vulkan pipeline cache persists in. `None` uses
+    /// `pipeline_cache::default_path` for `application_name`, or keeps the
+    /// cache in memory if `entry` is set.
+    pub pipeline_cache_path: Option<PathBuf>,
+    /// Directory to compile and hot reload the renderer's shader sources
+    /// from, see `VkRenderer::watch_shaders`
+    pub shader_dir: Option<PathBuf>,
+}
+
+impl Default for XRConfig {
+    fn default() -> Self {
+        let mut required_extensions = xr::ExtensionSet::default();
+        required_extensions.khr_vulkan_enable2 = true;
+
+        #[cfg(target_os = "android")]
+        {
+            required_extensions.khr_android_create_instance = true;
+        }
+
+        XRConfig {
+            application_name: "demo".to_owned(),
+            application_version: 0,
+            engine_name: "demo engine".to_owned(),
+            engine_version: 0,
+            required_extensions,
+            optional_extensions: vec![|e| &mut e.khr_composition_layer_depth],
+            form_factor: xr::FormFactor::HEAD_MOUNTED_DISPLAY,
+            view_type: xr::ViewConfigurationType::PRIMARY_STEREO,
+            blend_mode: None,
+            entry: None,
+            actions: ActionManifest::hand_poses(),
+            depth_formats: DEPTH_FORMATS.to_vec(),
+            pipeline_cache_path: None,
+            shader_dir: None,
+        }
+    }
+}
 
 pub struct XRBase {
     pub xr_instance: openxr::Instance,
     pub system_id: openxr::SystemId,
+    pub config: XRConfig,
+    pub enabled_extensions: openxr::ExtensionSet,
+    pub environment_blend_mode: openxr::EnvironmentBlendMode,
 }
 
 impl XRBase {
-    pub fn new() -> Arc<XRBase> {
-        #[cfg(feature = "static")]
-        let entry = xr::Entry::linked();
-        #[cfg(not(feature = "static"))]
-        let entry = unsafe {
-            xr::Entry::load()
-                .expect("Error finding OpenXR loader.")
+    pub fn new(config: XRConfig) -> Result<Arc<XRBase>> {
+        let entry = match &config.entry {
+            Some(entry) => entry.clone(),
+            #[cfg(feature = "static")]
+            None => xr::Entry::linked(),
+            #[cfg(not(feature = "static"))]
+            None => unsafe { xr::Entry::load()? },
         };
 
         #[cfg(target_os = "android")]
-        entry.initialize_android_loader().unwrap();
+        entry.initialize_android_loader().context("initializing Android loader")?;
 
-        let mut extensions = xr::ExtensionSet::default();
-        extensions.khr_vulkan_enable2 = true;
+        let mut available_extensions = entry
+            .enumerate_extensions()
+            .context("enumerating instance extensions")?;
 
-        #[cfg(target_os = "android")]
-        {
-            extensions.khr_android_create_instance = true;
+        let mut extensions = config.required_extensions.clone();
+        for flag in &config.optional_extensions {
+            if *flag(&mut available_extensions) {
+                *flag(&mut extensions) = true;
+            }
         }
 
         let xr_instance = entry
             .create_instance(
                 &xr::ApplicationInfo {
-                    application_name: "demo",
-                    application_version: 0,
-                    engine_name: "demo engine",
-                    engine_version: 0,
+                    application_name: &config.application_name,
+                    application_version: config.application_version,
+                    engine_name: &config.engine_name,
+                    engine_version: config.engine_version,
                 },
                 &extensions,
                 &[],
             )
-            .unwrap();
+            .context("creating OpenXR instance")?;
 
         let system_id = xr_instance
-            .system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)
-            .unwrap();
+            .system(config.form_factor)
+            .context("querying OpenXR system")?;
+
+        let view_types = xr_instance
+            .enumerate_view_configurations(system_id)
+            .context("enumerating view configurations")?;
+        if !view_types.contains(&config.view_type) {
+            return Err(Error::UnsupportedViewConfiguration(config.view_type));
+        }
+
+        let blend_modes = xr_instance
+            .enumerate_environment_blend_modes(system_id, config.view_type)
+            .context("enumerating environment blend modes")?;
+        let environment_blend_mode = match config.blend_mode {
+            Some(blend_mode) if blend_modes.contains(&blend_mode) => blend_mode,
+            Some(blend_mode) => return Err(Error::UnsupportedBlendMode(blend_mode)),
+            None => *blend_modes.first().ok_or(Error::NoBlendModes)?,
+        };
 
         let vk_version = xr::Version::new(1, 1, 0);
 
         let graphics_requirements = xr_instance
             .graphics_requirements::<xr::Vulkan>(system_id)
-            .unwrap();
+            .context("querying Vulkan graphics requirements")?;
 
         if vk_version < graphics_requirements.min_api_version_supported
             || vk_version.major() > graphics_requirements.max_api_version_supported.major()
         {
-            panic!(
-                "Vulkan version {} not supported.
-                OpenXR runtime requires Vulkan version > {}, < {}.0.0",
-                vk_version,
-                graphics_requirements.min_api_version_supported,
-                graphics_requirements.max_api_version_supported.major() + 1
-            );
+            return Err(Error::UnsupportedVulkanVersion {
+                requested: vk_version,
+                min_supported: graphics_requirements.min_api_version_supported,
+                max_supported: graphics_requirements.max_api_version_supported,
+            });
         }
 
-        Arc::new(XRBase {
+        Ok(Arc::new(XRBase {
             xr_instance: xr_instance,
-            system_id: system_id
-        })
+            system_id: system_id,
+            config: config,
+            enabled_extensions: extensions,
+            environment_blend_mode: environment_blend_mode,
+        }))
     }
 }
 
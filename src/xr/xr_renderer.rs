@@ -1,11 +1,17 @@
-use std::sync::{Arc};
+use std::{
+    ffi::c_void,
+    ptr,
+    sync::{Arc},
+};
 
 use ash::{vk::Handle};
 use openxr as xr;
 
 use crate::{
+    error::{Context, Result},
     xr::{
         action::Action,
+        frame::{EyeView, FrameContext},
         space::Space,
         xr_base::XRBase,
         swapchain::Swapchain
@@ -17,8 +23,6 @@ use crate::{
     }
 };
 
-const VIEW_TYPE: xr::ViewConfigurationType = xr::ViewConfigurationType::PRIMARY_STEREO;
-
 pub struct XRRenderer {
     pub xr_base: Arc<XRBase>,
     pub session: openxr::Session<xr::Vulkan>,
@@ -29,10 +33,12 @@ pub struct XRRenderer {
     pub swapchain: Swapchain,
     pub actions: Action,
     pub spaces: Space,
+    /// Handed to the renderer for the most recently rendered frame
+    pub last_frame: Option<FrameContext>,
 }
 
 impl XRRenderer {
-    pub fn new(xr_base: Arc<XRBase>, vk_base: &VkBase) -> Self {
+    pub fn new(xr_base: Arc<XRBase>, vk_base: &VkBase) -> Result<Self> {
         unsafe {
             let (session, frame_wait, frame_stream) = xr_base.xr_instance
                 .create_session::<xr::Vulkan>(
@@ -45,24 +51,36 @@ impl XRRenderer {
                         queue_index: 0,
                     },
                 )
-                .unwrap();
+                .context("creating session")?;
+
+            // Depth is only submitted if the runtime can take the format the
+            // render pass already uses
+            let depth_format = if xr_base.enabled_extensions.khr_composition_layer_depth {
+                let formats = session
+                    .enumerate_swapchain_formats()
+                    .context("enumerating swapchain formats")?;
+                formats
+                    .contains(&(vk_base.depth_format.as_raw() as _))
+                    .then_some(vk_base.depth_format)
+            } else {
+                None
+            };
 
             let swapchain = Swapchain::new(&xr_base.xr_instance,
                                            xr_base.system_id,
-                                           &session
-            );
+                                           &session,
+                                           xr_base.config.view_type,
+                                           depth_format
+            )?;
 
-            let actions = Action::new(&xr_base.xr_instance, &session);
-            let spaces = Space::new(&session);
+            let actions = Action::new(&xr_base.xr_instance, &session, &xr_base.config.actions)?;
+            let spaces = Space::new(&session)?;
 
             let event_storage = xr::EventDataBuffer::new();
 
-            let environment_blend_mode = xr_base
-                .xr_instance
-                .enumerate_environment_blend_modes(xr_base.system_id, VIEW_TYPE)
-                .unwrap()[0];
+            let environment_blend_mode = xr_base.environment_blend_mode;
 
-            XRRenderer {
+            Ok(XRRenderer {
                 xr_base,
                 session,
                 frame_wait,
@@ -72,35 +90,56 @@ impl XRRenderer {
                 swapchain,
                 actions,
                 spaces,
-            }
+                last_frame: None,
+            })
         }
     }
 
-    pub fn update_frame(&mut self, vk_renderer: &mut VkRenderer) {
-        let xr_frame_state = self.frame_wait.wait().unwrap();
-        self.frame_stream.begin().unwrap();
+    pub fn update_frame(&mut self, vk_renderer: &mut VkRenderer) -> Result<()> {
+        let xr_frame_state = self.frame_wait.wait().context("waiting for frame")?;
+        let device = vk_renderer.vk_base.device.clone();
+        {
+            let _queue = device.lock_queue();
+            self.frame_stream.begin().context("beginning frame")?;
+        }
 
         if !xr_frame_state.should_render {
+            let _queue = device.lock_queue();
             self.frame_stream
                 .end(
                     xr_frame_state.predicted_display_time,
                     self.environment_blend_mode,
                     &[],
                 )
-                .unwrap();
+                .context("ending frame")?;
 
-            return;
+            return Ok(());
         }
 
-        vk_renderer.draw(&mut self.swapchain);
+        self.actions.sync()?;
+        let (_, views) = self.session
+            .locate_views(self.xr_base.config.view_type,
+                          xr_frame_state.predicted_display_time,
+                          &self.spaces.stage_space
+            )
+            .context("locating views")?;
+
+        let depth = vk_renderer.vk_base.depth_range;
+        let frame = FrameContext {
+            predicted_display_time: xr_frame_state.predicted_display_time,
+            views: views.iter().map(|view| EyeView::new(view, depth)).collect(),
+            depth,
+            poses: self.actions.locate_poses(&self.spaces.stage_space, xr_frame_state.predicted_display_time)?,
+        };
 
-        self.swapchain.handle.wait_image(xr::Duration::INFINITE).unwrap();
-        self.swapchain.handle.release_image().unwrap();
+        vk_renderer.draw(&mut self.swapchain, &frame)?;
+        self.last_frame = Some(frame);
 
-        self.session.sync_actions(&[(&self.actions.action_set).into()]).unwrap();
-        let (_, views) = self.session
-            .locate_views(VIEW_TYPE, xr_frame_state.predicted_display_time, &self.spaces.stage_space)
-            .unwrap();
+        {
+            let _queue = device.lock_queue();
+            self.swapchain.wait_image()?;
+            self.swapchain.release_image()?;
+        }
 
         let rect = xr::Rect2Di {
             offset: xr::Offset2Di { x: 0, y: 0 },
@@ -109,34 +148,72 @@ impl XRRenderer {
                 height: self.swapchain.resolution.height as _,
             },
         };
-        let left_subimage = xr::SwapchainSubImage::new()
-            .swapchain(&self.swapchain.handle)
-            .image_array_index(0)
-            .image_rect(rect);
-        let right_subimage = xr::SwapchainSubImage::new()
-            .swapchain(&self.swapchain.handle)
-            .image_array_index(1)
-            .image_rect(rect);
-        let left_projection_view = xr::CompositionLayerProjectionView::new()
-            .pose(views[0].pose)
-            .fov(views[0].fov)
-            .sub_image(left_subimage);
-        let right_projection_view = xr::CompositionLayerProjectionView::new()
-            .pose(views[1].pose)
-            .fov(views[1].fov)
-            .sub_image(right_subimage);
-        let projection_views = [left_projection_view, right_projection_view];
+        let xr_swapchain = self.swapchain
+            .xr_handle()
+            .expect("XRRenderer always renders to an OpenXR swapchain");
+
+        // nearZ and farZ are the distances at minDepth and maxDepth, so they
+        // swap with reverse-Z
+        let (near_z, far_z) = if depth.reverse_z {
+            (depth.far, depth.near)
+        } else {
+            (depth.near, depth.far)
+        };
+        let depth_infos = match &self.swapchain.depth {
+            Some(depth_swapchain) => (0..views.len())
+                .map(|i| xr::sys::CompositionLayerDepthInfoKHR {
+                    ty: xr::sys::StructureType::COMPOSITION_LAYER_DEPTH_INFO_KHR,
+                    next: ptr::null(),
+                    sub_image: xr::sys::SwapchainSubImage {
+                        swapchain: depth_swapchain.handle.as_raw(),
+                        image_rect: rect,
+                        image_array_index: i as u32,
+                    },
+                    min_depth: 0.0,
+                    max_depth: 1.0,
+                    near_z,
+                    far_z,
+                })
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+
+        let projection_views = views
+            .iter()
+            .enumerate()
+            .map(|(i, view)| {
+                let projection_view = xr::CompositionLayerProjectionView::new()
+                    .pose(view.pose)
+                    .fov(view.fov)
+                    .sub_image(
+                        xr::SwapchainSubImage::new()
+                            .swapchain(xr_swapchain)
+                            .image_array_index(i as u32)
+                            .image_rect(rect),
+                    );
+                match depth_infos.get(i) {
+                    // depth_infos outlives the end of the frame below
+                    Some(depth_info) => unsafe {
+                        let mut raw = projection_view.into_raw();
+                        raw.next = depth_info as *const _ as *const c_void;
+                        xr::CompositionLayerProjectionView::from_raw(raw)
+                    },
+                    None => projection_view,
+                }
+            })
+            .collect::<Vec<_>>();
         let projection = xr::CompositionLayerProjection::new()
             .space(&self.spaces.stage_space)
             .views(&projection_views);
 
+        let _queue = device.lock_queue();
         self.frame_stream
             .end(
                 xr_frame_state.predicted_display_time,
                 self.environment_blend_mode,
                 &[&projection],
             )
-            .unwrap();
+            .context("ending frame")
     }
 }
 
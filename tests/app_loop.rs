@@ -0,0 +1,340 @@
+//! Drives `App::run` against the in-process mock runtime.
+//!
+//! Needs a Vulkan implementation but no headset or GPU; lavapipe is enough.
+
+use openxr as xr;
+
+use xrrs::{
+    app::{App, AppEvent},
+    xr::{
+        action::{ActionDesc, ActionManifest, ActionSetDesc, ActionType, LEFT_HAND, RIGHT_HAND},
+        bindings::{Input, BUILTIN_PROFILES, OCULUS_TOUCH_CONTROLLER, VALVE_INDEX_CONTROLLER},
+        mock_runtime::{MockConfig, MockRuntime, SubmittedLayer},
+    },
+};
+
+#[test]
+fn session_runs_through_lifecycle() {
+    let runtime = MockRuntime::new(MockConfig {
+        exit_after_frames: Some(5),
+        ..Default::default()
+    });
+
+    let mut app = App::builder().entry(runtime.entry()).build().unwrap();
+    app.run().unwrap();
+
+    use xr::SessionState::*;
+    assert_eq!(
+        runtime.session_states(),
+        [IDLE, READY, SYNCHRONIZED, VISIBLE, FOCUSED, VISIBLE, SYNCHRONIZED, STOPPING, IDLE, EXITING]
+    );
+
+    let frames = runtime.frames();
+    assert_eq!(frames.len(), 5);
+    for pair in frames.windows(2) {
+        assert!(pair[1].display_time > pair[0].display_time);
+    }
+
+    for frame in &frames {
+        assert_eq!(frame.blend_mode, xr::EnvironmentBlendMode::OPAQUE);
+        assert_eq!(frame.layers.len(), 1);
+        let views = match &frame.layers[0] {
+            SubmittedLayer::Projection { views } => views,
+            other => panic!("unexpected layer {:?}", other),
+        };
+        assert_eq!(views.len(), 2);
+        for (i, view) in views.iter().enumerate() {
+            let expected = runtime.view(i as u32);
+            assert_eq!(view.image_array_index, i as u32);
+            assert_eq!(view.pose.position.x, expected.pose.position.x);
+            assert_eq!(view.fov.angle_left, expected.fov.angle_left);
+            assert_eq!(view.image_rect.extent.width, 64);
+            assert_eq!(view.image_rect.extent.height, 64);
+        }
+    }
+}
+
+#[test]
+fn frames_without_layers_until_visible() {
+    let runtime = MockRuntime::new(MockConfig {
+        synchronized_frames: 2,
+        exit_after_frames: Some(4),
+        ..Default::default()
+    });
+
+    let mut app = App::builder().entry(runtime.entry()).build().unwrap();
+    app.run().unwrap();
+
+    let layer_counts = runtime
+        .frames()
+        .iter()
+        .map(|frame| frame.layers.len())
+        .collect::<Vec<_>>();
+    assert_eq!(layer_counts, [0, 0, 1, 1]);
+}
+
+#[test]
+fn unsupported_blend_mode_is_an_error() {
+    let runtime = MockRuntime::new(MockConfig::default());
+
+    let result = App::builder()
+        .entry(runtime.entry())
+        .blend_mode(xr::EnvironmentBlendMode::ADDITIVE)
+        .build();
+
+    assert!(matches!(
+        result,
+        Err(xrrs::Error::UnsupportedBlendMode(xr::EnvironmentBlendMode::ADDITIVE))
+    ));
+}
+
+#[test]
+fn missing_blend_modes_are_an_error() {
+    let runtime = MockRuntime::new(MockConfig {
+        blend_modes: Vec::new(),
+        ..Default::default()
+    });
+
+    let result = App::builder().entry(runtime.entry()).build();
+
+    assert!(matches!(result, Err(xrrs::Error::NoBlendModes)));
+}
+
+#[test]
+fn custom_action_manifest_runs() {
+    let runtime = MockRuntime::new(MockConfig {
+        exit_after_frames: Some(2),
+        ..Default::default()
+    });
+
+    let manifest = ActionManifest::new()
+        .action_set(
+            ActionSetDesc::new("gameplay", "Gameplay", 0)
+                .action(ActionDesc::new("trigger", "Trigger", ActionType::Float).hands())
+                .action(ActionDesc::new("menu", "Menu", ActionType::Bool))
+                .action(ActionDesc::new("move", "Move", ActionType::Vector2))
+                .action(ActionDesc::new("aim", "Aim", ActionType::Pose).hands())
+                .action(ActionDesc::new("rumble", "Rumble", ActionType::Haptic).hands()),
+        )
+        .binding("/interaction_profiles/khr/simple_controller",
+                 "gameplay/trigger",
+                 "/user/hand/right/input/select/click");
+
+    let mut app = App::builder().entry(runtime.entry()).actions(manifest).build().unwrap();
+    assert_eq!(app.actions().actions.len(), 5);
+    assert!(matches!(
+        app.actions().bool_state("gameplay/trigger", None),
+        Err(xrrs::Error::ActionTypeMismatch { expected: ActionType::Bool, actual: ActionType::Float, .. })
+    ));
+    assert!(matches!(
+        app.actions().float_state("gameplay/trigger", Some("/user/head")),
+        Err(xrrs::Error::UnknownSubactionPath(_))
+    ));
+
+    app.run().unwrap();
+    assert_eq!(runtime.frames().len(), 2);
+}
+
+#[test]
+fn binding_to_undeclared_action_is_an_error() {
+    let runtime = MockRuntime::new(MockConfig::default());
+
+    let manifest = ActionManifest::hand_poses().binding(
+        "/interaction_profiles/khr/simple_controller",
+        "input/jump",
+        "/user/hand/right/input/select/click",
+    );
+    let result = App::builder().entry(runtime.entry()).actions(manifest).build();
+
+    assert!(matches!(result, Err(xrrs::Error::UnknownAction(name)) if name == "input/jump"));
+}
+
+#[test]
+fn builtin_bindings_are_suggested_and_profile_is_reported() {
+    let runtime = MockRuntime::new(MockConfig {
+        interaction_profile: Some(OCULUS_TOUCH_CONTROLLER.path.to_owned()),
+        ..Default::default()
+    });
+
+    let manifest = ActionManifest::hand_poses().action_set(
+        ActionSetDesc::new("gameplay", "Gameplay", 0)
+            .action(ActionDesc::new("menu", "Menu", ActionType::Bool).input(Input::Menu))
+            .action(ActionDesc::new("jump", "Jump", ActionType::Bool).hands().input(Input::PrimaryButton)),
+    );
+
+    let mut app = App::builder().entry(runtime.entry()).actions(manifest).build().unwrap();
+    let mut events = Vec::new();
+    app.run_with(|event| events.push(event.clone())).unwrap();
+
+    let suggested = runtime.suggested_bindings();
+    assert_eq!(suggested.len(), BUILTIN_PROFILES.len());
+    let (_, touch) = suggested
+        .iter()
+        .find(|(profile, _)| profile == OCULUS_TOUCH_CONTROLLER.path)
+        .unwrap();
+    assert_eq!(
+        touch,
+        &[
+            "/user/hand/left/input/grip/pose",
+            "/user/hand/right/input/grip/pose",
+            "/user/hand/left/input/menu/click",
+            "/user/hand/left/input/x/click",
+            "/user/hand/right/input/a/click",
+        ]
+    );
+
+    let profile = Some(OCULUS_TOUCH_CONTROLLER.path.to_owned());
+    assert_eq!(
+        events,
+        [AppEvent::InteractionProfileChanged { left: profile.clone(), right: profile }]
+    );
+}
+
+#[test]
+fn profiles_the_runtime_lacks_are_skipped() {
+    let runtime = MockRuntime::new(MockConfig {
+        unsupported_profiles: vec![VALVE_INDEX_CONTROLLER.path.to_owned()],
+        ..Default::default()
+    });
+
+    App::builder().entry(runtime.entry()).build().unwrap();
+
+    let suggested = runtime.suggested_bindings();
+    assert_eq!(suggested.len(), BUILTIN_PROFILES.len() - 1);
+    assert!(suggested.iter().all(|(profile, _)| profile != VALVE_INDEX_CONTROLLER.path));
+}
+
+#[test]
+fn input_must_suit_the_action_type() {
+    let runtime = MockRuntime::new(MockConfig::default());
+
+    let manifest = ActionManifest::new().action_set(
+        ActionSetDesc::new("gameplay", "Gameplay", 0)
+            .action(ActionDesc::new("fire", "Fire", ActionType::Float).hands().input(Input::Trigger))
+            .action(ActionDesc::new("move", "Move", ActionType::Float).hands().input(Input::Thumbstick)),
+    );
+    let result = App::builder().entry(runtime.entry()).actions(manifest).build();
+
+    assert!(matches!(
+        result,
+        Err(xrrs::Error::InputTypeMismatch { action, action_type: ActionType::Float, input: Input::Thumbstick })
+            if action == "gameplay/move"
+    ));
+    assert!(Input::Trigger.accepts(ActionType::Bool));
+    assert!(!Input::GripPose.accepts(ActionType::Float));
+    assert!(!Input::Haptic.accepts(ActionType::Bool));
+}
+
+#[test]
+fn explicit_binding_replaces_builtin_one() {
+    let manifest = ActionManifest::new()
+        .action_set(
+            ActionSetDesc::new("gameplay", "Gameplay", 0)
+                .action(ActionDesc::new("fire", "Fire", ActionType::Float).hands().input(Input::Trigger)),
+        )
+        .binding(VALVE_INDEX_CONTROLLER.path, "gameplay/fire", "/user/hand/right/input/trigger/click");
+
+    let suggested = manifest.suggested_bindings();
+    let (_, index) = suggested
+        .iter()
+        .find(|(profile, _)| profile == VALVE_INDEX_CONTROLLER.path)
+        .unwrap();
+    assert_eq!(
+        index,
+        &[("gameplay/fire".to_owned(), "/user/hand/right/input/trigger/click".to_owned())]
+    );
+}
+
+#[test]
+fn pose_actions_get_a_space_per_subaction_path() {
+    let runtime = MockRuntime::new(MockConfig::default());
+
+    let manifest = ActionManifest::hand_poses().action_set(
+        ActionSetDesc::new("gameplay", "Gameplay", 0)
+            .action(ActionDesc::new("aim", "Aim", ActionType::Pose).hands().input(Input::AimPose))
+            .action(ActionDesc::new("head", "Head", ActionType::Pose)),
+    );
+    let app = App::builder().entry(runtime.entry()).actions(manifest).build().unwrap();
+
+    let mut spaces = app
+        .actions()
+        .pose_spaces
+        .iter()
+        .map(|space| (space.action.as_str(), space.subaction_path.as_deref()))
+        .collect::<Vec<_>>();
+    spaces.sort();
+    assert_eq!(
+        spaces,
+        [
+            ("gameplay/aim", Some(LEFT_HAND)),
+            ("gameplay/aim", Some(RIGHT_HAND)),
+            ("gameplay/head", None),
+            ("input/left_hand", Some(LEFT_HAND)),
+            ("input/right_hand", Some(RIGHT_HAND)),
+        ]
+    );
+}
+
+#[test]
+fn located_poses_reach_the_renderer() {
+    let controller_pose = xr::Posef {
+        orientation: xr::Quaternionf { x: 0.0, y: 0.7071068, z: 0.0, w: 0.7071068 },
+        position: xr::Vector3f { x: 0.25, y: 1.5, z: -0.5 },
+    };
+    let runtime = MockRuntime::new(MockConfig {
+        exit_after_frames: Some(2),
+        controller_pose,
+        ..Default::default()
+    });
+
+    let mut app = App::builder()
+        .entry(runtime.entry())
+        .actions(ActionManifest::hand_poses())
+        .build()
+        .unwrap();
+    assert!(app.last_frame().is_none());
+    app.run().unwrap();
+
+    let frame = app.last_frame().expect("a frame was rendered");
+    for (action, hand) in [("input/left_hand", LEFT_HAND), ("input/right_hand", RIGHT_HAND)] {
+        let pose = frame.pose(action, Some(hand)).expect("pose located for the frame");
+        assert!(pose.position_valid && pose.orientation_valid);
+        let (p, q) = (pose.pose.position, pose.pose.orientation);
+        assert_eq!((p.x, p.y, p.z), (0.25, 1.5, -0.5));
+        assert_eq!((q.x, q.y, q.z, q.w), (0.0, 0.7071068, 0.0, 0.7071068));
+    }
+}
+
+#[test]
+fn depth_is_submitted_only_when_advertised() {
+    let views = |runtime: &MockRuntime| match &runtime.frames()[0].layers[0] {
+        SubmittedLayer::Projection { views } => views.clone(),
+        other => panic!("unexpected layer {:?}", other),
+    };
+
+    let runtime = MockRuntime::new(MockConfig { exit_after_frames: Some(1), ..Default::default() });
+    App::builder().entry(runtime.entry()).build().unwrap().run().unwrap();
+    assert!(views(&runtime).iter().all(|view| view.depth.is_none()));
+    drop(runtime);
+
+    let runtime = MockRuntime::new(MockConfig {
+        exit_after_frames: Some(1),
+        composition_layer_depth: true,
+        ..Default::default()
+    });
+    App::builder()
+        .entry(runtime.entry())
+        .depth_range(0.1, 50.0)
+        .reverse_z(true)
+        .build()
+        .unwrap()
+        .run()
+        .unwrap();
+
+    for (i, view) in views(&runtime).iter().enumerate() {
+        let depth = view.depth.as_ref().expect("depth info chained onto view");
+        assert_eq!(depth.image_array_index, i as u32);
+        assert_eq!((depth.min_depth, depth.max_depth), (0.0, 1.0));
+        assert_eq!((depth.near_z, depth.far_z), (50.0, 0.1));
+    }
+}
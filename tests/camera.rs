@@ -0,0 +1,78 @@
+//! Camera uniform tests. The renderer test needs a Vulkan device, see
+//! `common::vk_base`.
+
+use std::mem;
+
+use ash::vk;
+use glam::{Mat4, Vec3};
+use openxr as xr;
+
+use xrrs::{
+    graphics::{
+        camera::{CameraUniforms, MAX_VIEWS},
+        vk_renderer::VkRenderer,
+    },
+    math::DepthRange,
+    xr::{
+        frame::{EyeView, FrameContext},
+        swapchain::Swapchain,
+    },
+    Error,
+    Renderer,
+};
+
+mod common;
+
+fn eye(x: f32) -> EyeView {
+    let view = xr::View {
+        pose: xr::Posef {
+            orientation: xr::Quaternionf::IDENTITY,
+            position: xr::Vector3f { x, y: 1.6, z: 0.0 },
+        },
+        fov: xr::Fovf { angle_left: -0.8, angle_right: 0.7, angle_up: 0.75, angle_down: -0.85 },
+    };
+    EyeView::new(&view, DepthRange::default())
+}
+
+#[test]
+fn uniforms_match_the_std140_block() {
+    // Three mat4 arrays of MAX_VIEWS, no padding
+    assert_eq!(MAX_VIEWS, 2);
+    assert_eq!(mem::size_of::<CameraUniforms>(), 384);
+}
+
+#[test]
+fn from_frame_fills_one_slot_per_view() {
+    let views = vec![eye(-0.032), eye(0.032)];
+    let frame = FrameContext { views: views.clone(), ..Default::default() };
+
+    let uniforms = CameraUniforms::from_frame(&frame);
+    for i in 0..MAX_VIEWS {
+        assert_eq!(uniforms.view[i], views[i].view);
+        assert_eq!(uniforms.projection[i], views[i].projection);
+        assert_eq!(uniforms.view_projection[i], views[i].view_projection());
+    }
+    // Stage space to eye space moves the eye back to the origin
+    assert!(uniforms.view[1].transform_point3(Vec3::new(0.032, 1.6, 0.0)).abs_diff_eq(Vec3::ZERO, 1e-6));
+}
+
+#[test]
+fn from_frame_leaves_missing_views_at_identity() {
+    let frame = FrameContext { views: vec![eye(0.0)], ..Default::default() };
+
+    let uniforms = CameraUniforms::from_frame(&frame);
+    assert_eq!(uniforms.view[1], Mat4::IDENTITY);
+    assert_eq!(uniforms.projection[1], Mat4::IDENTITY);
+    assert_eq!(uniforms.view_projection[1], Mat4::IDENTITY);
+}
+
+#[test]
+fn renderer_rejects_more_views_than_the_uniforms_hold() {
+    let Some(vk_base) = common::vk_base("view count") else { return };
+    let swapchain = Swapchain::offscreen(&vk_base, vk::Extent2D { width: 4, height: 4 }, 4).unwrap();
+
+    assert!(matches!(
+        VkRenderer::new(vk_base.clone(), &swapchain),
+        Err(Error::TooManyViews { view_count: 4, max_views: 2 })
+    ));
+}
@@ -0,0 +1,67 @@
+//! Captured image tests. The swapchain test needs a Vulkan device, see
+//! `common::vk_base`.
+
+use std::{fs};
+
+use ash::vk;
+use xrrs::{
+    graphics::{
+        capture::{CaptureView, CapturedImage},
+    },
+    xr::swapchain::Swapchain,
+    Error,
+};
+
+mod common;
+
+fn gradient(width: u32, height: u32, seed: u8) -> CapturedImage {
+    CapturedImage {
+        width,
+        height,
+        pixels: (0..width * height * 4).map(|i| (i as u8).wrapping_mul(7).wrapping_add(seed)).collect(),
+    }
+}
+
+#[test]
+fn side_by_side_places_images_left_to_right() {
+    let left = gradient(2, 2, 0);
+    let right = gradient(3, 1, 100);
+
+    let image = CapturedImage::side_by_side(&[left.clone(), right.clone()]);
+    assert_eq!((image.width, image.height), (5, 2));
+
+    let pixel = |x: u32, y: u32| &image.pixels[((y * image.width + x) * 4) as usize..][..4];
+    assert_eq!(pixel(1, 1), &left.pixels[12..16]);
+    assert_eq!(pixel(2, 0), &right.pixels[..4]);
+    assert_eq!(pixel(4, 0), &right.pixels[8..12]);
+    // Shorter images are padded with transparent black
+    assert_eq!(pixel(2, 1), [0, 0, 0, 0]);
+}
+
+#[test]
+fn png_round_trips() {
+    let dir = common::temp_dir("capture-png");
+    let path = dir.join("capture.png");
+
+    let image = gradient(5, 3, 42);
+    image.save_png(&path).unwrap();
+    assert_eq!(CapturedImage::load_png(&path).unwrap(), image);
+
+    assert!(matches!(CapturedImage::load_png(dir.join("missing.png")), Err(Error::Io { .. })));
+    fs::write(&path, b"not a png").unwrap();
+    assert!(matches!(CapturedImage::load_png(&path), Err(Error::PngDecode(_))));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn swapchain_capture_checks_its_arguments() {
+    let Some(vk_base) = common::vk_base("swapchain capture") else { return };
+    let swapchain = Swapchain::offscreen(&vk_base, vk::Extent2D { width: 4, height: 4 }, 2).unwrap();
+
+    assert!(matches!(
+        swapchain.capture(&vk_base, CaptureView::Layer(2)),
+        Err(Error::InvalidCaptureLayer { layer: 2, view_count: 2 })
+    ));
+    assert!(matches!(swapchain.capture(&vk_base, CaptureView::SideBySide), Err(Error::NoRenderedImage)));
+}
@@ -0,0 +1,39 @@
+//! Helpers shared by the integration tests. Each test crate uses some of
+//! them.
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    process,
+    sync::Arc,
+};
+
+use xrrs::graphics::vk_base::VkBase;
+
+/// Set to skip tests that need a Vulkan device when there is none
+pub const SKIP_ENV: &str = "XRRS_SKIP_VULKAN_TESTS";
+
+/// A headless `VkBase` for `test`. Without a Vulkan device the test fails,
+/// unless `SKIP_ENV` is set to skip it instead, so a CI machine that lost its
+/// driver can't pass by running nothing.
+pub fn vk_base(test: &str) -> Option<Arc<VkBase>> {
+    match VkBase::headless() {
+        Ok(vk_base) => Some(vk_base),
+        Err(e) if env::var_os(SKIP_ENV).is_some() => {
+            eprintln!("Skipping {} test, no Vulkan device: {}", test, e);
+            None
+        }
+        Err(e) => panic!("no Vulkan device for the {} test, set {} to skip it: {}", test, SKIP_ENV, e),
+    }
+}
+
+/// An empty directory for `name` under the system temp directory, unique to
+/// this test run and canonicalized like the paths `ShaderWatcher` reports.
+/// Tests remove it when they pass.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("xrrs-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir.canonicalize().unwrap()
+}
@@ -0,0 +1,94 @@
+//! Descriptor layout, allocator and writer tests. Need a Vulkan device, see
+//! `common::vk_base`.
+
+use std::{collections::HashSet};
+
+use ash::vk;
+use xrrs::graphics::{
+    descriptor::{DescriptorAllocator, DescriptorLayoutBuilder, DescriptorWriter},
+    memory::{Buffer, MemoryLocation},
+    sampler::SamplerDesc,
+    texture::{ColorSpace, Texture},
+};
+
+mod common;
+
+#[test]
+fn layouts_are_cached_by_bindings() {
+    let Some(vk_base) = common::vk_base("descriptor layout") else { return };
+
+    let first = DescriptorLayoutBuilder::new()
+        .uniform_buffer(0, vk::ShaderStageFlags::VERTEX)
+        .combined_image_sampler(1, vk::ShaderStageFlags::FRAGMENT)
+        .build(&vk_base)
+        .unwrap();
+    let reordered = DescriptorLayoutBuilder::new()
+        .combined_image_sampler(1, vk::ShaderStageFlags::FRAGMENT)
+        .uniform_buffer(0, vk::ShaderStageFlags::VERTEX)
+        .build(&vk_base)
+        .unwrap();
+    let other_stages = DescriptorLayoutBuilder::new()
+        .uniform_buffer(0, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+        .combined_image_sampler(1, vk::ShaderStageFlags::FRAGMENT)
+        .build(&vk_base)
+        .unwrap();
+
+    assert_eq!(first, reordered);
+    assert_ne!(first, other_stages);
+    assert_eq!(vk_base.descriptor_layouts.len(), 2);
+}
+
+#[test]
+#[should_panic(expected = "binding 0 is described twice")]
+fn duplicate_bindings_panic() {
+    DescriptorLayoutBuilder::new()
+        .uniform_buffer(0, vk::ShaderStageFlags::VERTEX)
+        .storage_buffer(0, vk::ShaderStageFlags::VERTEX);
+}
+
+#[test]
+fn allocator_grows_and_reuses_pools() {
+    let Some(vk_base) = common::vk_base("descriptor allocator") else { return };
+
+    let layout = DescriptorLayoutBuilder::new()
+        .storage_buffer(0, vk::ShaderStageFlags::COMPUTE)
+        .build(&vk_base)
+        .unwrap();
+    let mut allocator = DescriptorAllocator::new(&vk_base, 2);
+
+    let sets = (0..5).map(|_| allocator.allocate(layout).unwrap()).collect::<HashSet<_>>();
+    assert_eq!(sets.len(), 5);
+    assert_eq!(allocator.pool_count(), 3);
+
+    allocator.reset().unwrap();
+    for _ in 0..5 {
+        allocator.allocate(layout).unwrap();
+    }
+    assert_eq!(allocator.pool_count(), 3);
+}
+
+#[test]
+fn writer_fills_every_descriptor_type() {
+    let Some(vk_base) = common::vk_base("descriptor writer") else { return };
+
+    let layout = DescriptorLayoutBuilder::new()
+        .uniform_buffer(0, vk::ShaderStageFlags::FRAGMENT)
+        .storage_buffer(1, vk::ShaderStageFlags::FRAGMENT)
+        .combined_image_sampler(2, vk::ShaderStageFlags::FRAGMENT)
+        .build(&vk_base)
+        .unwrap();
+    let uniforms = Buffer::new(&vk_base, 64, vk::BufferUsageFlags::UNIFORM_BUFFER, MemoryLocation::HostVisible).unwrap();
+    let storage = Buffer::new(&vk_base, 64, vk::BufferUsageFlags::STORAGE_BUFFER, MemoryLocation::DeviceLocal).unwrap();
+    let mut batch = vk_base.begin_upload();
+    let texture = Texture::from_rgba8(&mut batch, 1, 1, &[255; 4], ColorSpace::Srgb, false).unwrap();
+    batch.submit().unwrap().wait().unwrap();
+    let sampler = vk_base.samplers.get(&SamplerDesc::default()).unwrap();
+
+    let mut allocator = DescriptorAllocator::new(&vk_base, 1);
+    let set = allocator.allocate(layout).unwrap();
+    DescriptorWriter::new()
+        .uniform_buffer(0, &uniforms, 0, vk::WHOLE_SIZE)
+        .storage_buffer(1, &storage, 0, 64)
+        .texture(2, &texture, sampler)
+        .write(&vk_base, set);
+}
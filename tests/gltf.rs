@@ -0,0 +1,188 @@
+//! glTF loading tests. The upload test needs a Vulkan device, see
+//! `common::vk_base`.
+
+use std::{fs, path::PathBuf};
+
+use glam::{Mat4, Vec3, Vec4};
+use xrrs::assets::model::{AlphaMode, AssetWarning, ModelData, Projection};
+
+mod common;
+
+/// A triangle, a line primitive, a mesh reading a sparse accessor, a two
+/// level node hierarchy and a camera, in a `.gltf` with an external `.bin`
+fn write_scene(test: &str) -> PathBuf {
+    let mut bin = Vec::new();
+    for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+        bin.extend(value.to_le_bytes());
+    }
+    for index in [0u16, 1, 2, 0] {
+        bin.extend(index.to_le_bytes());
+    }
+    bin.extend(1u16.to_le_bytes());
+    bin.extend([0, 0]);
+    for value in [0.0f32, 2.0, 0.0] {
+        bin.extend(value.to_le_bytes());
+    }
+    assert_eq!(bin.len(), 60);
+
+    let json = r#"{
+        "asset": { "version": "2.0" },
+        "extensionsUsed": ["KHR_draco_mesh_compression", "KHR_texture_transform"],
+        "extensionsRequired": ["KHR_draco_mesh_compression"],
+        "buffers": [{ "uri": "scene.bin", "byteLength": 60 }],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 6 },
+            { "buffer": 0, "byteOffset": 44, "byteLength": 2 },
+            { "buffer": 0, "byteOffset": 48, "byteLength": 12 }
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+              "min": [0, 0, 0], "max": [1, 1, 0] },
+            { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" },
+            { "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [0, 2, 0],
+              "sparse": { "count": 1,
+                          "indices": { "bufferView": 2, "componentType": 5123 },
+                          "values": { "bufferView": 3 } } }
+        ],
+        "materials": [{
+            "name": "red",
+            "pbrMetallicRoughness": { "baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0.25, "roughnessFactor": 0.5 },
+            "alphaMode": "MASK",
+            "alphaCutoff": 0.25,
+            "doubleSided": true
+        }],
+        "meshes": [
+            { "name": "triangle", "primitives": [
+                { "attributes": { "POSITION": 0 }, "indices": 1, "material": 0 },
+                { "attributes": { "POSITION": 0 }, "mode": 1 }
+            ] },
+            { "primitives": [{ "attributes": { "POSITION": 2 } }] }
+        ],
+        "cameras": [{ "type": "perspective", "perspective": { "yfov": 1.0, "znear": 0.1 } }],
+        "nodes": [
+            { "name": "root", "translation": [1, 0, 0], "children": [1] },
+            { "name": "child", "translation": [0, 2, 0], "mesh": 0 },
+            { "camera": 0 },
+            { "mesh": 1 }
+        ],
+        "scenes": [{ "nodes": [0, 2] }],
+        "scene": 0
+    }"#;
+
+    let dir = common::temp_dir(&format!("gltf-{}", test));
+    fs::write(dir.join("scene.bin"), bin).unwrap();
+    fs::write(dir.join("scene.gltf"), json).unwrap();
+    dir.join("scene.gltf")
+}
+
+#[test]
+fn loads_meshes_materials_nodes_and_cameras() {
+    let model = ModelData::from_gltf_file(write_scene("contents")).unwrap();
+
+    let triangle = &model.meshes[0];
+    assert_eq!(triangle.name.as_deref(), Some("triangle"));
+    assert_eq!(triangle.primitives.len(), 1);
+    assert_eq!(triangle.primitives[0].material, Some(0));
+    assert_eq!(triangle.indices, [0, 1, 2]);
+    // No normals in the file, so they are generated flat
+    assert!(triangle.vertices.iter().all(|vertex| vertex.normal == Vec3::Z));
+    assert_eq!(triangle.vertices[1].position, Vec3::X);
+
+    let material = &model.materials[0];
+    assert_eq!(material.name.as_deref(), Some("red"));
+    assert_eq!(material.base_color_factor, Vec4::new(1.0, 0.0, 0.0, 1.0));
+    assert_eq!((material.metallic_factor, material.roughness_factor), (0.25, 0.5));
+    assert_eq!(material.alpha_mode, AlphaMode::Mask(0.25));
+    assert!(material.double_sided);
+
+    assert_eq!(model.roots, [0, 2]);
+    assert_eq!(model.nodes[1].parent, Some(0));
+    assert_eq!(model.world_transform(1), Mat4::from_translation(Vec3::new(1.0, 2.0, 0.0)));
+    assert_eq!(model.nodes[2].camera, Some(0));
+    assert_eq!(
+        model.cameras[0].projection,
+        Projection::Perspective { yfov: 1.0, aspect_ratio: None, znear: 0.1, zfar: None }
+    );
+}
+
+#[test]
+fn unsupported_features_are_warnings() {
+    let model = ModelData::from_gltf_file(write_scene("warnings")).unwrap();
+
+    assert_eq!(
+        model.warnings,
+        [
+            AssetWarning::Extension { name: "KHR_draco_mesh_compression".to_owned(), required: true },
+            AssetWarning::Extension { name: "KHR_texture_transform".to_owned(), required: false },
+            AssetWarning::PrimitiveMode { mesh: 0, primitive: 1, mode: "Lines".to_owned() },
+            AssetWarning::SparseAccessor { mesh: 1, primitive: 0, accessor: 2 },
+        ]
+    );
+    assert!(model.meshes[1].primitives.is_empty());
+}
+
+#[test]
+fn invalid_files_fail() {
+    let result = ModelData::from_gltf_slice(br#"{ "asset": { "version": "2.0" }, "scene": 3 }"#, ".".as_ref());
+    assert!(matches!(result, Err(xrrs::Error::Gltf(_))));
+}
+
+#[test]
+fn indices_past_the_vertices_are_warnings() {
+    let mut bin = Vec::new();
+    for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+        bin.extend(value.to_le_bytes());
+    }
+    for index in [0u16, 1, 3] {
+        bin.extend(index.to_le_bytes());
+    }
+    let json = r#"{
+        "asset": { "version": "2.0" },
+        "buffers": [{ "uri": "scene.bin", "byteLength": 42 }],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 6 }
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+              "min": [0, 0, 0], "max": [1, 1, 0] },
+            { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }
+        ],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1 }] }]
+    }"#;
+    let dir = common::temp_dir("gltf-bad-index");
+    fs::write(dir.join("scene.bin"), bin).unwrap();
+
+    let model = ModelData::from_gltf_slice(json.as_bytes(), &dir).unwrap();
+    assert_eq!(
+        model.warnings,
+        [AssetWarning::IndexOutOfRange { mesh: 0, primitive: 0, index: 3, vertex_count: 3 }]
+    );
+    assert!(model.meshes[0].primitives.is_empty());
+}
+
+#[test]
+fn node_cycles_and_shared_children_fail() {
+    let cycle = br#"{ "asset": { "version": "2.0" }, "nodes": [{ "children": [1] }, { "children": [0] }] }"#;
+    let own_child = br#"{ "asset": { "version": "2.0" }, "nodes": [{ "children": [0] }] }"#;
+    let shared = br#"{ "asset": { "version": "2.0" }, "nodes": [{ "children": [2] }, { "children": [2] }, {}] }"#;
+    for json in [&cycle[..], own_child, shared] {
+        let result = ModelData::from_gltf_slice(json, ".".as_ref());
+        assert!(matches!(result, Err(xrrs::Error::Gltf(_))));
+    }
+}
+
+#[test]
+fn upload_skips_empty_meshes() {
+    let Some(vk_base) = common::vk_base("glTF upload") else { return };
+
+    let mut batch = vk_base.begin_upload();
+    let model = ModelData::from_gltf_file(write_scene("upload")).unwrap().upload(&mut batch).unwrap();
+    batch.submit().unwrap().wait().unwrap();
+
+    let triangle = model.meshes[0].as_ref().unwrap();
+    assert_eq!(triangle.mesh.index_count, 3);
+    assert_eq!(triangle.materials, [Some(0)]);
+    assert!(model.meshes[1].is_none());
+}
@@ -0,0 +1,102 @@
+//! Golden-image tests for the library renderers.
+//!
+//! References live in `tests/golden` and a missing one fails its test; set
+//! `XRRS_UPDATE_GOLDEN=1` to write new references or rewrite all of them after
+//! an intended change. Tests that render need a Vulkan device, see
+//! `common::vk_base`.
+
+use std::{env, fs};
+
+use xrrs::graphics::{
+    capture::CapturedImage,
+    golden::{self, GoldenOutcome, GoldenTest},
+    vk_renderer::VkRenderer,
+};
+
+mod common;
+
+fn solid(width: u32, height: u32, rgba: [u8; 4]) -> CapturedImage {
+    CapturedImage {
+        width,
+        height,
+        pixels: rgba.repeat((width * height) as usize),
+    }
+}
+
+#[test]
+fn vk_renderer_matches_reference() {
+    let Some(vk_base) = common::vk_base("vk_renderer golden") else { return };
+
+    let mut test = GoldenTest::new("vk_renderer");
+    // Drivers may disagree on pixels whose center lies right on an edge
+    test.max_mismatched_pixels = 8;
+    test.run::<VkRenderer>(&vk_base)
+        .unwrap()
+        .assert_matched();
+}
+
+#[test]
+fn compare_counts_pixels_outside_tolerance() {
+    let reference = solid(4, 2, [100, 100, 100, 255]);
+    let mut actual = reference.clone();
+    actual.pixels[0] = 102;
+    actual.pixels[4] = 110;
+    actual.pixels[9] = 90;
+
+    let diff = golden::compare(&reference, &actual, 2);
+    assert_eq!(diff.mismatched_pixels, 2);
+    assert_eq!(diff.max_difference, 10);
+    assert_eq!((diff.image.width, diff.image.height), (4, 2));
+
+    assert_eq!(golden::compare(&reference, &actual, 10).mismatched_pixels, 0);
+}
+
+#[test]
+fn mismatch_writes_actual_and_diff_images() {
+    if env::var_os(golden::UPDATE_ENV).is_some() {
+        return;
+    }
+
+    let dir = common::temp_dir("golden-mismatch");
+    let mut test = GoldenTest::new("mismatch");
+    test.reference_dir = dir.join("reference");
+    test.output_dir = dir.join("output");
+
+    let reference = solid(8, 8, [0, 0, 0, 255]);
+    match test.check(&reference).unwrap() {
+        GoldenOutcome::MissingReference { reference_path, actual_path } => {
+            assert_eq!(reference_path, test.reference_path());
+            assert!(!reference_path.exists());
+            assert_eq!(CapturedImage::load_png(&actual_path).unwrap(), reference);
+        }
+        other => panic!("unexpected outcome {:?}", other),
+    }
+
+    fs::create_dir_all(&test.reference_dir).unwrap();
+    reference.save_png(test.reference_path()).unwrap();
+    assert!(matches!(test.check(&reference).unwrap(), GoldenOutcome::Matched));
+
+    let actual = solid(8, 8, [255, 255, 255, 255]);
+    match test.check(&actual).unwrap() {
+        GoldenOutcome::Mismatched { mismatched_pixels, max_difference, actual_path, diff_path } => {
+            assert_eq!(mismatched_pixels, 64);
+            assert_eq!(max_difference, 255);
+            assert_eq!(CapturedImage::load_png(&actual_path).unwrap(), actual);
+            assert_eq!(CapturedImage::load_png(&diff_path).unwrap().pixels[..4], [255, 0, 0, 255]);
+        }
+        other => panic!("unexpected outcome {:?}", other),
+    }
+
+    assert!(matches!(
+        test.check(&solid(4, 8, [0, 0, 0, 255])).unwrap(),
+        GoldenOutcome::SizeMismatch { expected: (8, 8), actual: (4, 8), .. }
+    ));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn vk_renderer_reference_is_committed() {
+    let reference = CapturedImage::load_png(GoldenTest::new("vk_renderer").reference_path()).unwrap();
+    assert_eq!((reference.width, reference.height), (256, 128));
+}
@@ -0,0 +1,122 @@
+//! Shader watching and runtime shader loading tests. Loading needs a Vulkan
+//! device, see `common::vk_base`.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use ash::vk;
+use xrrs::{
+    graphics::{
+        camera::CameraBuffers,
+        hot_reload::{ReloadablePipeline, ShaderWatcher},
+        pipeline::Pipeline,
+        render_pass::RenderPass,
+        shader_module::{ShaderCompiler, ShaderModule},
+    },
+    Error,
+};
+
+mod common;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+#[test]
+fn watcher_reports_changed_files_once_settled() {
+    let dir = common::temp_dir("hot-reload-watcher");
+    let shader = dir.join("triangle.frag.spv");
+    let other = dir.join("notes.txt");
+    fs::write(&shader, b"old").unwrap();
+
+    let mut watcher = ShaderWatcher::new().unwrap();
+    watcher.watch(&shader).unwrap();
+    assert!(watcher.changed().is_empty());
+
+    fs::write(&other, b"unwatched").unwrap();
+    fs::write(&shader, b"new").unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let changed = loop {
+        let changed = watcher.changed();
+        if !changed.is_empty() || Instant::now() > deadline {
+            break changed;
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    assert_eq!(changed.into_iter().collect::<Vec<_>>(), vec![shader]);
+    assert!(watcher.changed().is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_shader_files_are_rejected() {
+    let Some(vk_base) = common::vk_base("shader file") else { return };
+    let dir = common::temp_dir("hot-reload-invalid");
+
+    let not_spirv = dir.join("not_spirv.spv");
+    fs::write(&not_spirv, [0u8; 16]).unwrap();
+    assert!(matches!(ShaderModule::from_file(&vk_base, &not_spirv), Err(Error::InvalidSpirv(_))));
+
+    // A valid header followed by an instruction claiming more words than
+    // there are
+    let corrupt = dir.join("corrupt.spv");
+    let words = [SPIRV_MAGIC, 0x0001_0000, 0, 16, 0, 0x0010_0011];
+    fs::write(&corrupt, words.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<_>>()).unwrap();
+    assert!(matches!(ShaderModule::from_file(&vk_base, &corrupt), Err(Error::InvalidSpirv(_))));
+
+    let unknown_version = dir.join("unknown_version.spv");
+    let words = [SPIRV_MAGIC, 0x0002_0000, 0, 16, 0];
+    fs::write(&unknown_version, words.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<_>>()).unwrap();
+    assert!(matches!(ShaderModule::from_file(&vk_base, &unknown_version), Err(Error::InvalidSpirv(_))));
+
+    let truncated = dir.join("truncated.spv");
+    fs::write(&truncated, [0x03, 0x02, 0x23, 0x07, 0x00]).unwrap();
+    assert!(matches!(ShaderModule::from_file(&vk_base, &truncated), Err(Error::Io { .. })));
+
+    assert!(matches!(ShaderModule::from_file(&vk_base, dir.join("missing.spv")), Err(Error::Io { .. })));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failed_reload_keeps_the_previous_pipeline() {
+    let Some(vk_base) = common::vk_base("pipeline reload") else { return };
+    let dir = common::temp_dir("hot-reload");
+    let shader_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/graphics/shaders");
+    for name in ["camera.wgsl", "triangle.vert.wgsl", "triangle.frag.wgsl"] {
+        fs::copy(shader_dir.join(name), dir.join(name)).unwrap();
+    }
+    let render_pass = RenderPass::new(&vk_base.device, 2, vk_base.depth_format).unwrap();
+    let set_layouts = [CameraBuffers::layout(&vk_base).unwrap()];
+
+    let mut pipeline = ReloadablePipeline::new(&vk_base,
+                                               Pipeline::triangle_builder(render_pass.handle, &set_layouts),
+                                               ShaderCompiler::new().define("MULTIVIEW", "1"),
+                                               &[
+                                                   (vk::ShaderStageFlags::VERTEX, dir.join("triangle.vert.wgsl")),
+                                                   (vk::ShaderStageFlags::FRAGMENT, dir.join("triangle.frag.wgsl")),
+                                               ]
+    ).unwrap();
+    assert_eq!(pipeline.sources().count(), 3);
+    let original = pipeline.handle;
+
+    let fragment = dir.join("triangle.frag.wgsl");
+    let changed = HashSet::from([fragment.clone()]);
+    fs::write(&fragment, "@fragment fn main() -> @location(0) vec4<f32> { return oops; }").unwrap();
+    assert!(!pipeline.reload_changed(&vk_base, &changed));
+    assert_eq!(pipeline.handle, original);
+
+    fs::write(&fragment, "@fragment fn main() -> @location(0) vec4<f32> { return vec4(1.0); }").unwrap();
+    assert!(!pipeline.reload_changed(&vk_base, &HashSet::new()));
+    assert!(pipeline.reload_changed(&vk_base, &changed));
+    assert_ne!(pipeline.handle, original);
+
+    drop(pipeline);
+    vk_base.device.destroy_render_pass(render_pass.handle);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,130 @@
+use xrrs::xr::{
+    action::{ActionManifest, ActionType},
+    bindings::Input,
+};
+
+const VALID: &str = r#"
+[[action_sets]]
+name = "gameplay"
+localized_name = "Gameplay"
+priority = 1
+
+[[action_sets.actions]]
+name = "fire"
+localized_name = "Fire"
+type = "float"
+subaction_paths = ["/user/hand/left", "/user/hand/right"]
+input = "trigger"
+
+[[action_sets.actions]]
+name = "hand"
+localized_name = "Hand"
+type = "pose"
+subaction_paths = ["/user/hand/left", "/user/hand/right"]
+
+[[bindings]]
+profile = "/interaction_profiles/valve/index_controller"
+action = "gameplay/fire"
+paths = ["/user/hand/right/input/trigger/click"]
+
+[[bindings]]
+profile = "/interaction_profiles/oculus/touch_controller"
+action = "gameplay/hand"
+paths = ["/user/hand/left/input/aim/pose", "/user/hand/right/input/aim/pose"]
+"#;
+
+#[test]
+fn valid_manifest_loads() {
+    let manifest = ActionManifest::from_toml(VALID).unwrap();
+
+    assert_eq!(manifest.action_sets.len(), 1);
+    let set = &manifest.action_sets[0];
+    assert_eq!((set.name.as_str(), set.priority), ("gameplay", 1));
+    assert_eq!(set.actions[0].action_type, ActionType::Float);
+    assert_eq!(set.actions[0].input, Some(Input::Trigger));
+    assert_eq!(set.actions[1].subaction_paths.len(), 2);
+
+    assert_eq!(manifest.bindings.len(), 3);
+    assert_eq!(manifest.bindings[0].path, "/user/hand/right/input/trigger/click");
+}
+
+#[test]
+fn invalid_manifest_reports_every_problem_with_its_line() {
+    let source = r#"
+[[action_sets]]
+name = "gameplay"
+localized_name = "Gameplay"
+
+[[action_sets.actions]]
+name = "fire"
+localized_name = "Fire"
+type = "float"
+
+[[action_sets.actions]]
+name = "fire"
+localized_name = "Fire again"
+type = "trigger"
+
+[[bindings]]
+profile = "/interaction_profiles/valve/index_controller"
+action = "gameplay/jump"
+paths = ["/user/hand/right/input/a/click"]
+
+[[bindings]]
+profile = "/interaction_profiles/valve/index_controller"
+action = "gameplay/fire"
+paths = ["/user/hand/right/input/grip/pose", "/user/hand/right/input/banana/click"]
+"#;
+
+    let diagnostics = ActionManifest::from_toml(source).unwrap_err();
+    let lines = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        lines,
+        [
+            (12, "duplicate action gameplay/fire"),
+            (14, "unknown action type trigger"),
+            (18, "unknown action gameplay/jump"),
+            (24, "Float action gameplay/fire cannot be bound to /user/hand/right/input/grip/pose"),
+            (24, "unknown path /user/hand/right/input/banana/click for /interaction_profiles/valve/index_controller"),
+        ]
+    );
+}
+
+#[test]
+fn input_of_the_wrong_type_is_reported_at_its_line() {
+    let source = r#"
+[[action_sets]]
+name = "gameplay"
+localized_name = "Gameplay"
+
+[[action_sets.actions]]
+name = "move"
+localized_name = "Move"
+type = "float"
+input = "thumbstick"
+
+[[action_sets.actions]]
+name = "hand"
+localized_name = "Hand"
+type = "pose"
+input = "grip_pose"
+"#;
+
+    let diagnostics = ActionManifest::from_toml(source).unwrap_err();
+
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!((diagnostics[0].line, diagnostics[0].column), (10, 9));
+    assert_eq!(diagnostics[0].message, "Float action gameplay/move cannot be bound to input thumbstick");
+}
+
+#[test]
+fn syntax_errors_have_a_line() {
+    let diagnostics = ActionManifest::from_toml("[[action_sets]]\nname = \n").unwrap_err();
+
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].line, 2);
+}
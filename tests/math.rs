@@ -0,0 +1,82 @@
+use glam::Vec4;
+use openxr as xr;
+
+use xrrs::math::{self, DepthRange};
+
+const FOV: xr::Fovf = xr::Fovf {
+    angle_left: -0.785398,
+    angle_right: 0.785398,
+    angle_up: 0.785398,
+    angle_down: -0.785398,
+};
+
+fn ndc(projection: glam::Mat4, point: Vec4) -> glam::Vec3 {
+    let clip = projection * point;
+    clip.truncate() / clip.w
+}
+
+#[test]
+fn projection_maps_near_and_far_to_vulkan_depth() {
+    let depth = DepthRange { near: 0.1, far: 10.0, reverse_z: false };
+    let projection = math::projection_from_fov(&FOV, depth);
+
+    assert!(ndc(projection, Vec4::new(0.0, 0.0, -0.1, 1.0)).z.abs() < 1e-5);
+    assert!((ndc(projection, Vec4::new(0.0, 0.0, -10.0, 1.0)).z - 1.0).abs() < 1e-5);
+
+    // Y points down in Vulkan clip space
+    let up = ndc(projection, Vec4::new(0.0, 1.0, -1.0, 1.0));
+    assert!((up.y + 1.0).abs() < 1e-4);
+}
+
+#[test]
+fn reverse_z_swaps_near_and_far() {
+    let depth = DepthRange { near: 0.1, far: 10.0, reverse_z: true };
+    let projection = math::projection_from_fov(&FOV, depth);
+
+    assert!((ndc(projection, Vec4::new(0.0, 0.0, -0.1, 1.0)).z - 1.0).abs() < 1e-5);
+    assert!(ndc(projection, Vec4::new(0.0, 0.0, -10.0, 1.0)).z.abs() < 1e-5);
+
+    let infinite = math::projection_from_fov(&FOV, DepthRange { far: f32::INFINITY, ..depth });
+    assert!((ndc(infinite, Vec4::new(0.0, 0.0, -0.1, 1.0)).z - 1.0).abs() < 1e-5);
+    assert!(ndc(infinite, Vec4::new(0.0, 0.0, -1.0e6, 1.0)).z < 1e-6);
+}
+
+#[test]
+fn depth_test_state_follows_reverse_z() {
+    let forward = DepthRange::default();
+    let reverse = DepthRange { reverse_z: true, ..forward };
+
+    assert_eq!(forward.clear_depth(), 1.0);
+    assert_eq!(forward.compare_op(), ash::vk::CompareOp::LESS_OR_EQUAL);
+    assert_eq!(reverse.clear_depth(), 0.0);
+    assert_eq!(reverse.compare_op(), ash::vk::CompareOp::GREATER_OR_EQUAL);
+}
+
+#[test]
+fn asymmetric_fov_edges_map_to_clip_edges() {
+    let fov = xr::Fovf {
+        angle_left: -0.9,
+        angle_right: 0.6,
+        angle_up: 0.7,
+        angle_down: -0.8,
+    };
+    let projection = math::projection_from_fov(&fov, DepthRange::default());
+
+    let left = ndc(projection, Vec4::new(-(0.9f32).tan(), 0.0, -1.0, 1.0));
+    let right = ndc(projection, Vec4::new((0.6f32).tan(), 0.0, -1.0, 1.0));
+    let top = ndc(projection, Vec4::new(0.0, (0.7f32).tan(), -1.0, 1.0));
+    assert!((left.x + 1.0).abs() < 1e-4);
+    assert!((right.x - 1.0).abs() < 1e-4);
+    assert!((top.y + 1.0).abs() < 1e-4);
+}
+
+#[test]
+fn view_matrix_moves_eye_to_origin() {
+    let pose = xr::Posef {
+        orientation: xr::Quaternionf::IDENTITY,
+        position: xr::Vector3f { x: 0.032, y: 1.6, z: 0.0 },
+    };
+    let eye = math::view_from_pose(&pose) * Vec4::new(0.032, 1.6, 0.0, 1.0);
+
+    assert!(eye.truncate().length() < 1e-6);
+}
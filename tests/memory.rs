@@ -0,0 +1,97 @@
+//! Allocator tests. Need a Vulkan device, see `common::vk_base`.
+
+use std::sync::Arc;
+
+use ash::vk;
+use xrrs::graphics::{
+    memory::{Buffer, Image, MemoryLocation, BLOCK_SIZE},
+    vk_base::VkBase,
+};
+
+mod common;
+
+fn image(vk_base: &Arc<VkBase>) -> Image {
+    Image::new(
+        vk_base,
+        &vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(vk::Format::R8G8B8A8_UNORM)
+            .extent(vk::Extent3D { width: 16, height: 16, depth: 1 })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::SAMPLED)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE),
+        MemoryLocation::DeviceLocal,
+    )
+    .unwrap()
+}
+
+#[test]
+fn small_resources_share_a_block() {
+    let Some(vk_base) = common::vk_base("shared block") else { return };
+    let before = vk_base.allocator.stats();
+
+    let buffers = (0..4)
+        .map(|_| {
+            Buffer::new(&vk_base, 256, vk::BufferUsageFlags::VERTEX_BUFFER, MemoryLocation::DeviceLocal).unwrap()
+        })
+        .collect::<Vec<_>>();
+    let image = image(&vk_base);
+
+    for pair in buffers.windows(2) {
+        assert_eq!(pair[0].allocation.memory, pair[1].allocation.memory);
+        assert!(pair[0].allocation.offset + pair[0].allocation.size <= pair[1].allocation.offset);
+    }
+
+    // Linear and non-linear resources may not share a granularity page
+    let granularity = vk_base.physical_device.properties.limits.buffer_image_granularity;
+    let pages = |offset: vk::DeviceSize, size: vk::DeviceSize| offset / granularity..=(offset + size - 1) / granularity;
+    let image_pages = pages(image.allocation.offset, image.allocation.size);
+    for buffer in buffers.iter().filter(|buffer| buffer.allocation.memory == image.allocation.memory) {
+        let buffer_pages = pages(buffer.allocation.offset, buffer.allocation.size);
+        assert!(buffer_pages.end() < image_pages.start() || buffer_pages.start() > image_pages.end());
+    }
+
+    let stats = vk_base.allocator.stats();
+    assert_eq!(stats.allocation_count(), before.allocation_count() + 5);
+
+    drop(buffers);
+    drop(image);
+    assert_eq!(vk_base.allocator.stats().allocation_count(), before.allocation_count());
+}
+
+#[test]
+fn large_buffers_get_dedicated_blocks() {
+    let Some(vk_base) = common::vk_base("dedicated block") else { return };
+
+    let buffer = Buffer::new(&vk_base, BLOCK_SIZE, vk::BufferUsageFlags::TRANSFER_SRC, MemoryLocation::HostVisible)
+        .unwrap();
+    let stats = vk_base.allocator.stats();
+    assert!(stats
+        .pools
+        .iter()
+        .any(|pool| pool.location == MemoryLocation::HostVisible && pool.dedicated_block_count == 1));
+
+    drop(buffer);
+    assert!(vk_base
+        .allocator
+        .stats()
+        .pools
+        .iter()
+        .all(|pool| pool.dedicated_block_count == 0));
+}
+
+#[test]
+fn host_visible_buffers_are_mapped() {
+    let Some(vk_base) = common::vk_base("mapped buffer") else { return };
+
+    let upload = Buffer::new(&vk_base, 16, vk::BufferUsageFlags::UNIFORM_BUFFER, MemoryLocation::HostVisible).unwrap();
+    upload.write(4, &[1u32, 2, 3]).unwrap();
+    assert_eq!(&upload.mapped().unwrap()[4..8], &1u32.to_ne_bytes());
+
+    let device = Buffer::new(&vk_base, 16, vk::BufferUsageFlags::VERTEX_BUFFER, MemoryLocation::DeviceLocal).unwrap();
+    assert!(device.mapped().is_none());
+    assert!(matches!(device.write(0, &[0u8]), Err(xrrs::Error::MemoryNotMapped)));
+}
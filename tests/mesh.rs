@@ -0,0 +1,75 @@
+//! Vertex layout and mesh tests. The upload test needs a Vulkan device, see
+//! `common::vk_base`.
+
+use std::{mem, panic::{self, AssertUnwindSafe}};
+
+use ash::vk;
+use glam::{Vec2, Vec3};
+use xrrs::graphics::mesh::{DrawRange, Mesh, VertexLayout};
+
+mod common;
+
+#[derive(Clone, Copy, VertexLayout)]
+#[repr(C)]
+struct Vertex {
+    position: Vec3,
+    uv: Vec2,
+    #[vertex(format = "R8G8B8A8_UNORM")]
+    color: [u8; 4],
+    joints: [u16; 4],
+}
+
+#[test]
+fn derived_layout_matches_struct() {
+    let binding = Vertex::binding(1);
+    assert_eq!(binding.binding, 1);
+    assert_eq!(binding.stride as usize, mem::size_of::<Vertex>());
+    assert_eq!(binding.input_rate, vk::VertexInputRate::VERTEX);
+
+    let attributes = Vertex::attributes(1);
+    let summary = attributes
+        .iter()
+        .map(|attribute| (attribute.location, attribute.binding, attribute.format, attribute.offset))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            (0, 1, vk::Format::R32G32B32_SFLOAT, 0),
+            (1, 1, vk::Format::R32G32_SFLOAT, 12),
+            (2, 1, vk::Format::R8G8B8A8_UNORM, 20),
+            (3, 1, vk::Format::R16G16B16A16_UINT, 24),
+        ]
+    );
+}
+
+#[test]
+fn mesh_upload_records_counts_and_ranges() {
+    let Some(vk_base) = common::vk_base("mesh upload") else { return };
+
+    let vertices = [Vertex { position: Vec3::ZERO, uv: Vec2::ZERO, color: [255; 4], joints: [0; 4] }; 4];
+    let mut batch = vk_base.begin_upload();
+    let mesh = Mesh::indexed(&mut batch, &vertices, &[0u16, 1, 2, 2, 1, 3]).unwrap();
+    let unindexed = Mesh::new(&mut batch, &vertices).unwrap();
+    batch.submit().unwrap().wait().unwrap();
+
+    assert!(mesh.is_indexed());
+    assert_eq!(mesh.index_type, vk::IndexType::UINT16);
+    assert_eq!((mesh.vertex_count, mesh.index_count), (4, 6));
+    assert_eq!(mesh.ranges, [DrawRange { first: 0, count: 6, vertex_offset: 0 }]);
+    assert_eq!(mesh.vertex_buffer.size as usize, mem::size_of_val(&vertices));
+
+    let mesh = mesh.with_ranges(vec![
+        DrawRange { first: 0, count: 3, vertex_offset: 0 },
+        DrawRange { first: 3, count: 3, vertex_offset: 0 },
+    ]);
+    assert_eq!(mesh.ranges.len(), 2);
+
+    assert!(!unindexed.is_indexed());
+    assert_eq!(unindexed.ranges, [DrawRange { first: 0, count: 4, vertex_offset: 0 }]);
+
+    // Would wrap around to 0 without the overflow check
+    let overflowing = panic::catch_unwind(AssertUnwindSafe(|| {
+        unindexed.with_ranges(vec![DrawRange { first: 1, count: u32::MAX, vertex_offset: 0 }])
+    }));
+    assert!(overflowing.is_err());
+}
@@ -0,0 +1,112 @@
+//! OBJ loading tests
+
+use std::{fs, path::PathBuf};
+
+use glam::{Vec2, Vec3, Vec4};
+use xrrs::assets::model::{AlphaMode, AssetWarning, ModelData, TextureRef};
+
+mod common;
+
+const OBJ: &str = "\
+mtllib model.mtl
+o quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+usemtl textured
+f 1/1 2/2 3/3 4/4
+o triangle
+v 0 0 1
+v 1 0 1
+v 0 1 1
+vn 0 0 -1
+usemtl untextured
+f 5//1 6//1 7//1
+";
+
+const MTL: &str = "\
+newmtl textured
+Kd 1 0 0
+d 0.5
+map_Kd checker.png
+newmtl untextured
+Kd 0 1 0
+Ns 0
+map_Bump missing.png
+";
+
+const CHECKER: [u8; 16] = [255, 255, 255, 255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255, 255];
+
+fn write_files(test: &str, files: &[(&str, &[u8])]) -> PathBuf {
+    let dir = common::temp_dir(&format!("obj-{}", test));
+    for (name, contents) in files {
+        fs::write(dir.join(name), contents).unwrap();
+    }
+    dir.join("model.obj")
+}
+
+#[test]
+fn loads_groups_and_materials() {
+    let path = write_files("model", &[("model.obj", OBJ.as_bytes()), ("model.mtl", MTL.as_bytes())]);
+    image::RgbaImage::from_raw(2, 2, CHECKER.to_vec())
+        .unwrap()
+        .save(path.with_file_name("checker.png"))
+        .unwrap();
+    let model = ModelData::from_obj_file(&path).unwrap();
+
+    let names = model.meshes.iter().map(|mesh| mesh.name.as_deref().unwrap()).collect::<Vec<_>>();
+    assert_eq!(names, ["quad", "triangle"]);
+    assert_eq!(model.roots, [0, 1]);
+    assert_eq!(model.nodes[1].mesh, Some(1));
+
+    // The quad is triangulated and, lacking normals, unwelded with flat ones
+    let quad = &model.meshes[0];
+    assert_eq!(quad.indices, [0, 1, 2, 3, 4, 5]);
+    assert!(quad.vertices.iter().all(|vertex| vertex.normal == Vec3::Z));
+    assert_eq!(quad.vertices[0].tex_coord, Vec2::new(0.0, 1.0));
+    assert_eq!(quad.primitives[0].material, Some(0));
+
+    let triangle = &model.meshes[1];
+    assert_eq!(triangle.vertices.len(), 3);
+    assert!(triangle.vertices.iter().all(|vertex| vertex.normal == Vec3::NEG_Z));
+    assert_eq!(triangle.primitives[0].material, Some(1));
+
+    let textured = &model.materials[0];
+    assert_eq!(textured.base_color_factor, Vec4::new(1.0, 0.0, 0.0, 0.5));
+    assert_eq!(textured.alpha_mode, AlphaMode::Blend);
+    assert_eq!(textured.base_color_texture, Some(TextureRef { texture: 0, tex_coord: 0 }));
+    assert_eq!(model.textures[0].image, 0);
+    assert_eq!((model.images[0].width, model.images[0].height), (2, 2));
+    assert_eq!(model.images[0].pixels, CHECKER);
+    assert!(model.images[0].srgb);
+
+    let untextured = &model.materials[1];
+    assert_eq!(untextured.base_color_factor, Vec4::new(0.0, 1.0, 0.0, 1.0));
+    assert_eq!(untextured.roughness_factor, 1.0);
+    assert_eq!(untextured.normal_texture, None);
+    assert!(matches!(
+        &model.warnings[..],
+        [AssetWarning::Texture { path, .. }] if path.ends_with("missing.png")
+    ));
+}
+
+#[test]
+fn missing_material_library_is_a_warning() {
+    let path = write_files("no-mtl", &[("model.obj", OBJ.as_bytes())]);
+    let model = ModelData::from_obj_file(path).unwrap();
+
+    assert!(matches!(&model.warnings[..], [AssetWarning::MaterialLibrary { .. }]));
+    assert!(model.materials.is_empty());
+    assert_eq!(model.meshes.len(), 2);
+}
+
+#[test]
+fn missing_file_fails() {
+    let result = ModelData::from_obj_file(std::env::temp_dir().join("xrrs-no-such-model.obj"));
+    assert!(matches!(result, Err(xrrs::Error::Obj(_))));
+}
@@ -0,0 +1,61 @@
+//! Graphics pipeline tests against the library render pass. Need a Vulkan
+//! device, see `common::vk_base`.
+
+use std::{path::Path};
+
+use ash::vk;
+use xrrs::{
+    assets::model::Vertex,
+    graphics::{
+        camera::CameraBuffers,
+        pipeline::{GraphicsPipelineBuilder, MeshPushConstants, Pipeline, BLEND_ALPHA},
+        render_pass::RenderPass,
+        shader_module::{ShaderCompiler, ShaderModule},
+    },
+};
+
+mod common;
+
+#[test]
+fn presets_build_against_the_library_render_pass() {
+    let Some(vk_base) = common::vk_base("pipeline preset") else { return };
+    let render_pass = RenderPass::new(&vk_base.device, 2, vk_base.depth_format).unwrap();
+    let set_layouts = [CameraBuffers::layout(&vk_base).unwrap()];
+
+    let triangle = Pipeline::triangle(&vk_base, render_pass.handle, &set_layouts).unwrap();
+    let mesh = Pipeline::mesh(&vk_base, render_pass.handle, &set_layouts).unwrap();
+    assert_ne!(triangle.handle, vk::Pipeline::null());
+    assert_ne!(mesh.handle, vk::Pipeline::null());
+
+    drop((triangle, mesh));
+    vk_base.device.destroy_render_pass(render_pass.handle);
+}
+
+#[test]
+fn builder_takes_runtime_compiled_shaders_and_custom_state() {
+    let Some(vk_base) = common::vk_base("pipeline builder") else { return };
+    let render_pass = RenderPass::new(&vk_base.device, 2, vk_base.depth_format).unwrap();
+    let set_layouts = [CameraBuffers::layout(&vk_base).unwrap()];
+    let shader_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/graphics/shaders");
+
+    let compiler = ShaderCompiler::new().define("MULTIVIEW", "1");
+    let vert = ShaderModule::compile_file(&vk_base, &compiler, shader_dir.join("mesh.vert.wgsl")).unwrap();
+    let frag = ShaderModule::from_file(&vk_base, shader_dir.join("mesh.frag.wgsl")).unwrap();
+
+    let pipeline = GraphicsPipelineBuilder::new(render_pass.handle)
+        .vertex_shader(&vert)
+        .fragment_shader(&frag)
+        .vertex_layout::<Vertex>(0)
+        .push_constants::<MeshPushConstants>(MeshPushConstants::STAGES)
+        .set_layouts(&set_layouts)
+        .cull_mode(vk::CullModeFlags::BACK, vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth(true, false)
+        .blend_attachments(&[BLEND_ALPHA])
+        .dynamic_state(vk::DynamicState::LINE_WIDTH)
+        .build(&vk_base)
+        .unwrap();
+    assert_ne!(pipeline.handle, vk::Pipeline::null());
+
+    drop(pipeline);
+    vk_base.device.destroy_render_pass(render_pass.handle);
+}
@@ -0,0 +1,88 @@
+//! Pipeline cache persistence tests. The round trips need a Vulkan device, see
+//! `common::vk_base`.
+
+use std::{fs};
+
+use ash::vk;
+use xrrs::graphics::pipeline_cache::{self, PipelineCache};
+
+mod common;
+
+fn properties() -> vk::PhysicalDeviceProperties {
+    vk::PhysicalDeviceProperties {
+        vendor_id: 0x10de,
+        device_id: 0x2204,
+        pipeline_cache_uuid: *b"0123456789abcdef",
+        ..Default::default()
+    }
+}
+
+fn header(length: u32, version: u32, vendor_id: u32, device_id: u32, uuid: &[u8; 16]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for field in [length, version, vendor_id, device_id] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(uuid);
+    data
+}
+
+#[test]
+fn header_is_checked_against_the_device() {
+    let properties = properties();
+    let uuid = &properties.pipeline_cache_uuid;
+
+    let mut valid = header(32, 1, 0x10de, 0x2204, uuid);
+    assert!(pipeline_cache::header_matches(&valid, &properties));
+    valid.extend_from_slice(&[0; 64]);
+    assert!(pipeline_cache::header_matches(&valid, &properties));
+
+    assert!(!pipeline_cache::header_matches(&valid[..31], &properties));
+    assert!(!pipeline_cache::header_matches(&header(16, 1, 0x10de, 0x2204, uuid), &properties));
+    assert!(!pipeline_cache::header_matches(&header(32, 2, 0x10de, 0x2204, uuid), &properties));
+    assert!(!pipeline_cache::header_matches(&header(32, 1, 0x1002, 0x2204, uuid), &properties));
+    assert!(!pipeline_cache::header_matches(&header(32, 1, 0x10de, 0x2205, uuid), &properties));
+    assert!(!pipeline_cache::header_matches(&header(32, 1, 0x10de, 0x2204, b"fedcba9876543210"), &properties));
+}
+
+#[test]
+fn default_path_is_per_application() {
+    if let Some(path) = pipeline_cache::default_path("demo") {
+        assert!(path.ends_with("demo/pipeline_cache.bin"));
+    }
+}
+
+#[test]
+fn saved_cache_is_loaded_again() {
+    let Some(vk_base) = common::vk_base("pipeline cache round trip") else { return };
+    let dir = common::temp_dir("pipeline-cache-round-trip");
+    // Saving creates the missing app directory
+    let path = dir.join("demo").join("pipeline_cache.bin");
+
+    let cache = PipelineCache::new(&vk_base.device.handle, &vk_base.physical_device.properties, Some(path.clone())).unwrap();
+    assert!(!cache.loaded);
+    cache.save().unwrap();
+    cache.destroy();
+
+    let saved = fs::read(&path).unwrap();
+    assert!(pipeline_cache::header_matches(&saved, &vk_base.physical_device.properties));
+
+    let cache = PipelineCache::new(&vk_base.device.handle, &vk_base.physical_device.properties, Some(path.clone())).unwrap();
+    assert!(cache.loaded);
+    cache.destroy();
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn foreign_cache_is_ignored() {
+    let Some(vk_base) = common::vk_base("foreign pipeline cache") else { return };
+    let dir = common::temp_dir("pipeline-cache-foreign");
+    let path = dir.join("pipeline_cache.bin");
+    fs::write(&path, header(32, 1, 0xffff, 0xffff, &[0xab; 16])).unwrap();
+
+    let cache = PipelineCache::new(&vk_base.device.handle, &vk_base.physical_device.properties, Some(path.clone())).unwrap();
+    assert!(!cache.loaded);
+    cache.destroy();
+
+    fs::remove_dir_all(&dir).unwrap();
+}
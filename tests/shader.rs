@@ -0,0 +1,196 @@
+//! GLSL and WGSL compilation tests, from the crate's own shaders to includes,
+//! defines and error locations
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use xrrs::graphics::shader_module::{shader_kind, validate_spirv, ShaderCompiler, ShaderLanguage, ShaderStage};
+
+mod common;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+fn shader_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src/graphics/shaders")
+}
+
+#[test]
+fn shader_kind_follows_extensions() {
+    assert_eq!(shader_kind(Path::new("a/mesh.vert")), Some((ShaderLanguage::Glsl, ShaderStage::Vertex)));
+    assert_eq!(shader_kind(Path::new("mesh.frag")), Some((ShaderLanguage::Glsl, ShaderStage::Fragment)));
+    assert_eq!(shader_kind(Path::new("mesh.comp.wgsl")), Some((ShaderLanguage::Wgsl, ShaderStage::Compute)));
+    assert_eq!(shader_kind(Path::new("camera.wgsl")), None);
+    assert_eq!(shader_kind(Path::new("mesh.vert.spv")), None);
+    assert_eq!(shader_kind(Path::new("vert")), None);
+}
+
+#[test]
+fn library_shaders_compile_with_and_without_multiview() {
+    for compiler in [ShaderCompiler::new(), ShaderCompiler::new().define("MULTIVIEW", "1")] {
+        for name in ["triangle.vert.wgsl", "triangle.frag.wgsl", "mesh.vert.wgsl", "mesh.frag.wgsl"] {
+            let shader = compiler
+                .compile_file(shader_dir().join(name))
+                .unwrap_or_else(|e| panic!("{}", e));
+            assert_eq!(shader.spirv[0], SPIRV_MAGIC);
+            assert_eq!(shader.to_bytes().len(), shader.spirv.len() * 4);
+            // What ShaderModule warns about for binaries loaded from disk
+            validate_spirv(&shader.spirv).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        }
+    }
+
+    let shader = ShaderCompiler::new().compile_file(shader_dir().join("mesh.vert.wgsl")).unwrap();
+    assert_eq!(shader.stage, ShaderStage::Vertex);
+    assert_eq!(shader.sources, [shader_dir().join("mesh.vert.wgsl"), shader_dir().join("camera.wgsl")]);
+}
+
+#[test]
+fn corrupt_spirv_fails_validation() {
+    let shader = ShaderCompiler::new().compile_file(shader_dir().join("triangle.frag.wgsl")).unwrap();
+    let mut truncated = shader.spirv.clone();
+    truncated.truncate(truncated.len() - 2);
+    assert!(validate_spirv(&truncated).is_err());
+
+    assert!(validate_spirv(&[SPIRV_MAGIC, 0x0001_0000, 0, 16, 0, 0x0010_0011]).is_err());
+}
+
+#[test]
+fn errors_point_into_included_files() {
+    let dir = common::temp_dir("shader-include-error");
+    fs::write(dir.join("common.wgsl"), "// Shared\nconst SCALE: f32 = undefined_value;\n").unwrap();
+    fs::write(dir.join("broken.frag.wgsl"), concat!(
+        "#include \"common.wgsl\"\n",
+        "\n",
+        "@fragment\n",
+        "fn main() -> @location(0) vec4<f32> {\n",
+        "    return vec4(SCALE);\n",
+        "}\n",
+    )).unwrap();
+
+    let error = ShaderCompiler::new().compile_file(dir.join("broken.frag.wgsl")).err().unwrap();
+    assert_eq!(error.file, dir.join("broken.frag.wgsl"));
+    assert_eq!(error.diagnostics.len(), 1);
+    assert_eq!(error.diagnostics[0].file, dir.join("common.wgsl"));
+    assert_eq!(error.diagnostics[0].line, 2);
+    assert!(error.to_string().contains(&format!("{}:2:", dir.join("common.wgsl").display())));
+
+    let missing = ShaderCompiler::new()
+        .compile_source(dir.join("inline.frag.wgsl"), "\n#include \"missing.wgsl\"\n", ShaderLanguage::Wgsl, ShaderStage::Fragment)
+        .err()
+        .unwrap();
+    assert_eq!((missing.diagnostics[0].line, missing.diagnostics[0].column), (2, 1));
+    assert!(missing.diagnostics[0].message.contains("missing.wgsl"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn includes_resolve_once_and_through_include_dirs() {
+    let dir = common::temp_dir("shader-include-dirs");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(dir.join("lib/color.wgsl"), "const RED = vec4(1.0, 0.0, 0.0, 1.0);\n").unwrap();
+    let source = concat!(
+        "#include \"color.wgsl\"\n",
+        "#include <color.wgsl>\n",
+        "@fragment\n",
+        "fn main() -> @location(0) vec4<f32> {\n",
+        "    return RED;\n",
+        "}\n",
+    );
+
+    let compile = |compiler: ShaderCompiler| {
+        compiler.compile_source(dir.join("red.frag.wgsl"), source, ShaderLanguage::Wgsl, ShaderStage::Fragment)
+    };
+    assert!(compile(ShaderCompiler::new()).is_err());
+    let shader = compile(ShaderCompiler::new().include_dir(dir.join("lib"))).unwrap();
+    assert_eq!(shader.sources, [dir.join("red.frag.wgsl"), dir.join("lib/color.wgsl")]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn wgsl_conditionals_follow_defines() {
+    let source = concat!(
+        "@fragment\n",
+        "fn main() -> @location(0) vec4<f32> {\n",
+        "#ifdef BROKEN\n",
+        "    return not_defined;\n",
+        "#else\n",
+        "    return vec4(1.0);\n",
+        "#endif\n",
+        "}\n",
+    );
+    let compile = |compiler: ShaderCompiler| {
+        compiler.compile_source("conditional.frag.wgsl", source, ShaderLanguage::Wgsl, ShaderStage::Fragment)
+    };
+
+    compile(ShaderCompiler::new()).unwrap();
+    let error = compile(ShaderCompiler::new().define("BROKEN", "")).err().unwrap();
+    assert_eq!(error.diagnostics[0].line, 4);
+
+    let unterminated = ShaderCompiler::new()
+        .compile_source("open.frag.wgsl", "\n#ifndef A\n", ShaderLanguage::Wgsl, ShaderStage::Fragment)
+        .err()
+        .unwrap();
+    assert_eq!(unterminated.diagnostics[0].line, 2);
+}
+
+#[test]
+fn glsl_defines_and_errors() {
+    let source = concat!(
+        "#version 450\n",
+        "layout(location = 0) out vec4 out_color;\n",
+        "void main() {\n",
+        "    out_color = vec4(BRIGHTNESS);\n",
+        "}\n",
+    );
+    let compile = |compiler: ShaderCompiler| {
+        compiler.compile_source("bright.frag", source, ShaderLanguage::Glsl, ShaderStage::Fragment)
+    };
+
+    let shader = compile(ShaderCompiler::new().define("BRIGHTNESS", "0.5")).unwrap();
+    assert_eq!(shader.spirv[0], SPIRV_MAGIC);
+
+    let error = compile(ShaderCompiler::new()).err().unwrap();
+    assert_eq!(error.diagnostics[0].file, Path::new("bright.frag"));
+    assert_eq!(error.diagnostics[0].line, 4);
+}
+
+#[test]
+fn glsl_includes_inside_conditionals_are_rejected() {
+    let dir = common::temp_dir("shader-glsl-conditional-include");
+    fs::write(dir.join("color.glsl"), "const vec4 COLOR = vec4(1.0);\n").unwrap();
+    let shader = |guarded: bool| {
+        let include = if guarded {
+            "#ifdef FANCY\n#include \"color.glsl\"\n#endif\n"
+        } else {
+            "#ifdef FANCY\n#endif\n#include \"color.glsl\"\n"
+        };
+        format!(
+            "#version 450\n{}layout(location = 0) out vec4 out_color;\nvoid main() {{\n    out_color = COLOR;\n}}\n",
+            include
+        )
+    };
+    let compile = |source: &str| {
+        ShaderCompiler::new().compile_source(dir.join("color.frag"), source, ShaderLanguage::Glsl, ShaderStage::Fragment)
+    };
+
+    compile(&shader(false)).unwrap();
+    let error = compile(&shader(true)).err().unwrap();
+    assert_eq!((error.diagnostics[0].line, error.diagnostics[0].column), (3, 1));
+    assert!(error.diagnostics[0].message.contains("opened on line 2"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_entry_point_is_reported_without_location() {
+    let fragment = fs::read_to_string(shader_dir().join("triangle.frag.wgsl")).unwrap();
+    let error = ShaderCompiler::new()
+        .compile_source("triangle.vert.wgsl", &fragment, ShaderLanguage::Wgsl, ShaderStage::Vertex)
+        .err()
+        .unwrap();
+    assert_eq!(error.diagnostics[0].line, 0);
+    assert!(error.to_string().contains("triangle.vert.wgsl: "));
+}
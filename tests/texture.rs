@@ -0,0 +1,197 @@
+//! Texture loading tests. Tests that upload need a Vulkan device, see
+//! `common::vk_base`.
+
+use std::{io::Cursor};
+
+use ash::vk;
+use xrrs::{
+    graphics::{
+        memory::{Buffer, MemoryLocation},
+        sampler::SamplerDesc,
+        texture::{mip_level_count, with_color_space, ColorSpace, Texture},
+    },
+    Error,
+};
+
+mod common;
+
+/// A 2D KTX2 file with the given levels, a minimal data format descriptor and
+/// no key/value data
+fn ktx2(format: vk::Format, width: u32, height: u32, level_count: u32, levels: &[&[u8]], supercompression: u32) -> Vec<u8> {
+    let index_end = 80 + 24 * levels.len();
+    let mut data_offset = index_end + 4;
+
+    let mut bytes = vec![0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+    for value in [format.as_raw() as u32, 1, width, height, 0, 0, 1, level_count, supercompression] {
+        bytes.extend(value.to_le_bytes());
+    }
+    for value in [index_end as u32, 4, 0, 0] {
+        bytes.extend(value.to_le_bytes());
+    }
+    bytes.extend([0; 16]);
+    for level in levels {
+        for value in [data_offset, level.len(), level.len()] {
+            bytes.extend((value as u64).to_le_bytes());
+        }
+        data_offset += level.len();
+    }
+    bytes.extend(4u32.to_le_bytes());
+    for level in levels {
+        bytes.extend(*level);
+    }
+    bytes
+}
+
+#[test]
+fn mip_chains_end_at_one_pixel() {
+    assert_eq!(mip_level_count(1, 1), 1);
+    assert_eq!(mip_level_count(2, 1), 2);
+    assert_eq!(mip_level_count(256, 256), 9);
+    assert_eq!(mip_level_count(300, 20), 9);
+}
+
+#[test]
+fn color_space_picks_format_variant() {
+    assert_eq!(with_color_space(vk::Format::R8G8B8A8_UNORM, ColorSpace::Srgb), vk::Format::R8G8B8A8_SRGB);
+    assert_eq!(with_color_space(vk::Format::BC7_SRGB_BLOCK, ColorSpace::Linear), vk::Format::BC7_UNORM_BLOCK);
+    assert_eq!(with_color_space(vk::Format::R16G16_SFLOAT, ColorSpace::Srgb), vk::Format::R16G16_SFLOAT);
+}
+
+#[test]
+fn generated_mips_average_the_base_level() {
+    let Some(vk_base) = common::vk_base("mip generation") else { return };
+
+    // Uniform color, so every level of the chain is exactly the same
+    let pixels = [200u8, 100, 50, 255].repeat(4 * 4);
+    let readback = Buffer::new(&vk_base, 4, vk::BufferUsageFlags::TRANSFER_DST, MemoryLocation::Readback).unwrap();
+    let mut batch = vk_base.begin_upload();
+    let texture = Texture::from_rgba8(&mut batch, 4, 4, &pixels, ColorSpace::Linear, true).unwrap();
+    let last = texture.image.mip_levels - 1;
+
+    let cmd_buffer = batch.cmd_buffer().unwrap();
+    let range = vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: last,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+    unsafe {
+        let device = &vk_base.device.handle;
+        device.cmd_pipeline_barrier(
+            cmd_buffer,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[vk::ImageMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .old_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(texture.image.handle)
+                .subresource_range(range)
+                .build()],
+        );
+        device.cmd_copy_image_to_buffer(
+            cmd_buffer,
+            texture.image.handle,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            readback.handle,
+            &[vk::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: last,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D::default(),
+                image_extent: vk::Extent3D { width: 1, height: 1, depth: 1 },
+            }],
+        );
+    }
+    batch.submit().unwrap().wait().unwrap();
+
+    // Linear blits are mandatory for R8G8B8A8_UNORM, so the chain is never
+    // cut short
+    assert_eq!(texture.image.format, vk::Format::R8G8B8A8_UNORM);
+    assert_eq!(texture.image.mip_levels, 3);
+    assert_eq!(readback.mapped().unwrap(), [200, 100, 50, 255]);
+}
+
+#[test]
+fn images_decode_with_a_full_mip_chain() {
+    let Some(vk_base) = common::vk_base("image decoding") else { return };
+
+    let mut png = Vec::new();
+    image::RgbaImage::from_pixel(8, 4, image::Rgba([255, 0, 0, 255]))
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let mut batch = vk_base.begin_upload();
+    let texture = Texture::from_bytes(&mut batch, &png, ColorSpace::Srgb).unwrap();
+    let unmipped = Texture::from_rgba8(&mut batch, 2, 2, &[0; 16], ColorSpace::Srgb, false).unwrap();
+    let mismatched = Texture::from_rgba8(&mut batch, 2, 2, &[0; 12], ColorSpace::Srgb, false);
+    batch.submit().unwrap().wait().unwrap();
+
+    assert_eq!(texture.image.format, vk::Format::R8G8B8A8_SRGB);
+    assert_eq!((texture.image.extent.width, texture.image.extent.height), (8, 4));
+    assert_eq!(texture.image.mip_levels, 4);
+    assert_eq!(unmipped.image.mip_levels, 1);
+    assert!(matches!(mismatched, Err(Error::TextureSizeMismatch { len: 12, expected: 16 })));
+}
+
+#[test]
+fn ktx2_levels_are_uploaded_as_stored() {
+    let Some(vk_base) = common::vk_base("KTX2") else { return };
+
+    let file = ktx2(vk::Format::R8G8B8A8_UNORM, 2, 2, 2, &[&[128; 16], &[64; 4]], 0);
+    let mut batch = vk_base.begin_upload();
+    let texture = Texture::from_bytes(&mut batch, &file, ColorSpace::Srgb).unwrap();
+    batch.submit().unwrap().wait().unwrap();
+
+    assert_eq!(texture.image.format, vk::Format::R8G8B8A8_SRGB);
+    assert_eq!(texture.image.mip_levels, 2);
+}
+
+#[test]
+fn unsupported_ktx2_fails() {
+    let Some(vk_base) = common::vk_base("KTX2 rejection") else { return };
+
+    let zstd = ktx2(vk::Format::R8G8B8A8_UNORM, 1, 1, 1, &[&[0; 4]], 2);
+    let basis = ktx2(vk::Format::UNDEFINED, 1, 1, 1, &[&[0; 4]], 0);
+    let mut batch = vk_base.begin_upload();
+    assert!(matches!(
+        Texture::from_ktx2(&mut batch, &zstd, ColorSpace::Linear),
+        Err(Error::UnsupportedTexture(_))
+    ));
+    assert!(matches!(
+        Texture::from_ktx2(&mut batch, &basis, ColorSpace::Linear),
+        Err(Error::UnsupportedTexture(_))
+    ));
+    assert!(matches!(
+        Texture::from_ktx2(&mut batch, &zstd[..40], ColorSpace::Linear),
+        Err(Error::Ktx2(_))
+    ));
+}
+
+#[test]
+fn samplers_are_shared_by_description() {
+    let Some(vk_base) = common::vk_base("sampler cache") else { return };
+
+    let linear = SamplerDesc::default();
+    let nearest = SamplerDesc {
+        mag_filter: vk::Filter::NEAREST,
+        min_filter: vk::Filter::NEAREST,
+        ..SamplerDesc::default()
+    };
+    let first = vk_base.samplers.get(&linear).unwrap();
+    assert_eq!(vk_base.samplers.get(&linear).unwrap(), first);
+    assert_ne!(vk_base.samplers.get(&nearest).unwrap(), first);
+    assert_eq!(vk_base.samplers.len(), 2);
+}
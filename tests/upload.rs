@@ -0,0 +1,133 @@
+//! Staging upload tests. Need a Vulkan device, see `common::vk_base`.
+
+use std::sync::Arc;
+
+use ash::vk;
+use xrrs::{
+    graphics::{
+        capture,
+        memory::{Buffer, MemoryLocation},
+        upload::{self, STAGING_BUFFER_SIZE},
+        vk_base::VkBase,
+    },
+    Error,
+};
+
+mod common;
+
+/// Uploads `data` and copies it back through a readback buffer
+fn round_trip(vk_base: &Arc<VkBase>, data: &[u32]) -> Vec<u32> {
+    let size = (data.len() * 4) as vk::DeviceSize;
+    let readback = Buffer::new(vk_base, size, vk::BufferUsageFlags::TRANSFER_DST, MemoryLocation::Readback).unwrap();
+
+    let mut batch = vk_base.begin_upload();
+    let buffer = batch
+        .buffer(data, vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC)
+        .unwrap();
+    let cmd_buffer = batch.cmd_buffer().unwrap();
+    unsafe {
+        vk_base.device.handle.cmd_copy_buffer(
+            cmd_buffer,
+            buffer.handle,
+            readback.handle,
+            &[vk::BufferCopy { src_offset: 0, dst_offset: 0, size }],
+        );
+    }
+    batch.submit().unwrap().wait().unwrap();
+
+    readback
+        .mapped()
+        .unwrap()
+        .chunks_exact(4)
+        .map(|bytes| u32::from_ne_bytes(bytes.try_into().unwrap()))
+        .collect()
+}
+
+#[test]
+fn buffer_contents_reach_device_memory() {
+    let Some(vk_base) = common::vk_base("buffer upload") else { return };
+
+    let data = (0..1024).collect::<Vec<u32>>();
+    assert_eq!(round_trip(&vk_base, &data), data);
+    assert!(vk_base.allocator.stats().pools.iter().any(|pool| pool.location == MemoryLocation::DeviceLocal));
+}
+
+#[test]
+fn uploads_larger_than_a_staging_buffer() {
+    let Some(vk_base) = common::vk_base("large upload") else { return };
+
+    let data = (0..(STAGING_BUFFER_SIZE / 4 + 1024) as u32).collect::<Vec<_>>();
+    assert!(round_trip(&vk_base, &data) == data);
+}
+
+#[test]
+fn image_upload_ends_in_final_layout() {
+    let Some(vk_base) = common::vk_base("image upload") else { return };
+
+    let pixels = [255u8, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255, 255];
+    let mut batch = vk_base.begin_upload();
+    let image = batch
+        .image(
+            &vk::ImageCreateInfo::builder()
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(vk::Format::R8G8B8A8_UNORM)
+                .extent(vk::Extent3D { width: 2, height: 2, depth: 1 })
+                .mip_levels(1)
+                .array_layers(1)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE),
+            &[&pixels],
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        )
+        .unwrap();
+    batch.submit().unwrap().wait().unwrap();
+
+    let captured = capture::capture_layer(&vk_base, image.handle, vk::Extent2D { width: 2, height: 2 }, 0).unwrap();
+    assert_eq!(captured.pixels, pixels);
+}
+
+#[test]
+fn short_image_levels_are_an_error() {
+    let Some(vk_base) = common::vk_base("short image level") else { return };
+
+    let info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(vk::Format::R8G8B8A8_UNORM)
+        .extent(vk::Extent3D { width: 4, height: 4, depth: 1 })
+        .mip_levels(2)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(vk::ImageUsageFlags::SAMPLED)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .build();
+    let mut batch = vk_base.begin_upload();
+    let result = batch.image(&info, &[&[0; 64], &[0; 15]], vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    assert!(matches!(result, Err(Error::ImageLevelTooShort { level: 1, len: 15, expected: 16 })));
+    let result = batch.image(&info, &[&[0; 64], &[0; 16], &[0; 4]], vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    assert!(matches!(result, Err(Error::TooManyImageLevels { levels: 3, mip_levels: 2 })));
+}
+
+#[test]
+fn buffer_writes_past_the_end_are_an_error() {
+    let Some(vk_base) = common::vk_base("buffer write past the end") else { return };
+
+    let buffer = Buffer::new(&vk_base, 16, vk::BufferUsageFlags::TRANSFER_DST, MemoryLocation::DeviceLocal).unwrap();
+    let mut batch = vk_base.begin_upload();
+    assert!(matches!(
+        batch.write_buffer(&buffer, 8, &[0u32; 3]),
+        Err(Error::UploadOutOfBounds { offset: 8, len: 12, size: 16 })
+    ));
+    assert!(matches!(batch.write_buffer(&buffer, u64::MAX, &[0u8]), Err(Error::UploadOutOfBounds { .. })));
+    batch.write_buffer(&buffer, 8, &[0u32; 2]).unwrap();
+}
+
+#[test]
+fn texel_blocks_cover_compressed_formats() {
+    assert_eq!(upload::texel_block(vk::Format::R8G8B8A8_SRGB), Some((1, 1, 4)));
+    assert_eq!(upload::texel_block(vk::Format::BC1_RGB_UNORM_BLOCK), Some((4, 4, 8)));
+    assert_eq!(upload::texel_block(vk::Format::ASTC_6X6_SRGB_BLOCK), Some((6, 6, 16)));
+    assert_eq!(upload::texel_block(vk::Format::D32_SFLOAT), None);
+}
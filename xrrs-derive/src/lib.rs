@@ -0,0 +1,88 @@
+//! Derive macros for xrrs. Use them through the re-exports in `xrrs`.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+/// Implements `xrrs::graphics::mesh::VertexLayout` for a `#[repr(C)]` struct
+/// with named fields. Fields get consecutive locations starting at 0 and a
+/// format from their type's `VertexFormat` impl, which
+/// `#[vertex(format = "R8G8B8A8_UNORM")]` overrides with any `vk::Format`.
+#[proc_macro_derive(VertexLayout, attributes(vertex))]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match vertex_layout(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn vertex_layout(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut repr_c = false;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("C") {
+                repr_c = true;
+            }
+            Ok(())
+        })?;
+    }
+    if !repr_c {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "VertexLayout needs #[repr(C)] so field offsets are stable",
+        ));
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(&input.ident, "VertexLayout needs named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(&input.ident, "VertexLayout can only be derived for structs")),
+    };
+
+    let mut attributes = Vec::new();
+    for (location, field) in fields.iter().enumerate() {
+        let name = field.ident.as_ref().expect("named fields have names");
+        let ty = &field.ty;
+        let location = location as u32;
+
+        let mut format = None;
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("vertex")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("format") {
+                    let value: LitStr = meta.value()?.parse()?;
+                    format = Some(syn::Ident::new(&value.value(), value.span()));
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown vertex attribute, expected `format`"))
+                }
+            })?;
+        }
+        let format = match format {
+            Some(format) => quote! { ::xrrs::ash::vk::Format::#format },
+            None => quote! { <#ty as ::xrrs::graphics::mesh::VertexFormat>::FORMAT },
+        };
+
+        attributes.push(quote! {
+            ::xrrs::ash::vk::VertexInputAttributeDescription {
+                location: #location,
+                binding,
+                format: #format,
+                offset: ::core::mem::offset_of!(Self, #name) as u32,
+            }
+        });
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::xrrs::graphics::mesh::VertexLayout for #ident #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn attributes(binding: u32) -> ::std::vec::Vec<::xrrs::ash::vk::VertexInputAttributeDescription> {
+                ::std::vec![#(#attributes),*]
+            }
+        }
+    })
+}
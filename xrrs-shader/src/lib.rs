@@ -0,0 +1,265 @@
+//! GLSL and WGSL to SPIR-V compilation through naga, shared by the xrrs build
+//! script and runtime shader loading. Use it through the re-exports in
+//! `xrrs::graphics::shader_module`.
+//!
+//! Both languages get `#include "file"`, resolved next to the including file
+//! and then in the compiler's include directories. Each file is included at
+//! most once per shader. GLSL sources then go through naga's preprocessor with
+//! the compiler's defines; WGSL, which has no preprocessor, gets `#ifdef`,
+//! `#ifndef`, `#else` and `#endif` on the defined names instead. Since GLSL
+//! conditionals are evaluated after includes are expanded, a GLSL `#include`
+//! can't be inside one. Every shader has a single entry point called `main`.
+
+mod preprocess;
+
+use std::{
+    collections::BTreeMap,
+    error, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use naga::{
+    back::spv,
+    front::{glsl, wgsl},
+    valid::{Capabilities, ValidationFlags, Validator},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    Compute,
+}
+
+impl ShaderStage {
+    fn to_naga(self) -> naga::ShaderStage {
+        match self {
+            ShaderStage::Vertex => naga::ShaderStage::Vertex,
+            ShaderStage::Fragment => naga::ShaderStage::Fragment,
+            ShaderStage::Compute => naga::ShaderStage::Compute,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ShaderLanguage {
+    Glsl,
+    Wgsl,
+}
+
+/// The language and stage of a shader file by its extensions: `.vert`,
+/// `.frag` and `.comp` for GLSL, `.vert.wgsl`, `.frag.wgsl` and `.comp.wgsl`
+/// for WGSL. `None` for anything else, e.g. a `.wgsl` include.
+pub fn shader_kind(path: &Path) -> Option<(ShaderLanguage, ShaderStage)> {
+    let (language, stem) = match path.extension()?.to_str()? {
+        "wgsl" => (ShaderLanguage::Wgsl, Path::new(path.file_stem()?)),
+        _ => (ShaderLanguage::Glsl, path),
+    };
+    let stage = match stem.extension()?.to_str()? {
+        "vert" => ShaderStage::Vertex,
+        "frag" => ShaderStage::Fragment,
+        "comp" => ShaderStage::Compute,
+        _ => return None,
+    };
+    Some((language, stage))
+}
+
+/// A problem at a line and column of a shader source, both 1-based. They are
+/// 0 when naga doesn't report a location.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file.display(), self.message)
+        } else {
+            write!(f, "{}:{}:{}: {}", self.file.display(), self.line, self.column, self.message)
+        }
+    }
+}
+
+/// A shader failed to compile. Diagnostics may point into included files.
+#[derive(Clone, Debug)]
+pub struct CompileError {
+    pub file: PathBuf,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CompileError {
+    fn new(diagnostic: Diagnostic) -> Self {
+        CompileError {
+            file: diagnostic.file.clone(),
+            diagnostics: vec![diagnostic],
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error compiling shader {}:", self.file.display())?;
+        for diagnostic in &self.diagnostics {
+            write!(f, "\n  {}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for CompileError {}
+
+pub struct CompiledShader {
+    pub stage: ShaderStage,
+    pub spirv: Vec<u32>,
+    /// The compiled file followed by every file it included, e.g. to watch
+    /// them for changes
+    pub sources: Vec<PathBuf>,
+}
+
+impl CompiledShader {
+    /// The SPIR-V words in little-endian byte order, as in a `.spv` file
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.spirv.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ShaderCompiler {
+    defines: BTreeMap<String, String>,
+    include_dirs: Vec<PathBuf>,
+}
+
+impl ShaderCompiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Like `#define name value` at the top of every shader. WGSL's `#ifdef`
+    /// only looks at the name.
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.defines.insert(name.to_owned(), value.to_owned());
+        self
+    }
+
+    /// Searched for `#include`s that aren't next to the including file, in
+    /// the order they were added
+    pub fn include_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.include_dirs.push(dir.into());
+        self
+    }
+
+    /// Compiles a file named as described in `shader_kind`
+    pub fn compile_file(&self, path: impl AsRef<Path>) -> Result<CompiledShader, CompileError> {
+        let path = path.as_ref();
+        let error = |message: String| CompileError::new(Diagnostic {
+            file: path.to_owned(),
+            line: 0,
+            column: 0,
+            message,
+        });
+
+        let (language, stage) = shader_kind(path)
+            .ok_or_else(|| error("unknown shader type, expected .vert, .frag or .comp, optionally followed by .wgsl".to_owned()))?;
+        let source = fs::read_to_string(path).map_err(|e| error(format!("error reading: {}", e)))?;
+
+        self.compile_source(path, &source, language, stage)
+    }
+
+    /// Compiles `source` as if read from `path`, which names it in
+    /// diagnostics and anchors relative `#include`s
+    pub fn compile_source(&self,
+                          path: impl AsRef<Path>,
+                          source: &str,
+                          language: ShaderLanguage,
+                          stage: ShaderStage,
+    ) -> Result<CompiledShader, CompileError> {
+        let expanded = preprocess::expand(path.as_ref(), source, language, &self.defines, &self.include_dirs)
+            .map_err(CompileError::new)?;
+        let fail = |diagnostics: Vec<Diagnostic>| CompileError {
+            file: expanded.files[0].clone(),
+            diagnostics,
+        };
+        let location = |location: Option<naga::SourceLocation>| {
+            location.map(|location| (location.line_number as usize, location.line_position as usize))
+        };
+
+        let module = match language {
+            ShaderLanguage::Glsl => {
+                let options = glsl::Options {
+                    stage: stage.to_naga(),
+                    defines: self.defines.iter().map(|(name, value)| (name.clone(), value.clone())).collect(),
+                };
+                glsl::Frontend::default().parse(&options, &expanded.source).map_err(|errors| {
+                    fail(errors
+                        .errors
+                        .iter()
+                        .map(|e| expanded.diagnostic(location(e.location(&expanded.source)), e.kind.to_string()))
+                        .collect())
+                })?
+            }
+            ShaderLanguage::Wgsl => wgsl::parse_str(&expanded.source).map_err(|e| {
+                fail(vec![expanded.diagnostic(location(e.location(&expanded.source)), e.message().to_owned())])
+            })?,
+        };
+
+        let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .map_err(|e| {
+                fail(vec![expanded.diagnostic(location(e.location(&expanded.source)), error_chain(e.as_inner()))])
+            })?;
+
+        // Vulkan clip space as written, without naga's WebGPU coordinate flip
+        let options = spv::Options {
+            flags: spv::WriterFlags::LABEL_VARYINGS,
+            ..Default::default()
+        };
+        let pipeline_options = spv::PipelineOptions {
+            shader_stage: stage.to_naga(),
+            entry_point: "main".to_owned(),
+        };
+        let spirv = spv::write_vec(&module, &info, &options, Some(&pipeline_options))
+            .map_err(|e| fail(vec![expanded.diagnostic(None, e.to_string())]))?;
+
+        Ok(CompiledShader {
+            stage,
+            spirv,
+            sources: expanded.files,
+        })
+    }
+}
+
+/// Checks SPIR-V that didn't come from `ShaderCompiler`, e.g. loaded from
+/// disk, by reading it back into naga and validating the result. Returns the
+/// first problem found. naga's SPIR-V reader doesn't support everything other
+/// compilers emit, so a failure doesn't always mean the module is invalid.
+pub fn validate_spirv(words: &[u32]) -> Result<(), String> {
+    let options = naga::front::spv::Options {
+        adjust_coordinate_space: false,
+        ..Default::default()
+    };
+    let module = naga::front::spv::Frontend::new(words.iter().copied(), &options)
+        .parse()
+        .map_err(|e| e.to_string())?;
+    Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|e| error_chain(e.as_inner()))?;
+
+    Ok(())
+}
+
+/// naga nests the cause of validation errors, e.g. the invalid expression
+/// inside the invalid function
+fn error_chain(error: &dyn error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
@@ -0,0 +1,223 @@
+//! Expands `#include`s, and for WGSL the `#ifdef` family, before naga sees the
+//! source. Every line of the expanded source remembers the file and line it
+//! came from, so naga's errors can point at the original location.
+//!
+//! GLSL conditionals are left to naga's preprocessor, which only runs after
+//! expansion, so an `#include` inside one is an error rather than being
+//! expanded whichever branch is taken.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{Diagnostic, ShaderLanguage};
+
+pub(crate) struct Expanded {
+    pub source: String,
+    /// Index into `files` and 1-based line of each line of `source`
+    pub lines: Vec<(usize, usize)>,
+    /// The root file first, then each included file in inclusion order
+    pub files: Vec<PathBuf>,
+}
+
+impl Expanded {
+    /// Maps a 1-based line of the expanded source back to its file
+    pub fn diagnostic(&self, line: Option<(usize, usize)>, message: String) -> Diagnostic {
+        match line.and_then(|(line, column)| Some((self.lines.get(line.checked_sub(1)?)?, column))) {
+            Some((&(file, line), column)) => Diagnostic {
+                file: self.files[file].clone(),
+                line,
+                column,
+                message,
+            },
+            None => Diagnostic {
+                file: self.files[0].clone(),
+                line: 0,
+                column: 0,
+                message,
+            },
+        }
+    }
+}
+
+struct Condition {
+    line: usize,
+    /// Whether lines in the current branch are kept
+    active: bool,
+    /// Whether an earlier branch was taken, or the enclosing block is inactive
+    taken: bool,
+    has_else: bool,
+}
+
+struct Expander<'a> {
+    language: ShaderLanguage,
+    defines: &'a BTreeMap<String, String>,
+    include_dirs: &'a [PathBuf],
+    expanded: Expanded,
+    /// Canonical paths of every file in `expanded.files`, each is only
+    /// expanded once
+    seen: HashSet<PathBuf>,
+}
+
+pub(crate) fn expand(path: &Path,
+                     source: &str,
+                     language: ShaderLanguage,
+                     defines: &BTreeMap<String, String>,
+                     include_dirs: &[PathBuf],
+) -> Result<Expanded, Diagnostic> {
+    let mut expander = Expander {
+        language,
+        defines,
+        include_dirs,
+        expanded: Expanded {
+            source: String::with_capacity(source.len()),
+            lines: Vec::new(),
+            files: vec![path.to_owned()],
+        },
+        seen: HashSet::new(),
+    };
+    expander.seen.insert(fs::canonicalize(path).unwrap_or_else(|_| path.to_owned()));
+    expander.expand_file(0, source)?;
+    Ok(expander.expanded)
+}
+
+impl Expander<'_> {
+    fn expand_file(&mut self, file: usize, source: &str) -> Result<(), Diagnostic> {
+        let path = self.expanded.files[file].clone();
+        let defines = self.defines;
+        let mut conditions: Vec<Condition> = Vec::new();
+        // Lines of the GLSL `#if`, `#ifdef` and `#ifndef` blocks still open
+        let mut glsl_conditions: Vec<usize> = Vec::new();
+
+        for (index, text) in source.lines().enumerate() {
+            let line = index + 1;
+            let directive = text.trim_start();
+            let error = |message: String| Diagnostic {
+                file: path.clone(),
+                line,
+                column: text.len() - directive.len() + 1,
+                message,
+            };
+            let active = conditions.last().is_none_or(|condition| condition.active);
+
+            if self.language == ShaderLanguage::Wgsl {
+                let (ifdef, ifndef) = (argument(directive, "#ifdef"), argument(directive, "#ifndef"));
+                if let Some(name) = ifdef.or(ifndef) {
+                    if name.is_empty() {
+                        return Err(error("expected a name to test".to_owned()));
+                    }
+                    let holds = defines.contains_key(name) == ifdef.is_some();
+                    conditions.push(Condition {
+                        line,
+                        active: active && holds,
+                        taken: !active || holds,
+                        has_else: false,
+                    });
+                    self.push_line(file, line, "");
+                    continue;
+                }
+                if argument(directive, "#else").is_some() {
+                    match conditions.last_mut() {
+                        Some(condition) if !condition.has_else => {
+                            condition.active = !condition.taken;
+                            condition.taken = true;
+                            condition.has_else = true;
+                        }
+                        Some(_) => return Err(error("#else after #else".to_owned())),
+                        None => return Err(error("#else without #ifdef".to_owned())),
+                    }
+                    self.push_line(file, line, "");
+                    continue;
+                }
+                if argument(directive, "#endif").is_some() {
+                    if conditions.pop().is_none() {
+                        return Err(error("#endif without #ifdef".to_owned()));
+                    }
+                    self.push_line(file, line, "");
+                    continue;
+                }
+            }
+
+            if self.language == ShaderLanguage::Glsl {
+                if ["#if", "#ifdef", "#ifndef"].iter().any(|keyword| argument(directive, keyword).is_some()) {
+                    glsl_conditions.push(line);
+                } else if argument(directive, "#endif").is_some() {
+                    glsl_conditions.pop();
+                }
+            }
+
+            if !active {
+                self.push_line(file, line, "");
+                continue;
+            }
+
+            if let Some(name) = argument(directive, "#include") {
+                if let Some(start) = glsl_conditions.last() {
+                    return Err(error(format!(
+                        "#include inside the conditional block opened on line {}, GLSL conditionals are only \
+                         evaluated after includes are expanded",
+                        start
+                    )));
+                }
+                let name = name
+                    .strip_prefix('"')
+                    .and_then(|name| name.strip_suffix('"'))
+                    .or_else(|| name.strip_prefix('<').and_then(|name| name.strip_suffix('>')))
+                    .ok_or_else(|| error("expected #include \"file\"".to_owned()))?;
+                let include = self
+                    .resolve(file, name)
+                    .ok_or_else(|| error(format!("can't find include \"{}\"", name)))?;
+
+                if self.seen.insert(fs::canonicalize(&include).unwrap_or_else(|_| include.clone())) {
+                    let source = fs::read_to_string(&include)
+                        .map_err(|e| error(format!("error reading include \"{}\": {}", name, e)))?;
+                    self.expanded.files.push(include);
+                    self.expand_file(self.expanded.files.len() - 1, &source)?;
+                } else {
+                    self.push_line(file, line, "");
+                }
+                continue;
+            }
+
+            self.push_line(file, line, text);
+        }
+
+        match conditions.last() {
+            Some(condition) => Err(Diagnostic {
+                file: path,
+                line: condition.line,
+                column: 1,
+                message: "#ifdef without #endif".to_owned(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn push_line(&mut self, file: usize, line: usize, text: &str) {
+        self.expanded.source.push_str(text);
+        self.expanded.source.push('\n');
+        self.expanded.lines.push((file, line));
+    }
+
+    /// Looks next to the including file first, then in the include directories
+    fn resolve(&self, file: usize, name: &str) -> Option<PathBuf> {
+        let including_dir = self.expanded.files[file].parent().unwrap_or(Path::new(""));
+
+        std::iter::once(including_dir)
+            .chain(self.include_dirs.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    }
+}
+
+/// The rest of `line` if it is the directive `keyword`
+fn argument<'a>(line: &'a str, keyword: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix(keyword)?;
+    match rest.chars().next() {
+        None => Some(rest),
+        Some(c) if c.is_whitespace() => Some(rest.trim()),
+        Some(_) => None,
+    }
+}